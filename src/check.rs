@@ -0,0 +1,262 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use crate::{commands::CommandError, image_util, summary};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DRIFT: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Compare generated outputs with the existing files instead of writing them, set by `--check`.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn record(path: &Path, msg: impl Into<String>) {
+    DRIFT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push((path.to_path_buf(), msg.into()));
+}
+
+/// Compare the RGBA pixels of a generated image with the existing file, returns the size of the existing file.
+///
+/// The encoding itself is not compared and the color of fully transparent pixels is ignored,
+/// both may change with the encoder version without any visible difference.
+#[must_use]
+pub fn image(buf: &[u8], width: u32, height: u32, path: &Path) -> u64 {
+    if !path.exists() {
+        record(path, "is missing");
+        return 0;
+    }
+
+    let existing = match image_util::load_image_from_file(path) {
+        Ok(existing) => existing,
+        Err(err) => {
+            record(path, format!("can not be loaded: {err}"));
+            return 0;
+        }
+    };
+
+    if existing.dimensions() == (width, height) {
+        let changed = existing
+            .as_raw()
+            .chunks_exact(4)
+            .zip(buf.chunks_exact(4))
+            .filter(|(a, b)| a != b && (a[3] != 0 || b[3] != 0))
+            .count();
+
+        if changed > 0 {
+            record(path, format!("{changed} pixels differ"));
+        }
+    } else {
+        record(
+            path,
+            format!(
+                "is {}x{}px, expected {width}x{height}px",
+                existing.width(),
+                existing.height()
+            ),
+        );
+    }
+
+    std::fs::metadata(path).map_or(0, |meta| meta.len())
+}
+
+fn read_existing(path: &Path) -> Option<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(existing) => Some(existing),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            record(path, "is missing");
+            None
+        }
+        Err(err) => {
+            record(path, format!("can not be read: {err}"));
+            None
+        }
+    }
+}
+
+/// Write a generated file, with `--check` it is compared with the existing one instead.
+///
+/// # Errors
+///
+/// Fails when the file can't be written.
+pub fn write(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    if enabled() {
+        file(path, content);
+        return Ok(());
+    }
+
+    std::fs::write(path, content)?;
+    summary::record_file(path);
+    Ok(())
+}
+
+/// Write a generated data file, with `--check` it is compared with the existing one instead.
+///
+/// # Errors
+///
+/// Fails when the file can't be written.
+pub fn write_data(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    if enabled() {
+        data_file(path, content);
+        return Ok(());
+    }
+
+    std::fs::write(path, content)?;
+    summary::record_file(path);
+    Ok(())
+}
+
+/// Create an output folder, with `--check` nothing is created and its outputs are reported as missing.
+///
+/// # Errors
+///
+/// Fails when the folder can't be created.
+pub fn create_dir(path: &Path) -> std::io::Result<()> {
+    if enabled() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(path)
+}
+
+/// Compare a generated file with the existing one byte by byte.
+fn file(path: &Path, content: &[u8]) {
+    if read_existing(path).is_some_and(|existing| existing != content) {
+        record(path, "content differs");
+    }
+}
+
+/// Compare a generated data file with the existing one, ignoring the spritter version.
+fn data_file(path: &Path, content: &[u8]) {
+    if read_existing(path).is_some_and(|existing| {
+        without_version(&String::from_utf8_lossy(&existing))
+            != without_version(&String::from_utf8_lossy(content))
+    }) {
+        record(path, "content differs");
+    }
+}
+
+/// Drop the generator header and `spritter` version entry of a data file,
+/// they change with every release without changing the data.
+fn without_version(content: &str) -> String {
+    let mut lines = content.lines().peekable();
+    let mut kept = Vec::new();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("-- Generated by spritter") {
+            continue;
+        }
+
+        if !["[\"spritter\"]", "\"spritter\":", "spritter =", "spritter:"]
+            .iter()
+            .any(|prefix| trimmed.starts_with(prefix))
+        {
+            kept.push(line);
+            continue;
+        }
+
+        // json and toml write the version as multi line array, yaml as list below the key
+        if trimmed.ends_with('[') {
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with(']') {
+                    break;
+                }
+            }
+        } else if trimmed.ends_with(':') {
+            while lines
+                .peek()
+                .is_some_and(|line| line.trim_start().starts_with("- "))
+            {
+                lines.next();
+            }
+        }
+    }
+
+    kept.join("\n")
+}
+
+/// Report all outputs that differ from the existing files, fails when any were found.
+///
+/// # Errors
+///
+/// Returns [`CommandError::OutputsOutdated`] with the number of outdated outputs.
+pub fn finish() -> Result<(), CommandError> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    let drift = std::mem::take(&mut *DRIFT.lock().unwrap_or_else(PoisonError::into_inner));
+    if drift.is_empty() {
+        info!("all outputs are up to date");
+        return Ok(());
+    }
+
+    for (path, msg) in &drift {
+        warn!("{}: {msg}", path.display());
+        summary::record_failure(Some(path), msg);
+    }
+
+    Err(CommandError::OutputsOutdated(drift.len()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::without_version;
+
+    #[test]
+    fn lua_version() {
+        let old = "-- Generated by spritter v1.2.3 - https://github.com/fgardt/factorio-spritter\nreturn {\n  [\"spritter\"] = { 1, 2, 3 },\n  [\"height\"] = 32,\n}";
+        let new = "-- Generated by spritter v1.3.0 - https://github.com/fgardt/factorio-spritter\nreturn {\n  [\"spritter\"] = { 1, 3, 0 },\n  [\"height\"] = 32,\n}";
+
+        assert_eq!(without_version(old), "return {\n  [\"height\"] = 32,\n}");
+        assert_eq!(without_version(old), without_version(new));
+    }
+
+    #[test]
+    fn json_version() {
+        let old = "{\n  \"spritter\": [\n    1,\n    2,\n    3\n  ],\n  \"height\": 32\n}";
+        let new = "{\n  \"spritter\": [\n    1,\n    3,\n    0\n  ],\n  \"height\": 32\n}";
+
+        assert_eq!(without_version(old), "{\n  \"height\": 32\n}");
+        assert_eq!(without_version(old), without_version(new));
+    }
+
+    #[test]
+    fn toml_version() {
+        let old = "spritter = [\n    1,\n    2,\n    3,\n]\nheight = 32";
+        let new = "spritter = [\n    1,\n    3,\n    0,\n]\nheight = 32";
+
+        assert_eq!(without_version(old), "height = 32");
+        assert_eq!(without_version(old), without_version(new));
+    }
+
+    #[test]
+    fn yaml_version() {
+        let old = "spritter:\n- 1\n- 2\n- 3\nheight: 32\nframes:\n- 1\n- 2";
+        let new = "spritter:\n- 1\n- 3\n- 0\nheight: 32\nframes:\n- 1\n- 2";
+
+        assert_eq!(without_version(old), "height: 32\nframes:\n- 1\n- 2");
+        assert_eq!(without_version(old), without_version(new));
+    }
+
+    #[test]
+    fn data_changes_are_kept() {
+        assert_ne!(
+            without_version("spritter = [\n    1,\n]\nheight = 32"),
+            without_version("spritter = [\n    1,\n]\nheight = 64")
+        );
+    }
+}
@@ -1,15 +1,65 @@
+mod compare;
+mod diff_anim;
 mod gif;
 mod icon;
+mod info;
+mod init;
+mod mangen;
+mod montage;
 mod optimize;
+mod palette;
+mod preview;
+mod rotate;
+mod run;
+mod shadow;
+mod split;
 mod spritesheet;
+mod stats;
+mod stitch;
+mod validate;
+mod verify;
 
+pub use compare::*;
+pub use diff_anim::*;
 pub use gif::*;
 pub use icon::*;
+pub use info::*;
+pub use init::*;
+pub use mangen::*;
+pub use montage::*;
 pub use optimize::*;
+pub use palette::*;
+pub use preview::*;
+pub use rotate::*;
+pub use run::*;
+pub use shadow::*;
+pub use split::*;
 pub use spritesheet::*;
+pub use stats::*;
+pub use stitch::*;
+pub use validate::*;
+pub use verify::*;
 
-use clap::{Args, Subcommand};
-use std::path::{Path, PathBuf};
+use clap::{
+    builder::PossibleValue, parser::ValueSource, ArgMatches, Args, Command, FromArgMatches,
+    Subcommand, ValueEnum,
+};
+use image::{ImageFormat, RgbaImage};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::Write,
+    num::NonZeroU8,
+    path::{Path, PathBuf},
+};
+use strum::{EnumIter, VariantArray};
+
+use crate::{
+    image_util::{self, EncodeOptions, PaletteOptions},
+    lockfile::{LockChange, LockEntry, Lockfile, LockfileError},
+    lua::{LuaOutput, LuaStyle, LuaValue, ShiftFormat},
+};
 
 #[derive(Subcommand, Debug)]
 pub enum GenerationCommand {
@@ -47,6 +97,262 @@ pub enum GenerationCommand {
         #[clap(flatten)]
         args: OptimizeArgs,
     },
+
+    /// Split a sprite sheet into individual frames.
+    ///
+    /// This is the reverse of the spritesheet command, the frames are saved as individual images.
+    Split {
+        // args
+        #[clap(flatten)]
+        args: SplitArgs,
+    },
+
+    /// Generate a html page previewing an animation.
+    ///
+    /// The page is self-contained and plays the frames of a folder or of sheets with a data file,
+    /// with controls for the speed and zoom.
+    Preview {
+        // args
+        #[clap(flatten)]
+        args: PreviewArgs,
+    },
+
+    /// Validate the graphics of a mod.
+    ///
+    /// Checks all pngs and spritter data files for files over the texture size limit,
+    /// icons with invalid mipmaps and sheets that don't match their data files.
+    /// Exits with a nonzero exit code when violations were found.
+    Validate {
+        // args
+        #[clap(flatten)]
+        args: ValidateArgs,
+    },
+
+    /// Verify that spritter data files still match their sheets.
+    ///
+    /// Checks the sheet dimensions and count, the frame count against the grid and the `frame_sequence` bounds
+    /// of every data file, e.g. to catch sheets that were regenerated without committing the new data files.
+    /// Exits with a nonzero exit code when mismatches were found.
+    Verify {
+        // args
+        #[clap(flatten)]
+        args: VerifyArgs,
+    },
+
+    /// Estimate the VRAM usage of the graphics of a mod.
+    ///
+    /// Sums the decompressed texture memory of all images grouped by folder
+    /// and estimates how many sprite atlases they occupy, e.g. to find the animations that dominate the budget.
+    Stats {
+        // args
+        #[clap(flatten)]
+        args: StatsArgs,
+    },
+
+    /// Compare two images or folders of images.
+    ///
+    /// Reports the pixel and perceptual differences of each image pair and optionally writes difference images.
+    /// Exits with a nonzero exit code when a pair is below the similarity threshold.
+    Compare {
+        // args
+        #[clap(flatten)]
+        args: CompareArgs,
+    },
+
+    /// Render the differences between two versions of an animation as a gif or apng.
+    ///
+    /// Highlights the changed pixels of every frame as a heatmap or blinks between both versions,
+    /// e.g. to review what changed after a re-render.
+    DiffAnim {
+        // args
+        #[clap(flatten)]
+        args: DiffAnimArgs,
+    },
+
+    /// Print details about images and spritter data files.
+    ///
+    /// Shows the dimensions, color format, alpha usage and estimated VRAM usage of images
+    /// and a decoded summary of the sheet layout described by data files.
+    Info {
+        // args
+        #[clap(flatten)]
+        args: InfoArgs,
+    },
+
+    /// Generate rotated variants of a sprite or animation.
+    ///
+    /// The results share a consistent canvas and can be used as input for the spritesheet command.
+    Rotate {
+        // args
+        #[clap(flatten)]
+        args: RotateArgs,
+    },
+
+    /// Generate shadow frames from a folder of images.
+    ///
+    /// Derives a blurred, skewed and semi-transparent silhouette from the alpha of each frame.
+    /// The results can be turned into a sheet with `spritesheet --draw-as-shadow`.
+    Shadow {
+        // args
+        #[clap(flatten)]
+        args: ShadowArgs,
+    },
+
+    /// Merge several sheets and their data files into fewer, larger sheets.
+    ///
+    /// The combined data file references the stitched sheets with `filename`, `x` and `y`.
+    Stitch {
+        // args
+        #[clap(flatten)]
+        args: StitchArgs,
+    },
+
+    /// Lay out thumbnails of a folder of images in a labeled grid.
+    ///
+    /// Useful to review a whole render batch or all icons of a mod in a single picture.
+    Montage {
+        // args
+        #[clap(flatten)]
+        args: MontageArgs,
+    },
+
+    /// Extract the dominant colors of an image or a folder of images.
+    ///
+    /// Writes the palette as swatch image and json file and optionally compares it with the palette
+    /// of other images, e.g. to keep the colors of an entity set consistent.
+    Palette {
+        // args
+        #[clap(flatten)]
+        args: PaletteArgs,
+    },
+
+    /// Write a starter `spritter.toml` for an existing graphics folder.
+    ///
+    /// Detects the used source image formats and adds a job for every animation folder.
+    Init {
+        // args
+        #[clap(flatten)]
+        args: InitArgs,
+    },
+
+    /// Run the jobs of the `spritter.toml` in the current folder.
+    ///
+    /// Every `[[jobs]]` entry runs its command like it was given on the command line,
+    /// the other settings of the config files apply to the jobs as well.
+    Run {
+        // args
+        #[clap(flatten)]
+        args: RunArgs,
+    },
+
+    /// Generate man pages and a markdown CLI reference.
+    #[clap(hide = true)]
+    Mangen {
+        // args
+        #[clap(flatten)]
+        args: MangenArgs,
+    },
+}
+
+impl GenerationCommand {
+    /// Shared arguments of the commands that record their command line.
+    pub fn shared_args_mut(&mut self) -> Option<&mut SharedArgs> {
+        match self {
+            Self::Spritesheet { args } => Some(&mut **args),
+            Self::Icon { args } => Some(&mut **args),
+            Self::Gif { args } => Some(&mut **args),
+            _ => None,
+        }
+    }
+}
+
+/// Build the arguments of a command from command line style strings, without the binary name.
+///
+/// This allows to run the commands from other tools without shelling out to the CLI.
+///
+/// # Errors
+///
+/// Returns the clap error for unknown or invalid arguments.
+pub fn parse_args<T: Args + FromArgMatches + 'static>(
+    args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+) -> Result<T, clap::Error> {
+    let cmd = T::augment_args(clap::Command::new(env!("CARGO_PKG_NAME")).no_binary_name(true));
+    let matches = cmd.clone().try_get_matches_from(args)?;
+    let mut parsed = T::from_arg_matches(&matches)?;
+
+    let parsed_any: &mut dyn std::any::Any = &mut parsed;
+    if let Some((name, shared)) = shared_args_mut(parsed_any) {
+        shared.command_line = std::iter::once(name.to_owned())
+            .chain(effective_args(&cmd, &cmd, &matches))
+            .collect();
+    }
+
+    Ok(parsed)
+}
+
+/// Name and shared arguments of the commands that record their command line.
+fn shared_args_mut(args: &mut dyn std::any::Any) -> Option<(&'static str, &mut SharedArgs)> {
+    if args.is::<SpritesheetArgs>() {
+        return args
+            .downcast_mut::<SpritesheetArgs>()
+            .map(|args| ("spritesheet", &mut **args));
+    }
+    if args.is::<IconArgs>() {
+        return args
+            .downcast_mut::<IconArgs>()
+            .map(|args| ("icon", &mut **args));
+    }
+    args.downcast_mut::<GifArgs>()
+        .map(|args| ("gif", &mut **args))
+}
+
+/// Arguments of the parsed `cmd` that set a value: positional arguments, options given on the command line
+/// and options whose default was changed by the config files or a preset compared to `stock`.
+///
+/// Options are written as `--name=value`, flags as `--name`.
+#[must_use]
+pub fn effective_args(cmd: &Command, stock: &Command, matches: &ArgMatches) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Ok(Some(raw)) = matches.try_get_raw(id) else {
+            continue;
+        };
+        let values = raw
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        let explicit = match matches.value_source(id) {
+            Some(ValueSource::DefaultValue) => stock
+                .get_arguments()
+                .find(|stock| stock.get_id() == arg.get_id())
+                .is_none_or(|stock| stock.get_default_values() != arg.get_default_values()),
+            Some(_) => true,
+            None => false,
+        };
+
+        if arg.is_positional() {
+            args.extend(values);
+            continue;
+        }
+
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+
+        if !explicit {
+            continue;
+        }
+
+        if arg.get_action().takes_values() {
+            args.extend(values.into_iter().map(|value| format!("--{long}={value}")));
+        } else if values.iter().all(|value| value != "false") {
+            args.push(format!("--{long}"));
+        }
+    }
+
+    args
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -63,14 +369,151 @@ pub enum CommandError {
     #[error("output path is not a directory")]
     OutputPathNotDir,
 
+    #[error("unable to write {path}: {1}", path = .0.display())]
+    OutputError(PathBuf, std::io::Error),
+
+    #[error("template error: {0}")]
+    TemplateError(#[from] tera::Error),
+
     #[error("{0}")]
     SpriteSheetError(#[from] SpriteSheetError),
 
     #[error("{0}")]
     IconError(#[from] IconError),
+
+    #[error("{0}")]
+    SplitError(#[from] SplitError),
+
+    #[error("{0} of {1} folders failed")]
+    FoldersFailed(usize, usize, Box<Self>),
+
+    #[error("validation failed with {0} violations")]
+    ValidationFailed(usize),
+
+    #[error("{0} images differ")]
+    ImagesDiffer(usize),
+
+    #[error("{0} palette colors have no close match")]
+    PalettesDiffer(usize),
+
+    #[error("{0} outputs are outdated")]
+    OutputsOutdated(usize),
+
+    #[error("data file error: {0}")]
+    DataFileError(#[from] crate::lua::LuaParseError),
+
+    #[error("{0}")]
+    InitError(#[from] InitError),
+
+    #[error("{0}")]
+    RunError(#[from] RunError),
+
+    #[error("{0}")]
+    GifError(#[from] GifError),
+
+    #[error("{0}")]
+    DiffAnimError(#[from] DiffAnimError),
+
+    #[error("{0}")]
+    LockfileError(#[from] LockfileError),
+}
+
+/// Process exit codes for the different error classes, documented in the README.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Other = 1,
+    InputNotFound = 3,
+    InvalidInput = 4,
+    Output = 5,
+    Encoding = 6,
+    Validation = 7,
+}
+
+impl CommandError {
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::IoError(err) => io_error_kind(err),
+            // the lockfile and the hashed source frames are only read
+            Self::LockfileError(LockfileError::IoError(err)) => read_error_kind(err),
+            Self::ImageError(err) => image_error_kind(err),
+            Self::ImgUtilError(err) => img_util_error_kind(err),
+            Self::OutputPathNotDir
+            | Self::OutputError(..)
+            | Self::LockfileError(LockfileError::WriteError(..))
+            | Self::InitError(_) => ErrorKind::Output,
+            Self::SpriteSheetError(SpriteSheetError::MemoryLimitExceeded(..))
+            | Self::TemplateError(_) => ErrorKind::Other,
+            Self::SpriteSheetError(_)
+            | Self::IconError(_)
+            | Self::SplitError(_)
+            | Self::GifError(_)
+            | Self::DiffAnimError(_)
+            | Self::DataFileError(_)
+            | Self::LockfileError(LockfileError::Invalid(..))
+            | Self::RunError(_) => ErrorKind::InvalidInput,
+            Self::FoldersFailed(_, _, first) => first.kind(),
+            Self::ValidationFailed(_)
+            | Self::ImagesDiffer(_)
+            | Self::PalettesDiffer(_)
+            | Self::OutputsOutdated(_) => ErrorKind::Validation,
+        }
+    }
+
+    #[must_use]
+    pub fn exit_code(&self) -> u8 {
+        self.kind() as u8
+    }
+}
+
+/// Kind of an io error that isn't attributed to reading an input or writing an output.
+fn io_error_kind(err: &std::io::Error) -> ErrorKind {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => ErrorKind::InputNotFound,
+        std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidData => {
+            ErrorKind::InvalidInput
+        }
+        _ => ErrorKind::Other,
+    }
+}
+
+/// Kind of an io error while reading an input, missing inputs keep their own code.
+fn read_error_kind(err: &std::io::Error) -> ErrorKind {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => ErrorKind::InputNotFound,
+        _ => ErrorKind::InvalidInput,
+    }
+}
+
+fn image_error_kind(err: &image::ImageError) -> ErrorKind {
+    match err {
+        image::ImageError::IoError(err) => io_error_kind(err),
+        image::ImageError::Encoding(_) => ErrorKind::Encoding,
+        _ => ErrorKind::InvalidInput,
+    }
+}
+
+fn img_util_error_kind(err: &crate::image_util::ImgUtilError) -> ErrorKind {
+    use crate::image_util::ImgUtilError;
+
+    match err {
+        ImgUtilError::IOError(err) => io_error_kind(err),
+        ImgUtilError::ReadError(_, err) => read_error_kind(err),
+        ImgUtilError::WriteError(..) => ErrorKind::Output,
+        ImgUtilError::ImageError(err) => image_error_kind(err),
+        ImgUtilError::ImageQuantError(_)
+        | ImgUtilError::OxipngError(_)
+        | ImgUtilError::ApngError(_) => ErrorKind::Encoding,
+        ImgUtilError::PngDecodingError(_)
+        | ImgUtilError::InvalidPalette(_)
+        | ImgUtilError::NoImagesToCrop
+        | ImgUtilError::NotSameSize
+        | ImgUtilError::AllImagesEmpty => ErrorKind::InvalidInput,
+    }
 }
 
-#[derive(Args, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug, Clone)]
 pub struct SharedArgs {
     /// Folder containing the individual sprites.
     pub source: PathBuf,
@@ -78,10 +521,82 @@ pub struct SharedArgs {
     /// Output folder.
     pub output: PathBuf,
 
+    /// Image formats to accept as source images, e.g. `png,tga`.
+    #[clap(long, value_delimiter = ',', default_values_t = [InputFormat::Png])]
+    input_formats: Vec<InputFormat>,
+
+    /// Effective arguments the command was parsed from, recorded in the provenance and lockfile.
+    #[clap(skip)]
+    command_line: Vec<String>,
+
+    /// Use the defaults of a preset for the kind of graphics, options given explicitly still take precedence.
+    /// entity: cropped 64px/tile sheets, lua and v2 data
+    /// icon / gui / tech: uncropped 32px/tile images, lua and v2 data
+    /// decorative: like entity but lossy compressed and cropping slightly transparent pixels
+    #[clap(long, verbatim_doc_comment)]
+    pub preset: Option<Preset>,
+
     /// Enable lua output generation.
     #[clap(short, long, action)]
     lua: bool,
 
+    /// Enable json output generation.
+    #[clap(short, long, action)]
+    json: bool,
+
+    /// Enable toml output generation.
+    #[clap(long, action)]
+    toml: bool,
+
+    /// Enable yaml output generation.
+    #[clap(long, action)]
+    yaml: bool,
+
+    /// Write the lua output as a module with `get_animation(overrides)` / `get_icon(overrides)` helpers
+    /// that build complete prototype fragments instead of returning the raw values.
+    /// The helpers expect the default keys and tile shifts, so `--rename-key` and `--shift-format` can't be used with it.
+    #[clap(
+        long,
+        action,
+        requires = "lua",
+        conflicts_with_all = ["rename_key", "shift_format"],
+        verbatim_doc_comment
+    )]
+    lua_module: bool,
+
+    /// Write floats (shift, scale, ...) in the data output with a fixed amount of decimals.
+    /// This keeps the output stable across runs and platforms.
+    #[clap(long, value_name = "DECIMALS", verbatim_doc_comment)]
+    float_precision: Option<usize>,
+
+    /// Render the data output through a Tera template.
+    /// The template gets all data values plus `name` and `files`, the output extension is
+    /// taken from the template name without the last extension (`data.ts.tera` -> `.ts`).
+    #[clap(long, value_name = "PATH", verbatim_doc_comment)]
+    template: Option<PathBuf>,
+
+    /// Record the source folder, frame count, a hash of the input images and the command line in the data output.
+    #[clap(long, action)]
+    embed_provenance: bool,
+
+    /// Layout version of the data output, written as `format_version`.
+    /// v1: `sprite_count` is the total amount of sprites, rotated animations add `frame_count` and `direction_count`.
+    /// v2: `frame_count` (per direction) and `direction_count` are always present, `sprite_count` is removed.
+    #[clap(long, default_value_t = DataFormat::V1, verbatim_doc_comment)]
+    data_format: DataFormat,
+
+    /// Print the data output to stdout instead of writing it next to the sheets.
+    #[clap(long, action)]
+    stdout_data: bool,
+
+    /// Rename a key of the data output, e.g. `line_length=columns`. Can be used multiple times.
+    #[clap(long, value_name = "OLD=NEW", value_parser = parse_key_mapping)]
+    rename_key: Vec<(String, String)>,
+
+    // lua style args
+    #[clap(flatten)]
+    lua_style: LuaStyleArgs,
+
     /// Prefix to add to the output file name.
     #[clap(short, long, default_value_t = String::new())]
     prefix: String,
@@ -90,6 +605,831 @@ pub struct SharedArgs {
     /// This is using pngquant / imagequant internally.
     #[clap(long, action)]
     lossy: bool,
+
+    /// Remap the lossy compression to a palette written by `--palette-out`
+    /// to keep colors consistent across files and runs.
+    #[clap(long, value_name = "PATH", requires = "lossy", verbatim_doc_comment)]
+    palette_in: Option<PathBuf>,
+
+    /// Write the palette of the lossy compression to a file.
+    #[clap(long, value_name = "PATH", requires = "lossy")]
+    palette_out: Option<PathBuf>,
+
+    // encoding args
+    #[clap(flatten)]
+    encode: EncodeArgs,
+
+    /// Don't write any outputs, compare them with the existing files instead and fail when they differ.
+    /// Images are compared by their pixels and the spritter version in data files is ignored,
+    /// e.g. to enforce in CI that committed sheets match the committed source frames.
+    #[clap(long, action, conflicts_with = "stdout_data", verbatim_doc_comment)]
+    check: bool,
+
+    /// Record hashes of the source frames and the used flags of every output in a lockfile, e.g. `spritter.lock`.
+    /// Frames that changed since the last run are reported before the lockfile is updated.
+    #[clap(long, value_name = "PATH", verbatim_doc_comment)]
+    lockfile: Option<PathBuf>,
+
+    /// Fail instead of updating the lockfile when source frames or flags differ from it.
+    #[clap(long, action, requires = "lockfile")]
+    locked: bool,
+}
+
+impl std::fmt::Display for ShiftFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tiles => write!(f, "tiles"),
+            Self::Pixels => write!(f, "pixels"),
+            Self::ByPixel => write!(f, "by-pixel"),
+        }
+    }
+}
+
+impl ValueEnum for ShiftFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Self::Tiles => PossibleValue::new("tiles"),
+            Self::Pixels => PossibleValue::new("pixels"),
+            Self::ByPixel => PossibleValue::new("by-pixel").alias("by_pixel"),
+        })
+    }
+}
+
+/// Curated option defaults for common kinds of graphics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
+pub enum Preset {
+    Entity,
+    Icon,
+    Gui,
+    Tech,
+    Decorative,
+}
+
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Entity => write!(f, "entity"),
+            Self::Icon => write!(f, "icon"),
+            Self::Gui => write!(f, "gui"),
+            Self::Tech => write!(f, "tech"),
+            Self::Decorative => write!(f, "decorative"),
+        }
+    }
+}
+
+impl ValueEnum for Preset {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Entity => "entity",
+            Self::Icon => "icon",
+            Self::Gui => "gui",
+            Self::Tech => "tech",
+            Self::Decorative => "decorative",
+        }))
+    }
+}
+
+impl Preset {
+    /// Default values by long option name, options a command doesn't have are ignored.
+    ///
+    /// Mipmaps only exist for icons, the game generates them for entity sheets itself.
+    #[must_use]
+    pub const fn defaults(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Entity => &[
+                ("tile-resolution", "64"),
+                ("lua", "true"),
+                ("data-format", "v2"),
+            ],
+            // icons, gui sprites and technology icons are used at their exact size
+            Self::Icon => &[
+                ("tile-resolution", "32"),
+                ("no-crop", "true"),
+                ("icon-size", "64"),
+                ("mipmap-levels", "4"),
+                ("lua", "true"),
+                ("data-format", "v2"),
+            ],
+            // gui sprites are always drawn at their own size, mipmaps would be unused
+            Self::Gui => &[
+                ("tile-resolution", "32"),
+                ("no-crop", "true"),
+                ("icon-size", "64"),
+                ("mipmap-levels", "1"),
+                ("lua", "true"),
+                ("data-format", "v2"),
+            ],
+            Self::Tech => &[
+                ("tile-resolution", "32"),
+                ("no-crop", "true"),
+                ("icon-size", "256"),
+                ("mipmap-levels", "4"),
+                ("lua", "true"),
+                ("data-format", "v2"),
+            ],
+            Self::Decorative => &[
+                ("tile-resolution", "64"),
+                ("crop-alpha", "8"),
+                ("lossy", "true"),
+                ("lua", "true"),
+                ("data-format", "v2"),
+            ],
+        }
+    }
+}
+
+/// Versioned layout of the data output.
+///
+/// Keys of a released version are never renamed or removed, new keys may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
+pub enum DataFormat {
+    V1,
+    V2,
+}
+
+impl std::fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V1 => write!(f, "v1"),
+            Self::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+impl ValueEnum for DataFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }))
+    }
+}
+
+/// Raster formats accepted as source images, anything but png is converted on load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
+pub enum InputFormat {
+    Png,
+    Tga,
+    Bmp,
+    Tiff,
+}
+
+impl std::fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Png => write!(f, "png"),
+            Self::Tga => write!(f, "tga"),
+            Self::Bmp => write!(f, "bmp"),
+            Self::Tiff => write!(f, "tiff"),
+        }
+    }
+}
+
+impl ValueEnum for InputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Png => "png",
+            Self::Tga => "tga",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tiff",
+        }))
+    }
+}
+
+impl From<InputFormat> for ImageFormat {
+    fn from(value: InputFormat) -> Self {
+        match value {
+            InputFormat::Png => Self::Png,
+            InputFormat::Tga => Self::Tga,
+            InputFormat::Bmp => Self::Bmp,
+            InputFormat::Tiff => Self::Tiff,
+        }
+    }
+}
+
+impl DataFormat {
+    const fn version(self) -> u32 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    /// Convert the internal (v1) data into this format, including the `format_version` field.
+    fn convert(self, data: &LuaOutput) -> LuaOutput {
+        let data = match self {
+            Self::V1 => data.clone(),
+            Self::V2 => to_v2(data.clone()),
+        };
+
+        data.set("format_version", self.version())
+    }
+}
+
+/// Apply `f` to the table and all nested tables, innermost first.
+fn map_tables(mut data: LuaOutput, f: &impl Fn(LuaOutput) -> LuaOutput) -> LuaOutput {
+    for key in data.keys() {
+        let value = match data.remove(&key) {
+            Some(LuaValue::Table(table)) => LuaValue::Table(map_tables(table, f)),
+            Some(LuaValue::Array(arr)) => LuaValue::Array(
+                arr.into_vec()
+                    .into_iter()
+                    .map(|value| match value {
+                        LuaValue::Table(table) => LuaValue::Table(map_tables(table, f)),
+                        value => value,
+                    })
+                    .collect(),
+            ),
+            Some(value) => value,
+            None => continue,
+        };
+
+        data = data.set(key, value);
+    }
+
+    f(data)
+}
+
+/// Replace `sprite_count` with `frame_count` and `direction_count` in all nested tables.
+fn to_v2(data: LuaOutput) -> LuaOutput {
+    map_tables(data, &|mut data| {
+        let Some(sprite_count) = data.remove("sprite_count") else {
+            return data;
+        };
+
+        // static sprites don't carry any animation layout
+        if data.get("type").and_then(LuaValue::as_str) == Some("sprite") {
+            for key in ["line_length", "lines_per_file", "file_count"] {
+                data.remove(key);
+            }
+
+            return data;
+        }
+
+        if data.get("frame_count").is_none() {
+            data = data.set("frame_count", sprite_count);
+        }
+
+        if data.get("direction_count").is_none() {
+            data = data.set("direction_count", 1);
+        }
+
+        data
+    })
+}
+
+/// Rename the keys of all nested tables according to `mapping`.
+fn rename_keys(data: LuaOutput, mapping: &[(String, String)]) -> LuaOutput {
+    if mapping.is_empty() {
+        return data;
+    }
+
+    map_tables(data, &|mut data| {
+        for (old, new) in mapping {
+            if let Some(value) = data.remove(old) {
+                data = data.set(new, value);
+            }
+        }
+
+        data
+    })
+}
+
+fn parse_key_mapping(mapping: &str) -> Result<(String, String), String> {
+    match mapping.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_owned(), new.to_owned()))
+        }
+        _ => Err(format!("expected OLD=NEW, got: {mapping}")),
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct EncodeArgs {
+    /// Use the Zopfli deflater for the final compression pass, optionally with the amount of iterations.
+    /// This compresses a few percent better but is a lot slower, mostly useful for release builds.
+    #[clap(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "15",
+        value_name = "ITERATIONS",
+        verbatim_doc_comment
+    )]
+    zopfli: Option<NonZeroU8>,
+
+    /// Write Adam7 interlaced PNGs for progressive loading, e.g. for web previews of large sheets.
+    /// This usually increases the file size.
+    #[clap(long, action, verbatim_doc_comment)]
+    interlace: bool,
+
+    /// Speed of the lossy quantization, from 1 (slowest, best quality) to 10 (fastest).
+    /// Higher values are useful for quick iteration builds.
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=10), verbatim_doc_comment)]
+    quant_speed: u8,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LuaStyleArgs {
+    /// Amount of spaces to indent the entries of the lua output with.
+    #[clap(long = "lua-indent", default_value_t = 2)]
+    indent: usize,
+
+    /// Omit the generator comment at the start of the lua output.
+    #[clap(long = "lua-no-header", action)]
+    no_header: bool,
+
+    /// Omit the comma after the last entry of each table in the lua output.
+    #[clap(long = "lua-no-trailing-comma", action)]
+    no_trailing_comma: bool,
+
+    /// Write the lua output as `local data = {...} return data` instead of returning the table directly.
+    #[clap(long = "lua-local", action)]
+    local: bool,
+
+    /// Format of the shift in the lua output.
+    /// tiles: `{x = 12 / 64, y = -4 / 64}`, pixels divided by the tile resolution
+    /// pixels: `{x = 12, y = -4}`, raw pixels at the tile resolution
+    /// by-pixel: `util.by_pixel(6, -2)`, pixels at 32px per tile
+    #[clap(long, default_value_t = ShiftFormat::Tiles, verbatim_doc_comment)]
+    shift_format: ShiftFormat,
+}
+
+impl LuaStyleArgs {
+    #[must_use]
+    pub const fn style(&self) -> LuaStyle {
+        LuaStyle {
+            indent: self.indent,
+            header: !self.no_header,
+            trailing_comma: !self.no_trailing_comma,
+            local: self.local,
+            float_precision: None,
+            shift_format: self.shift_format,
+        }
+    }
+}
+
+/// Flags that don't change the generated outputs.
+const NEUTRAL_FLAGS: [&str; 7] = [
+    "--check",
+    "--quiet",
+    "--no-color",
+    "--porcelain",
+    "--verbose",
+    "--locked",
+    "--single-threaded-encode",
+];
+
+/// Options with a value that don't change the generated outputs.
+const NEUTRAL_OPTIONS: [&str; 4] = [
+    "--threads",
+    "--oxipng-threads",
+    "--annotations",
+    "--lockfile",
+];
+
+/// Command line without the flags that don't change the outputs,
+/// so the data of `--check` or verbose runs matches the regular run.
+fn output_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut kept = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+        let short_flags = arg.len() > 1
+            && arg.starts_with('-')
+            && !arg.starts_with("--")
+            && arg[1..].chars().all(|c| c == 'q' || c == 'v');
+
+        if NEUTRAL_FLAGS.contains(&arg.as_str()) || short_flags {
+            continue;
+        }
+
+        if NEUTRAL_OPTIONS.contains(&name) {
+            if !arg.contains('=') {
+                args.next();
+            }
+            continue;
+        }
+
+        kept.push(arg);
+    }
+
+    kept
+}
+
+/// Lockfile updated with the hashes of the source frames, waiting for the outputs to be generated.
+struct PendingLock {
+    path: PathBuf,
+    lock: Lockfile,
+    entries: Vec<(String, LockEntry)>,
+    changes: usize,
+}
+
+impl PendingLock {
+    /// Save the lockfile with the entries of all `generated` outputs.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the lockfile can't be written.
+    fn save(mut self, generated: &[&str]) -> Result<(), CommandError> {
+        for (name, entry) in self.entries {
+            if generated.contains(&name.as_str()) {
+                self.lock.insert(name, entry);
+            }
+        }
+
+        self.lock.save(&self.path)?;
+        crate::summary::record_file(&self.path);
+        info!(
+            "{} changes since the last run, updated {}",
+            self.changes,
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Save the lockfile of a command with a single output once it was generated.
+fn save_lock(lock: Option<PendingLock>, name: &str) -> Result<(), CommandError> {
+    lock.map_or(Ok(()), |lock| lock.save(&[name]))
+}
+
+/// Hash of the source frames for `--embed-provenance`.
+#[derive(Default)]
+pub struct Provenance {
+    hasher: sha2::Sha256,
+    frame_count: usize,
+    command: String,
+}
+
+impl Provenance {
+    pub fn add(&mut self, image: &RgbaImage) {
+        use sha2::Digest as _;
+
+        self.hasher.update(image.width().to_le_bytes());
+        self.hasher.update(image.height().to_le_bytes());
+        self.hasher.update(image.as_raw());
+        self.frame_count += 1;
+    }
+
+    pub fn finish(self, source: &Path) -> LuaOutput {
+        use sha2::Digest as _;
+        use std::fmt::Write as _;
+
+        let mut hash = String::with_capacity(64);
+        for byte in self.hasher.finalize() {
+            let _ = write!(hash, "{byte:02x}");
+        }
+
+        LuaOutput::new()
+            .set("source", source.display().to_string())
+            .set("frame_count", self.frame_count)
+            .set("hash", format!("sha256:{hash}"))
+            .set("command", self.command)
+    }
+}
+
+impl SharedArgs {
+    fn palette(&self) -> PaletteOptions {
+        PaletteOptions {
+            input: self.palette_in.clone(),
+            output: self.palette_out.clone(),
+        }
+    }
+
+    /// Image formats to load source images from.
+    fn input_formats(&self) -> Vec<ImageFormat> {
+        self.input_formats
+            .iter()
+            .map(|&format| format.into())
+            .collect()
+    }
+
+    /// Provenance table for the data output when `--embed-provenance` is set.
+    fn provenance(&self, source: &Path, images: &[RgbaImage]) -> Option<LuaOutput> {
+        let mut provenance = self.provenance_hasher()?;
+        for image in images {
+            provenance.add(image);
+        }
+
+        Some(provenance.finish(source))
+    }
+
+    /// Incremental variant of [`SharedArgs::provenance`] for frames that are not kept in memory.
+    fn provenance_hasher(&self) -> Option<Provenance> {
+        self.embed_provenance.then(|| Provenance {
+            command: self.recorded_command(),
+            ..Provenance::default()
+        })
+    }
+
+    /// Set the effective arguments the command was parsed from, see [`effective_args`].
+    pub fn set_command_line(&mut self, command_line: Vec<String>) {
+        self.command_line = command_line;
+    }
+
+    /// Effective command line without the flags that don't change the outputs, quoted like in a shell.
+    fn recorded_command(&self) -> String {
+        output_args(self.command_line.iter().cloned())
+            .into_iter()
+            .map(|arg| {
+                if arg.is_empty()
+                    || arg.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"')
+                {
+                    format!("'{}'", arg.replace('\'', "'\\''"))
+                } else {
+                    arg
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Compare the source frames of the outputs with `--lockfile` before generating them,
+    /// with `--locked` any difference fails instead.
+    ///
+    /// With `directions` the frames of the direction subfolders of a rotated sprite are part of the source.
+    /// Returns the updated lockfile, it is only saved with [`PendingLock::save`] once the outputs were generated.
+    fn lock_sources(
+        &self,
+        outputs: &[(String, &Path)],
+        directions: bool,
+    ) -> Result<Option<PendingLock>, CommandError> {
+        let Some(path) = &self.lockfile else {
+            return Ok(None);
+        };
+
+        let lock = Lockfile::load(path)?;
+        let command = self.recorded_command();
+        let formats = self.input_formats();
+
+        let mut changes = Vec::new();
+        let mut entries = Vec::with_capacity(outputs.len());
+        for (name, source) in outputs {
+            let mut files = image_util::image_paths(source, &formats)?;
+            if directions {
+                let mut visited = HashSet::from([source.canonicalize()?]);
+                for folder in recursive_folders(source, false, &mut visited)? {
+                    files.extend(image_util::image_paths(&folder, &formats)?);
+                }
+            }
+
+            // folders without frames don't produce any output
+            if files.is_empty() {
+                continue;
+            }
+
+            let entry = LockEntry::new(command.clone(), source, &files)?;
+            match lock.get(name) {
+                Some(old) => {
+                    changes.extend(entry.diff(old).into_iter().map(|change| match change {
+                        LockChange::Added(frame) => {
+                            (source.join(frame), "added since the lockfile".to_owned())
+                        }
+                        LockChange::Changed(frame) => {
+                            (source.join(frame), "changed since the lockfile".to_owned())
+                        }
+                        LockChange::Removed(frame) => (
+                            source.to_path_buf(),
+                            format!("{frame} was removed since the lockfile"),
+                        ),
+                        LockChange::Command(old) => (
+                            source.to_path_buf(),
+                            format!("flags changed since the lockfile, locked: {old}"),
+                        ),
+                    }));
+                }
+                None if self.locked => changes.push((
+                    source.to_path_buf(),
+                    format!("{name} is not part of the lockfile"),
+                )),
+                None => debug!("{}: adding {name} to the lockfile", source.display()),
+            }
+
+            entries.push((name.clone(), entry));
+        }
+
+        for (source, msg) in &changes {
+            warn!("{}: {msg}", source.display());
+        }
+
+        if self.locked {
+            if !changes.is_empty() {
+                for (source, msg) in &changes {
+                    crate::summary::record_failure(Some(source), msg);
+                }
+                return Err(CommandError::ValidationFailed(changes.len()));
+            }
+
+            info!("source frames match {}", path.display());
+            return Ok(None);
+        }
+
+        // `--check` doesn't write anything
+        if crate::check::enabled() {
+            return Ok(None);
+        }
+
+        Ok(Some(PendingLock {
+            path: path.clone(),
+            lock,
+            entries,
+            changes: changes.len(),
+        }))
+    }
+
+    /// Save the data file(s) of the sprite `name` in all enabled output formats.
+    ///
+    /// `files` are the generated images described by the data, used by the lua module output.
+    fn save_data(
+        &self,
+        name: &str,
+        data: &LuaOutput,
+        files: &[PathBuf],
+    ) -> Result<(), CommandError> {
+        self.save_data_to(
+            |extension| {
+                Ok(output_path(
+                    name,
+                    &self.output,
+                    None,
+                    &self.prefix,
+                    extension,
+                ))
+            },
+            data,
+            files,
+        )
+    }
+
+    /// Save the data file(s) in all enabled output formats, `path` maps the extension to the output path.
+    fn save_data_to(
+        &self,
+        path: impl Fn(&str) -> Result<PathBuf, CommandError>,
+        data: &LuaOutput,
+        files: &[PathBuf],
+    ) -> Result<(), CommandError> {
+        let style = LuaStyle {
+            float_precision: self.float_precision,
+            ..self.lua_style.style()
+        };
+
+        let data = &rename_keys(self.data_format.convert(data), &self.rename_key);
+
+        let file_names = files
+            .iter()
+            .filter_map(|file| file.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+        if self.lua {
+            let mut out = Vec::new();
+            if self.lua_module && !file_names.is_empty() {
+                data.write_module(&mut out, style, &file_names)?;
+            } else {
+                data.write(&mut out, style)?;
+            }
+            self.emit(&path("lua")?, &out)?;
+        }
+
+        if self.json {
+            let mut out = Vec::new();
+            data.write_json(&mut out, self.float_precision)?;
+            self.emit(&path("json")?, &out)?;
+        }
+
+        if self.toml {
+            let mut out = Vec::new();
+            data.write_toml(&mut out, self.float_precision)?;
+            self.emit(&path("toml")?, &out)?;
+        }
+
+        if self.yaml {
+            let mut out = Vec::new();
+            data.write_yaml(&mut out, self.float_precision)?;
+            self.emit(&path("yaml")?, &out)?;
+        }
+
+        if let Some(template) = &self.template {
+            let extension = template
+                .file_stem()
+                .map(Path::new)
+                .and_then(Path::extension)
+                .map_or_else(|| "txt".to_owned(), |ext| ext.to_string_lossy().to_string());
+            let out = path(&extension)?;
+
+            let mut context = tera::Context::from_value(
+                data.to_json_value(self.float_precision)
+                    .map_err(std::io::Error::other)?,
+            )?;
+            #[allow(clippy::unwrap_used)]
+            context.insert(
+                "name",
+                &out.file_stem().unwrap().to_string_lossy().to_string(),
+            );
+            context.insert("files", &file_names);
+
+            let rendered = tera::Tera::one_off(&fs::read_to_string(template)?, &context, false)?;
+            self.emit(&out, rendered.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a data file, or print it to stdout when `--stdout-data` is set.
+    fn emit(&self, path: &Path, content: &[u8]) -> Result<(), CommandError> {
+        if self.stdout_data {
+            // the summary would end up after the data
+            if crate::summary::disable() {
+                warn!("no summary is printed when writing the data to stdout");
+            }
+
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(content)?;
+            Ok(stdout.flush()?)
+        } else {
+            crate::check::write_data(path, content).map_err(output_error(path))
+        }
+    }
+}
+
+impl EncodeArgs {
+    #[must_use]
+    pub const fn options(&self) -> EncodeOptions {
+        EncodeOptions {
+            zopfli: self.zopfli,
+            interlace: self.interlace,
+            quant_speed: self.quant_speed,
+        }
+    }
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let value = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{value} is not in 0.0..=1.0"))
+    }
+}
+
+fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid size: {value}"))?;
+
+    let factor = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown size unit: {unit}")),
+    };
+
+    Ok((number * factor).round() as u64)
+}
+
+fn human_readable_bytes(bytes: u64) -> String {
+    static UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"]; // wtf are you doing if this saves you petabytes -.-
+
+    if bytes < 1000 {
+        return format!("{bytes}{}", UNITS[0]);
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{:.2}{}", size, UNITS[unit])
 }
 
 fn output_name(
@@ -99,17 +1439,37 @@ fn output_name(
     prefix: &str,
     extension: &str,
 ) -> Result<PathBuf, CommandError> {
+    Ok(output_path(
+        &folder_name(source)?,
+        output_dir,
+        id,
+        prefix,
+        extension,
+    ))
+}
+
+/// Name of the file or folder `source` resolves to, symlinks are followed.
+fn folder_name(source: impl AsRef<Path>) -> Result<String, CommandError> {
     #[allow(clippy::unwrap_used)]
-    let name = source
+    Ok(source
         .as_ref()
         .canonicalize()?
         .components()
-        .last()
+        .next_back()
         .unwrap()
         .as_os_str()
         .to_string_lossy()
-        .to_string();
+        .to_string())
+}
 
+/// Output path of the sprite `name`, `id` numbers multiple files of the same sprite.
+fn output_path(
+    name: &str,
+    output_dir: impl AsRef<Path>,
+    id: Option<usize>,
+    prefix: &str,
+    extension: &str,
+) -> PathBuf {
     let pre_suff_name = id.map_or_else(
         || format!("{prefix}{name}"),
         |id| format!("{prefix}{name}-{id}"),
@@ -118,5 +1478,235 @@ fn output_name(
     let mut out = output_dir.as_ref().join(pre_suff_name);
     out.set_extension(extension);
 
-    Ok(out)
+    out
+}
+
+/// Create the output folder `path`.
+fn create_output_dir(path: &Path) -> Result<(), CommandError> {
+    crate::check::create_dir(path).map_err(output_error(path))?;
+    if path.exists() && !path.is_dir() {
+        return Err(CommandError::OutputPathNotDir);
+    }
+
+    Ok(())
+}
+
+/// Attribute an io failure to writing the output `path`.
+fn output_error(path: &Path) -> impl Fn(std::io::Error) -> CommandError + '_ {
+    move |err| CommandError::OutputError(path.to_owned(), err)
+}
+
+/// Attribute io failures while saving the output image `path` to writing it.
+fn output_image_error(path: &Path) -> impl Fn(image::ImageError) -> CommandError + '_ {
+    move |err| match err {
+        image::ImageError::IoError(err) => CommandError::OutputError(path.to_owned(), err),
+        err => err.into(),
+    }
+}
+
+/// Direction names of a 16 way rotated animation, starting north and going clockwise.
+const COMPASS: [&str; 16] = [
+    "north",
+    "north-north-east",
+    "north-east",
+    "east-north-east",
+    "east",
+    "east-south-east",
+    "south-east",
+    "south-south-east",
+    "south",
+    "south-south-west",
+    "south-west",
+    "west-south-west",
+    "west",
+    "west-north-west",
+    "north-west",
+    "north-north-west",
+];
+
+/// Name of a direction in a rotated animation, starting north and going clockwise.
+fn direction_name(direction: usize, directions: u32) -> String {
+    match directions {
+        1 | 2 | 4 | 8 | 16 => COMPASS[direction * (16 / directions as usize)].to_owned(),
+        _ => format!("direction-{direction}"),
+    }
+}
+
+/// Sort position of a direction folder name as written by [`direction_name`].
+fn direction_index(name: &str) -> Option<usize> {
+    COMPASS.iter().position(|dir| *dir == name).or_else(|| {
+        name.strip_prefix("direction-")
+            .and_then(|idx| idx.parse().ok())
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{
+        output_args, parse_byte_size, parse_key_mapping, rename_keys, to_v2, LuaOutput, LuaValue,
+    };
+
+    fn int(data: &LuaOutput, key: &str) -> Option<i64> {
+        data.get(key).and_then(LuaValue::as_int)
+    }
+
+    #[test]
+    fn v2_animation() {
+        let data = to_v2(
+            LuaOutput::new()
+                .set("sprite_count", 8)
+                .set("line_length", 4),
+        );
+
+        assert!(data.get("sprite_count").is_none());
+        assert_eq!(int(&data, "frame_count"), Some(8));
+        assert_eq!(int(&data, "direction_count"), Some(1));
+        assert_eq!(int(&data, "line_length"), Some(4));
+    }
+
+    #[test]
+    fn v2_rotated_animation() {
+        let data = to_v2(
+            LuaOutput::new()
+                .set("sprite_count", 32)
+                .set("frame_count", 4)
+                .set("direction_count", 8),
+        );
+
+        assert!(data.get("sprite_count").is_none());
+        assert_eq!(int(&data, "frame_count"), Some(4));
+        assert_eq!(int(&data, "direction_count"), Some(8));
+    }
+
+    #[test]
+    fn v2_sprite() {
+        let data = to_v2(
+            LuaOutput::new()
+                .set("type", "sprite")
+                .set("sprite_count", 1)
+                .set("line_length", 1)
+                .set("width", 64),
+        );
+
+        assert_eq!(data.keys(), ["type", "width"]);
+    }
+
+    #[test]
+    fn v2_nested_tables() {
+        let layer = LuaOutput::new().set("sprite_count", 8);
+        let data = to_v2(LuaOutput::new().set("layers", vec![layer].into_boxed_slice()));
+
+        let layers = data.get("layers").and_then(LuaValue::as_array).unwrap();
+        let LuaValue::Table(layer) = &layers[0] else {
+            panic!("layer is not a table");
+        };
+        assert!(layer.get("sprite_count").is_none());
+        assert_eq!(int(layer, "frame_count"), Some(8));
+    }
+
+    #[test]
+    fn renamed_keys() {
+        let mapping = [
+            parse_key_mapping("line_length=columns").unwrap(),
+            parse_key_mapping("missing=other").unwrap(),
+        ];
+        let layer = LuaOutput::new().set("line_length", 4);
+        let data = rename_keys(
+            LuaOutput::new()
+                .set("line_length", 8)
+                .set("width", 64)
+                .set("layers", vec![layer].into_boxed_slice()),
+            &mapping,
+        );
+
+        assert!(data.get("line_length").is_none());
+        assert!(data.get("other").is_none());
+        assert_eq!(int(&data, "columns"), Some(8));
+        assert_eq!(int(&data, "width"), Some(64));
+
+        let layers = data.get("layers").and_then(LuaValue::as_array).unwrap();
+        let LuaValue::Table(layer) = &layers[0] else {
+            panic!("layer is not a table");
+        };
+        assert_eq!(int(layer, "columns"), Some(4));
+    }
+
+    #[test]
+    fn key_mappings() {
+        assert_eq!(
+            parse_key_mapping("a=b").unwrap(),
+            ("a".to_owned(), "b".to_owned())
+        );
+        assert!(parse_key_mapping("a").is_err());
+        assert!(parse_key_mapping("=b").is_err());
+        assert!(parse_key_mapping("a=").is_err());
+    }
+
+    fn args(args: &str) -> Vec<String> {
+        output_args(args.split(' ').map(str::to_owned))
+    }
+
+    #[test]
+    fn neutral_args_are_dropped() {
+        let expected = args("spritesheet a out --tile-resolution 32");
+
+        assert_eq!(
+            args("spritesheet a out --threads 4 --tile-resolution 32 --check"),
+            expected
+        );
+        assert_eq!(
+            args("spritesheet a out --threads=4 --tile-resolution 32 -qv"),
+            expected
+        );
+        assert_eq!(
+            args("spritesheet --lockfile spritter.lock a out --no-color --tile-resolution 32"),
+            expected
+        );
+        assert_eq!(
+            args("spritesheet --lockfile=spritter.lock a out --tile-resolution 32 --porcelain"),
+            expected
+        );
+    }
+
+    #[test]
+    fn output_args_are_kept() {
+        assert_eq!(
+            args("spritesheet a out --tile-resolution=32 -l -p x"),
+            [
+                "spritesheet",
+                "a",
+                "out",
+                "--tile-resolution=32",
+                "-l",
+                "-p",
+                "x"
+            ]
+        );
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(parse_byte_size("512").unwrap(), 512);
+        assert_eq!(parse_byte_size("512B").unwrap(), 512);
+        assert_eq!(parse_byte_size("500KB").unwrap(), 500_000);
+        assert_eq!(parse_byte_size("1.5MB").unwrap(), 1_500_000);
+        assert_eq!(parse_byte_size("2 gb").unwrap(), 2_000_000_000);
+        assert_eq!(parse_byte_size("1k").unwrap(), 1000);
+    }
+
+    #[test]
+    fn binary_byte_sizes() {
+        assert_eq!(parse_byte_size("1KiB").unwrap(), 1024);
+        assert_eq!(parse_byte_size("1.5MiB").unwrap(), 1_572_864);
+        assert_eq!(parse_byte_size(" 1 GiB ").unwrap(), 1_073_741_824);
+    }
+
+    #[test]
+    fn invalid_byte_sizes() {
+        assert!(parse_byte_size("").is_err());
+        assert!(parse_byte_size("MB").is_err());
+        assert!(parse_byte_size("5TB").is_err());
+        assert!(parse_byte_size("-5MB").is_err());
+    }
 }
@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::{ImageFormat, Rgba, RgbaImage};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+
+use super::{create_output_dir, output_image_error, CommandError};
+use crate::{image_util, summary};
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// First image or folder of images.
+    pub a: PathBuf,
+
+    /// Second image or folder of images, folders are matched by file name.
+    pub b: PathBuf,
+
+    /// Minimum structural similarity [0.0-1.0] each image pair must reach.
+    #[clap(long, default_value_t = 0.99)]
+    pub min_ssim: f64,
+
+    /// Max difference of a premultiplied color channel [0-255] that still counts as the same pixel.
+    #[clap(long, default_value_t = 0)]
+    pub tolerance: u8,
+
+    /// Folder to write difference images of the pairs that are not identical to.
+    /// Differing pixels are highlighted in red on top of a faded version of the first image.
+    #[clap(long, verbatim_doc_comment)]
+    pub diff_output: Option<PathBuf>,
+}
+
+/// Differences of an image pair.
+struct Difference {
+    pixels: usize,
+    changed: usize,
+    max_delta: u8,
+    ssim: f64,
+    psnr: f64,
+    diff: Option<RgbaImage>,
+}
+
+/// Compare two images or folders, fails when any pair is below the similarity threshold.
+///
+/// # Errors
+///
+/// Fails when the inputs can't be loaded or [`CommandError::ImagesDiffer`] when any pair differs.
+pub fn compare(args: &CompareArgs) -> Result<(), CommandError> {
+    let pairs = image_pairs(&args.a, &args.b)?;
+    if pairs.is_empty() {
+        warn!("no source images found");
+        return Ok(());
+    }
+
+    if let Some(out) = &args.diff_output {
+        create_output_dir(out)?;
+    }
+
+    let results = pairs
+        .par_iter()
+        .map(|(name, a, b)| {
+            let (Some(a), Some(b)) = (a, b) else {
+                return Ok(Err(if a.is_some() {
+                    format!("missing in {}", args.b.display())
+                } else {
+                    format!("missing in {}", args.a.display())
+                }));
+            };
+
+            let a = image_util::load_image_from_file(a)?;
+            let b = image_util::load_image_from_file(b)?;
+            if a.dimensions() != b.dimensions() {
+                return Ok(Err(format!(
+                    "size differs, {}x{}px != {}x{}px",
+                    a.width(),
+                    a.height(),
+                    b.width(),
+                    b.height()
+                )));
+            }
+
+            let res = difference(&a, &b, args.tolerance);
+            if let (Some(out), Some(diff)) = (&args.diff_output, &res.diff) {
+                let mut path = out.join(name);
+                path.set_extension("diff.png");
+                diff.save(&path).map_err(output_image_error(&path))?;
+                summary::record_file(path);
+            }
+
+            Ok(Ok(res))
+        })
+        .collect::<Result<Vec<_>, CommandError>>()?;
+
+    let mut failed = 0;
+    for ((name, _, _), res) in pairs.iter().zip(results) {
+        let msg = match res {
+            Ok(res) if res.changed == 0 => {
+                debug!("{name}: identical");
+                continue;
+            }
+            Ok(res) => {
+                let percent = res.changed as f64 * 100.0 / res.pixels as f64;
+                let msg = format!(
+                    "{} pixels ({percent:.2}%) differ, max delta {}, ssim {:.4}, psnr {:.2}dB",
+                    res.changed, res.max_delta, res.ssim, res.psnr
+                );
+
+                if res.ssim >= args.min_ssim {
+                    info!("{name}: {msg}");
+                    continue;
+                }
+
+                format!("{msg}, below the minimum ssim of {}", args.min_ssim)
+            }
+            Err(msg) => msg,
+        };
+
+        failed += 1;
+        warn!("{name}: {msg}");
+        summary::record_failure(Some(Path::new(name)), &msg);
+    }
+
+    info!(
+        "compared {} images, {} differ too much",
+        pairs.len(),
+        failed
+    );
+
+    if failed > 0 {
+        return Err(CommandError::ImagesDiffer(failed));
+    }
+
+    Ok(())
+}
+
+type ImagePair = (String, Option<PathBuf>, Option<PathBuf>);
+
+/// Match the images of both paths by file name, two files are always compared with each other.
+fn image_pairs(a: &Path, b: &Path) -> Result<Vec<ImagePair>, CommandError> {
+    if a.is_file() && b.is_file() {
+        #[allow(clippy::unwrap_used)]
+        let name = a.file_name().unwrap().to_string_lossy().to_string();
+        return Ok(vec![(name, Some(a.to_path_buf()), Some(b.to_path_buf()))]);
+    }
+
+    let mut pairs = BTreeMap::<String, (Option<PathBuf>, Option<PathBuf>)>::new();
+    for path in image_util::image_paths(a, &[ImageFormat::Png])? {
+        #[allow(clippy::unwrap_used)]
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        pairs.entry(name).or_default().0 = Some(path);
+    }
+    for path in image_util::image_paths(b, &[ImageFormat::Png])? {
+        #[allow(clippy::unwrap_used)]
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        pairs.entry(name).or_default().1 = Some(path);
+    }
+
+    Ok(pairs
+        .into_iter()
+        .map(|(name, (a, b))| (name, a, b))
+        .collect())
+}
+
+fn difference(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> Difference {
+    let mut diff = RgbaImage::new(a.width(), a.height());
+    let mut changed = 0;
+    let mut max_delta = 0;
+
+    for ((pa, pb), out) in a.pixels().zip(b.pixels()).zip(diff.pixels_mut()) {
+        let delta = (0..4)
+            .map(|channel| premultiplied(*pa, channel).abs_diff(premultiplied(*pb, channel)))
+            .max()
+            .unwrap_or_default();
+        max_delta = max_delta.max(delta);
+
+        *out = if delta > tolerance {
+            changed += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // faded grayscale of the first image for orientation
+            let gray = (u16::from(pa[0]) + u16::from(pa[1]) + u16::from(pa[2])) / 3;
+            Rgba([gray as u8, gray as u8, gray as u8, pa[3] / 4])
+        };
+    }
+
+    Difference {
+        pixels: a.pixels().len(),
+        changed,
+        max_delta,
+        ssim: image_util::ssim(a, b),
+        psnr: image_util::psnr(a, b),
+        diff: (changed > 0).then_some(diff),
+    }
+}
+
+pub(super) fn premultiplied(pxl: Rgba<u8>, channel: usize) -> u8 {
+    if channel == 3 {
+        pxl[3]
+    } else {
+        (u16::from(pxl[channel]) * u16::from(pxl[3]) / 255) as u8
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fs, path::PathBuf};
+
+use clap::{builder::PossibleValue, Args, ValueEnum};
+use image::{ImageFormat, Rgba, RgbaImage};
+use strum::{EnumIter, VariantArray};
+
+use super::{
+    compare::premultiplied, output_error, save_gif, CommandError, FrameTimings, GIF_ALPHA_THRESHOLD,
+};
+use crate::image_util;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiffAnimError {
+    #[error("frame counts differ, {0} != {1}")]
+    FrameCount(usize, usize),
+
+    #[error("frame sizes differ, {0}x{1}px != {2}x{3}px")]
+    FrameSize(u32, u32, u32, u32),
+}
+
+#[derive(Args, Debug)]
+pub struct DiffAnimArgs {
+    /// Folder with the frames of the first version.
+    pub a: PathBuf,
+
+    /// Folder with the frames of the second version, matched by their sorted order.
+    pub b: PathBuf,
+
+    /// Output file, the extension decides between a gif (`.gif`) and an apng (`.png` / `.apng`).
+    #[clap(value_parser = parse_output)]
+    pub output: PathBuf,
+
+    /// How to show the differences.
+    /// `heatmap` colors changed pixels by how much they changed on top of a faded version of the first animation,
+    /// `blink` alternates between both versions of every frame.
+    #[clap(long, default_value_t = DiffMode::Heatmap, verbatim_doc_comment)]
+    pub mode: DiffMode,
+
+    /// Max difference of a premultiplied color channel [0-255] that still counts as the same pixel.
+    #[clap(long, default_value_t = 0)]
+    pub tolerance: u8,
+
+    /// Animation speed of the heatmap. This is identical to in-game speed, 1.0 means 60 frames per second.
+    #[clap(short = 's', long, default_value_t = 1.0)]
+    pub animation_speed: f64,
+
+    /// Duration in milliseconds each version is shown for in blink mode.
+    #[clap(long, default_value_t = 250, value_parser = clap::value_parser!(u32).range(10..))]
+    pub blink_duration: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, VariantArray)]
+pub enum DiffMode {
+    Heatmap,
+    Blink,
+}
+
+impl std::fmt::Display for DiffMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Heatmap => write!(f, "heatmap"),
+            Self::Blink => write!(f, "blink"),
+        }
+    }
+}
+
+impl ValueEnum for DiffMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Heatmap => "heatmap",
+            Self::Blink => "blink",
+        }))
+    }
+}
+
+fn parse_output(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gif" | "png" | "apng") => Ok(path),
+        _ => Err(format!("{value} has to end in .gif, .png or .apng")),
+    }
+}
+
+/// Render an animation of the differences between two versions of an animation.
+///
+/// # Errors
+///
+/// Fails when the animations can't be loaded, don't match in frame count or size, or the output can't be written.
+pub fn diff_anim(args: &DiffAnimArgs) -> Result<(), CommandError> {
+    if args.animation_speed <= 0.0 {
+        warn!("animation speed must be greater than 0");
+        return Ok(());
+    }
+
+    let a = image_util::load_from_path(&args.a, &[ImageFormat::Png])?;
+    let b = image_util::load_from_path(&args.b, &[ImageFormat::Png])?;
+
+    if a.len() != b.len() {
+        return Err(DiffAnimError::FrameCount(a.len(), b.len()).into());
+    }
+
+    if a.is_empty() {
+        warn!("no source images found");
+        return Ok(());
+    }
+
+    if let Some((fa, fb)) = a
+        .iter()
+        .zip(&b)
+        .find(|(fa, fb)| fa.dimensions() != fb.dimensions())
+    {
+        return Err(
+            DiffAnimError::FrameSize(fa.width(), fa.height(), fb.width(), fb.height()).into(),
+        );
+    }
+
+    let frame_count = a.len();
+    let mut changed_frames = 0;
+    let mut max_changed = (0, 0);
+    for (idx, (fa, fb)) in a.iter().zip(&b).enumerate() {
+        let changed = changed_pixels(fa, fb, args.tolerance);
+        debug!("frame {idx}: {changed} pixels differ");
+
+        if changed > 0 {
+            changed_frames += 1;
+        }
+        if changed > max_changed.1 {
+            max_changed = (idx, changed);
+        }
+    }
+
+    let (frames, speed) = match args.mode {
+        DiffMode::Heatmap => (
+            a.iter()
+                .zip(&b)
+                .map(|(fa, fb)| heatmap(fa, fb, args.tolerance))
+                .collect::<Vec<_>>(),
+            args.animation_speed,
+        ),
+        DiffMode::Blink => (
+            a.into_iter()
+                .zip(b)
+                .flat_map(<[RgbaImage; 2]>::from)
+                .collect(),
+            1000.0 / (60.0 * f64::from(args.blink_duration)),
+        ),
+    };
+
+    if let Some(parent) = args.output.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(output_error(parent))?;
+        }
+    }
+
+    if args.output.extension().is_some_and(|ext| ext == "gif") {
+        save_gif(
+            &frames,
+            &args.output,
+            speed,
+            GIF_ALPHA_THRESHOLD,
+            &FrameTimings::new(),
+        )?;
+    } else {
+        image_util::save_apng(&frames, &args.output, speed)?;
+    }
+
+    if changed_frames == 0 {
+        info!("animations are identical");
+    } else {
+        info!(
+            "{changed_frames} of {frame_count} frames differ, most changes in frame {} with {} pixels",
+            max_changed.0,
+            max_changed.1
+        );
+    }
+    info!("saved difference animation to {}", args.output.display());
+
+    Ok(())
+}
+
+fn channel_delta(a: Rgba<u8>, b: Rgba<u8>) -> u8 {
+    (0..4)
+        .map(|channel| premultiplied(a, channel).abs_diff(premultiplied(b, channel)))
+        .max()
+        .unwrap_or_default()
+}
+
+fn changed_pixels(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> usize {
+    a.pixels()
+        .zip(b.pixels())
+        .filter(|&(pa, pb)| channel_delta(*pa, *pb) > tolerance)
+        .count()
+}
+
+/// Changed pixels colored from blue (small change) over yellow to red (large change),
+/// everything else is a dark grayscale version of the first frame.
+fn heatmap(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let pa = *a.get_pixel(x, y);
+        let delta = channel_delta(pa, *b.get_pixel(x, y));
+
+        if delta <= tolerance {
+            let gray = (0..3)
+                .map(|channel| u16::from(premultiplied(pa, channel)))
+                .sum::<u16>()
+                / 9;
+            return Rgba([gray as u8, gray as u8, gray as u8, 255]);
+        }
+
+        // small changes are the interesting ones, spread them over more of the ramp
+        let t = (f64::from(delta) / 255.0).sqrt();
+        let lerp = |from: f64, to: f64, t: f64| t.mul_add(to - from, from).round() as u8;
+        if t < 0.5 {
+            let t = t * 2.0;
+            Rgba([
+                lerp(0.0, 255.0, t),
+                lerp(64.0, 255.0, t),
+                lerp(255.0, 0.0, t),
+                255,
+            ])
+        } else {
+            let t = (t - 0.5) * 2.0;
+            Rgba([255, lerp(255.0, 0.0, t), 0, 255])
+        }
+    })
+}
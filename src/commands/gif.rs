@@ -1,10 +1,49 @@
-use std::fs;
+use std::{
+    collections::BTreeMap,
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
 use clap::Args;
+use image::{imageops, RgbaImage};
 
-use super::{output_name, CommandError};
+use super::{
+    folder_name, human_readable_bytes, output_error, output_name, parse_byte_size, save_lock,
+    CommandError,
+};
 use crate::image_util;
 
+#[derive(Debug, thiserror::Error)]
+pub enum GifError {
+    #[error("{0} layer has {1} frames, expected 1 or {2}")]
+    LayerFrameCount(&'static str, usize, usize),
+
+    #[error("timings reference frame {0}, the animation has {1} frames")]
+    TimingFrameOutOfRange(usize, usize),
+
+    #[error("unable to fit the gif into {0}, the smallest attempt was {1}")]
+    MaxSizeUnreachable(String, String),
+}
+
+/// Scales tried in order by `--max-size`.
+const MAX_SIZE_SCALES: [f64; 6] = [1.0, 0.75, 0.5, 0.375, 0.25, 0.125];
+
+/// Max alpha value of a pixel that is made fully transparent in gifs unless `--alpha-threshold` is set.
+pub(super) const GIF_ALPHA_THRESHOLD: u8 = 10;
+
+/// Bits per color channel tried in order at every scale by `--max-size`.
+const MAX_SIZE_COLOR_BITS: [u8; 4] = [8, 6, 5, 4];
+
+/// Tint of the ghosts before (red) and after (blue) the current frame with `--onion-skin`.
+const ONION_SKIN_TINTS: [[u8; 3]; 2] = [[255, 64, 64], [64, 128, 255]];
+
+/// Opacity of the closest ghost with `--onion-skin`, farther ones fade out linearly.
+const ONION_SKIN_OPACITY: f64 = 0.5;
+
+/// Duration in milliseconds of individual frames, keyed by frame index.
+pub type FrameTimings = BTreeMap<usize, u32>;
+
 #[derive(Args, Debug)]
 pub struct GifArgs {
     // shared args
@@ -19,8 +58,61 @@ pub struct GifArgs {
 
     /// Alpha threshold to consider a pixel as transparent [0-255].
     /// Since GIFS only support 1-bit transparency, this is used to determine which pixels are transparent.
-    #[clap(short, long, default_value = "0", verbatim_doc_comment)]
+    #[clap(short, long, default_value_t = GIF_ALPHA_THRESHOLD, verbatim_doc_comment)]
     pub alpha_threshold: u8,
+
+    /// Shadow frames to draw below the source frames, e.g. the output of the shadow command.
+    #[clap(long, value_name = "PATH")]
+    pub shadow: Option<PathBuf>,
+
+    /// Glow / light frames to add on top of the source frames.
+    #[clap(long, value_name = "PATH")]
+    pub glow: Option<PathBuf>,
+
+    /// CSV file with `frame,duration` lines to override the duration (in ms) of individual frames.
+    /// Frame indices start at 0, frames that are not listed use the duration from `--animation-speed`.
+    #[clap(long, value_name = "PATH", value_parser = parse_timings, verbatim_doc_comment)]
+    pub timings: Option<FrameTimings>,
+
+    /// Max file size of the gif, e.g. `8MB`.
+    /// The frames are downscaled and their colors reduced step by step until the gif fits.
+    #[clap(long, value_name = "SIZE", value_parser = parse_byte_size, verbatim_doc_comment)]
+    pub max_size: Option<u64>,
+
+    /// Blend each frame with tinted ghosts of its N previous (red) and next (blue) frames.
+    /// This helps to spot jitter and popping between frames, the animation loops around at the ends.
+    #[clap(long, value_name = "N", verbatim_doc_comment)]
+    pub onion_skin: Option<NonZeroUsize>,
+}
+
+fn parse_timings(path: &str) -> Result<FrameTimings, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+
+    let mut timings = FrameTimings::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = line.split_once(',').and_then(|(frame, duration)| {
+            Some((
+                frame.trim().parse::<usize>().ok()?,
+                duration.trim().parse::<u32>().ok()?,
+            ))
+        });
+
+        match parsed {
+            Some((frame, duration)) => {
+                timings.insert(frame, duration);
+            }
+            // optional header
+            None if idx == 0 && !line.starts_with(|c: char| c.is_ascii_digit()) => {}
+            None => return Err(format!("{path}: invalid line {}: {line}", idx + 1)),
+        }
+    }
+
+    Ok(timings)
 }
 
 impl std::ops::Deref for GifArgs {
@@ -31,11 +123,24 @@ impl std::ops::Deref for GifArgs {
     }
 }
 
+impl std::ops::DerefMut for GifArgs {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shared
+    }
+}
+
+/// Generate a gif preview of the source frames.
+///
+/// # Errors
+///
+/// Fails when the frames can't be loaded or the gif can't be encoded or written.
 pub fn generate_gif(args: &GifArgs) -> Result<(), CommandError> {
-    use image::{codecs::gif, Delay, Frame};
+    if args.check {
+        crate::check::enable();
+    }
 
-    if args.lua {
-        warn!("lua output is not supported for gifs");
+    if args.lua || args.json || args.toml || args.yaml || args.template.is_some() {
+        warn!("data file output is not supported for gifs");
     }
 
     if args.animation_speed <= 0.0 {
@@ -43,43 +148,299 @@ pub fn generate_gif(args: &GifArgs) -> Result<(), CommandError> {
         return Ok(());
     }
 
-    let mut images = image_util::load_from_path(&args.source)?;
+    let name = folder_name(&args.source)?;
+    let lock = args.lock_sources(&[(name.clone(), args.source.as_path())], false)?;
+
+    let mut images = image_util::load_from_path(&args.source, &args.input_formats())?;
 
     if images.is_empty() {
         warn!("no source images found");
         return Ok(());
     }
 
-    for img in &mut images {
+    if args.shadow.is_some() || args.glow.is_some() {
+        let shadow = load_layer(args, args.shadow.as_deref(), "shadow", images.len())?;
+        let glow = load_layer(args, args.glow.as_deref(), "glow", images.len())?;
+        images = composite_layers(&images, &shadow, &glow);
+    }
+
+    if let Some(range) = args.onion_skin {
+        images = onion_skin(&images, range.get());
+    }
+
+    let no_timings = FrameTimings::new();
+    let timings = args.timings.as_ref().unwrap_or(&no_timings);
+    if let Some((&frame, _)) = timings.last_key_value() {
+        if frame >= images.len() {
+            Err(GifError::TimingFrameOutOfRange(frame, images.len()))?;
+        }
+    }
+
+    let path = output_name(&args.source, &args.output, None, &args.prefix, ".gif")?;
+    let Some(max_size) = args.max_size else {
+        save_gif(
+            &images,
+            path,
+            args.animation_speed,
+            args.alpha_threshold,
+            timings,
+        )?;
+        return save_lock(lock, &name);
+    };
+
+    let mut smallest = u64::MAX;
+    for scale in MAX_SIZE_SCALES {
+        let scaled = if scale < 1.0 {
+            images
+                .iter()
+                .map(|image| {
+                    let width = ((f64::from(image.width()) * scale).round() as u32).max(1);
+                    let height = ((f64::from(image.height()) * scale).round() as u32).max(1);
+                    imageops::resize(image, width, height, imageops::FilterType::Triangle)
+                })
+                .collect()
+        } else {
+            images.clone()
+        };
+
+        for bits in MAX_SIZE_COLOR_BITS {
+            let mut frames = scaled.clone();
+            if bits < 8 {
+                for frame in &mut frames {
+                    posterize(frame, bits);
+                }
+            }
+
+            let data = encode_gif(&frames, args.animation_speed, args.alpha_threshold, timings)?;
+            let size = data.len() as u64;
+            debug!(
+                "scale {scale}, {bits} bit colors: {}",
+                human_readable_bytes(size)
+            );
+
+            if size <= max_size {
+                info!(
+                    "gif fits into {} at scale {scale} with {bits} bits per color channel: {}",
+                    human_readable_bytes(max_size),
+                    human_readable_bytes(size)
+                );
+                write_gif(&path, &data)?;
+                return save_lock(lock, &name);
+            }
+
+            smallest = smallest.min(size);
+        }
+    }
+
+    Err(GifError::MaxSizeUnreachable(
+        human_readable_bytes(max_size),
+        human_readable_bytes(smallest),
+    ))?
+}
+
+/// Reduce every color channel to `bits` bits, rounded to the nearest remaining level.
+fn posterize(image: &mut RgbaImage, bits: u8) {
+    let levels = (1u32 << bits) - 1;
+    for pxl in image.pixels_mut() {
+        for channel in &mut pxl.0[..3] {
+            let level = (u32::from(*channel) * levels + 127) / 255;
+            *channel = (level * 255 / levels) as u8;
+        }
+    }
+}
+
+/// Frames of an additional layer, a single frame is used for the whole animation.
+fn load_layer(
+    args: &GifArgs,
+    path: Option<&Path>,
+    name: &'static str,
+    frame_count: usize,
+) -> Result<Vec<RgbaImage>, CommandError> {
+    let Some(path) = path else {
+        return Ok(Vec::new());
+    };
+
+    let frames = image_util::load_from_path(path, &args.input_formats())?;
+    if frames.len() != 1 && frames.len() != frame_count {
+        Err(GifError::LayerFrameCount(name, frames.len(), frame_count))?;
+    }
+
+    Ok(frames)
+}
+
+/// Composite every frame like it is drawn in-game: the shadow multiplied below and the glow added on top.
+///
+/// All layers are centered on a canvas large enough for each of them, so differently sized
+/// layers (like the grown shadow frames) keep their shift.
+fn composite_layers(
+    base: &[RgbaImage],
+    shadow: &[RgbaImage],
+    glow: &[RgbaImage],
+) -> Vec<RgbaImage> {
+    let (width, height) = base
+        .iter()
+        .chain(shadow)
+        .chain(glow)
+        .fold((0, 0), |(width, height), frame| {
+            (width.max(frame.width()), height.max(frame.height()))
+        });
+
+    let offset = |frame: &RgbaImage| ((width - frame.width()) / 2, (height - frame.height()) / 2);
+
+    base.iter()
+        .enumerate()
+        .map(|(idx, frame)| {
+            let mut canvas = RgbaImage::new(width, height);
+
+            if let Some(shadow) = layer_frame(shadow, idx) {
+                let (x, y) = offset(shadow);
+                let mut darkened = shadow.clone();
+                for pxl in darkened.pixels_mut() {
+                    pxl.0[..3].fill(0);
+                }
+                imageops::replace(&mut canvas, &darkened, i64::from(x), i64::from(y));
+            }
+
+            let (x, y) = offset(frame);
+            imageops::overlay(&mut canvas, frame, i64::from(x), i64::from(y));
+
+            if let Some(glow) = layer_frame(glow, idx) {
+                let (offset_x, offset_y) = offset(glow);
+                for (x, y, glow) in glow.enumerate_pixels() {
+                    let pxl = canvas.get_pixel_mut(x + offset_x, y + offset_y);
+                    let strength = u16::from(glow[3]);
+                    for channel in 0..3 {
+                        let added = u16::from(glow[channel]) * strength / 255;
+                        pxl[channel] = (u16::from(pxl[channel]) + added).min(255) as u8;
+                    }
+                    pxl[3] = pxl[3].max(glow[3]);
+                }
+            }
+
+            canvas
+        })
+        .collect()
+}
+
+/// Draw tinted ghosts of the `range` previous and next frames below every frame.
+///
+/// The animation is treated as loop, so the first frame shows the last ones as previous frames.
+/// Ghosts fade out with their distance to the frame.
+pub(super) fn onion_skin(frames: &[RgbaImage], range: usize) -> Vec<RgbaImage> {
+    // every neighbor is drawn at most once
+    let range = range.min(frames.len() / 2);
+    let (width, height) = frames.iter().fold((0, 0), |(width, height), frame| {
+        (width.max(frame.width()), height.max(frame.height()))
+    });
+    let offset = |frame: &RgbaImage| {
+        (
+            i64::from((width - frame.width()) / 2),
+            i64::from((height - frame.height()) / 2),
+        )
+    };
+
+    (0..frames.len())
+        .map(|idx| {
+            let mut canvas = RgbaImage::new(width, height);
+
+            for distance in (1..=range).rev() {
+                let opacity =
+                    ONION_SKIN_OPACITY * (range + 1 - distance) as f64 / (range + 1) as f64;
+                let neighbors = [
+                    (idx + frames.len() - distance) % frames.len(),
+                    (idx + distance) % frames.len(),
+                ];
+                // the opposite frame of an even count is both the previous and the next one
+                let sides = if distance * 2 == frames.len() { 1 } else { 2 };
+
+                for (neighbor, tint) in neighbors.into_iter().zip(ONION_SKIN_TINTS).take(sides) {
+                    let mut ghost = frames[neighbor].clone();
+                    for pxl in ghost.pixels_mut() {
+                        for (channel, tint) in pxl.0[..3].iter_mut().zip(tint) {
+                            *channel = channel.midpoint(tint);
+                        }
+                        pxl[3] = (f64::from(pxl[3]) * opacity).round() as u8;
+                    }
+
+                    let (x, y) = offset(&ghost);
+                    imageops::overlay(&mut canvas, &ghost, x, y);
+                }
+            }
+
+            let frame = &frames[idx];
+            let (x, y) = offset(frame);
+            imageops::overlay(&mut canvas, frame, x, y);
+            canvas
+        })
+        .collect()
+}
+
+/// Frame `idx` of a layer, layers with a single frame use it for every frame.
+fn layer_frame(layer: &[RgbaImage], idx: usize) -> Option<&RgbaImage> {
+    layer.get(idx).or_else(|| layer.first())
+}
+
+/// Encode the frames as an infinitely looping gif.
+///
+/// `animation_speed` is identical to the in-game speed, pixels with an alpha value
+/// at or below `alpha_threshold` become fully transparent.
+/// Frames listed in `timings` use their own duration instead.
+///
+/// # Errors
+///
+/// Fails when the gif can't be encoded or written.
+pub fn save_gif(
+    images: &[RgbaImage],
+    path: impl AsRef<Path>,
+    animation_speed: f64,
+    alpha_threshold: u8,
+    timings: &FrameTimings,
+) -> Result<(), CommandError> {
+    write_gif(
+        path.as_ref(),
+        &encode_gif(images, animation_speed, alpha_threshold, timings)?,
+    )?;
+    Ok(())
+}
+
+/// Write an encoded gif, with `--check` it is compared with the existing file instead.
+fn write_gif(path: &Path, data: &[u8]) -> Result<(), CommandError> {
+    crate::check::write(path, data).map_err(output_error(path))
+}
+
+/// Encode the frames like [`save_gif`] into memory.
+fn encode_gif(
+    images: &[RgbaImage],
+    animation_speed: f64,
+    alpha_threshold: u8,
+    timings: &FrameTimings,
+) -> Result<Vec<u8>, CommandError> {
+    use image::{codecs::gif, Delay, Frame};
+
+    let mut data = Vec::new();
+
+    let mut encoder = gif::GifEncoder::new(&mut data);
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    encoder.try_encode_frames(images.iter().enumerate().map(|(idx, img)| {
+        let mut img = img.clone();
         for pxl in img.pixels_mut() {
-            if pxl[3] <= 10 {
+            if pxl[3] <= alpha_threshold {
                 pxl[0] = 0;
                 pxl[1] = 0;
                 pxl[2] = 0;
                 pxl[3] = 0;
             }
         }
-    }
 
-    let mut file = fs::File::create(output_name(
-        &args.source,
-        &args.output,
-        None,
-        &args.prefix,
-        ".gif",
-    )?)?;
+        let delay = timings.get(&idx).map_or_else(
+            || Delay::from_numer_denom_ms(100_000, (6000.0 * animation_speed).round() as u32),
+            |&duration| Delay::from_numer_denom_ms(duration, 1),
+        );
 
-    let mut encoder = gif::GifEncoder::new(&mut file);
-    encoder.set_repeat(gif::Repeat::Infinite)?;
-
-    encoder.try_encode_frames(images.iter().map(|img| {
-        Ok(Frame::from_parts(
-            img.clone(),
-            0,
-            0,
-            Delay::from_numer_denom_ms(100_000, (6000.0 * args.animation_speed).round() as u32),
-        ))
+        Ok(Frame::from_parts(img, 0, 0, delay))
     }))?;
+    drop(encoder);
 
-    Ok(())
+    Ok(data)
 }
@@ -1,13 +1,10 @@
-use std::fs;
-
 use clap::Args;
-use image::ImageBuffer;
+use image::{imageops, ImageBuffer, RgbaImage};
 
-use super::{output_name, CommandError};
-use crate::{
-    image_util::{self, ImageBufferExt as _},
-    lua::LuaOutput,
+use super::{
+    create_output_dir, folder_name, output_name, save_lock, spritesheet::MAX_SIZE, CommandError,
 };
+use crate::{image_util, lua::LuaOutput};
 
 #[derive(Debug, thiserror::Error)]
 pub enum IconError {
@@ -22,6 +19,42 @@ pub enum IconError {
 
     #[error("source image has wrong size, {0} != {1}")]
     WrongImageSize(u32, u32),
+
+    #[error("icon strip would be {0}px wide, the limit is {max}px", max = MAX_SIZE)]
+    StripTooWide(u32),
+
+    #[error("frame {0} does not exist, the animation has {1} frames")]
+    FrameOutOfRange(usize, usize),
+}
+
+/// Frame of an animation to derive an icon from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSelection {
+    First,
+    Frame(usize),
+
+    /// Frame with the highest average luma of its visible pixels.
+    Brightest,
+}
+
+impl std::str::FromStr for FrameSelection {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "first" => Ok(Self::First),
+            "brightest" => Ok(Self::Brightest),
+            _ => value
+                .strip_prefix("frame:")
+                .and_then(|idx| idx.parse().ok())
+                .map(Self::Frame)
+                .ok_or_else(|| {
+                    format!(
+                        "invalid frame selection: {value}, expected first, frame:N or brightest"
+                    )
+                }),
+        }
+    }
 }
 
 #[derive(Args, Debug)]
@@ -29,8 +62,44 @@ pub struct IconArgs {
     // shared args
     #[clap(flatten)]
     shared: super::SharedArgs,
+
+    /// Pack all equally sized source icons into a single horizontal strip instead of generating mipmaps.
+    /// The data output maps every icon name (file name without extension) to its index and x / y position.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub icon_strip: bool,
+
+    /// Treat the source as an animation and derive the icon from one of its frames: `first`, `frame:N` or `brightest`.
+    /// The frame is cropped to a centered square, scaled to `--icon-size` and the mipmaps are generated from it.
+    #[clap(
+        long,
+        value_name = "FRAME",
+        conflicts_with = "icon_strip",
+        verbatim_doc_comment
+    )]
+    pub from_animation: Option<FrameSelection>,
+
+    /// Size of the largest mipmap level when deriving the icon from an animation.
+    #[clap(long, default_value_t = 64, requires = "from_animation", value_parser = clap::value_parser!(u32).range(2..=i64::from(MAX_SIZE)))]
+    pub icon_size: u32,
+
+    /// Amount of mipmap levels to generate when deriving the icon from an animation.
+    #[clap(long, default_value_t = 4, requires = "from_animation", value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub mipmap_levels: u32,
+
+    /// Report icons (or mipmap levels) whose edges are hard to see on the typical GUI backgrounds,
+    /// a dark inventory slot and the map view.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub contrast_check: bool,
+
+    /// Minimum average contrast ratio of the icon edges against the backgrounds [1.0-21.0].
+    #[clap(long, default_value_t = 1.5, requires = "contrast_check")]
+    pub contrast_threshold: f64,
 }
 
+/// Typical backgrounds icons are shown on.
+const BACKGROUNDS: [(&str, [u8; 3]); 2] =
+    [("inventory slot", [49, 48, 49]), ("map view", [40, 44, 36])];
+
 impl std::ops::Deref for IconArgs {
     type Target = super::SharedArgs;
 
@@ -39,23 +108,52 @@ impl std::ops::Deref for IconArgs {
     }
 }
 
+impl std::ops::DerefMut for IconArgs {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shared
+    }
+}
+
+/// Generate a mipmapped icon from the source images.
+///
+/// # Errors
+///
+/// Fails when the images can't be loaded, aren't square, can't be halved into the mipmap levels
+/// or the icon can't be written.
 pub fn generate_mipmap_icon(args: &IconArgs) -> Result<(), CommandError> {
-    fs::create_dir_all(&args.output)?;
-    if !args.output.is_dir() {
-        return Err(CommandError::OutputPathNotDir);
+    if args.check {
+        crate::check::enable();
+    }
+
+    create_output_dir(&args.output)?;
+
+    let name = folder_name(&args.source)?;
+    let lock = args.lock_sources(&[(name.clone(), &args.source)], false)?;
+
+    if args.icon_strip {
+        generate_icon_strip(args)?;
+        return save_lock(lock, &name);
     }
 
-    let mut images = image_util::load_from_path(&args.source)?;
+    let mut images = image_util::load_from_path(&args.source, &args.input_formats())?;
     if images.is_empty() {
         warn!("no source images found");
         return Ok(());
     }
 
+    let provenance = args.provenance(&args.source, &images);
+
+    if let Some(selection) = args.from_animation {
+        images = mipmaps_from_animation(&images, selection, args.icon_size, args.mipmap_levels)?;
+    }
+
     images.sort_by_key(ImageBuffer::width);
     images.reverse();
 
-    #[allow(clippy::unwrap_used)]
-    let (base_width, base_height) = images.first().unwrap().dimensions();
+    let Some((base_width, base_height)) = images.first().map(ImageBuffer::dimensions) else {
+        warn!("no source images found");
+        return Ok(());
+    };
     if base_width != base_height {
         Err(IconError::ImageNotSquare)?;
     }
@@ -91,25 +189,253 @@ pub fn generate_mipmap_icon(args: &IconArgs) -> Result<(), CommandError> {
         next_width /= 2;
     }
 
-    image::imageops::crop_imm(&res, 0, 0, next_x, res.height())
-        .to_image()
-        .save_optimized_png(
-            output_name(&args.source, &args.output, None, &args.prefix, "png")?,
-            args.lossy,
-        )?;
+    if args.contrast_check {
+        let name = folder_name(&args.source)?;
+        let passed = images.iter().fold(true, |passed, mip| {
+            let label = format!("{name} ({}px)", mip.width());
+            check_contrast(&label, mip, args.contrast_threshold) && passed
+        });
 
-    if args.lua {
-        LuaOutput::new()
+        if passed {
+            info!("all mipmap levels passed the contrast check");
+        }
+    }
+
+    let out = output_name(&args.source, &args.output, None, &args.prefix, "png")?;
+    let icon = image::imageops::crop_imm(&res, 0, 0, next_x, res.height()).to_image();
+    image_util::save_sheets(
+        &[(icon, out.clone())],
+        args.lossy,
+        true,
+        args.encode.options(),
+        &args.palette(),
+    )?;
+
+    args.save_data(
+        &name,
+        &LuaOutput::new()
             .set("icon_size", base_width)
             .set("icon_mipmaps", images.len())
-            .save(output_name(
-                &args.source,
-                &args.output,
-                None,
-                &args.prefix,
-                "lua",
-            )?)?;
+            .set_opt("provenance", provenance),
+        &[out],
+    )?;
+
+    save_lock(lock, &name)
+}
+
+/// Pick a frame of the animation, crop it to a centered square around its visible pixels
+/// and scale it down to all mipmap levels, largest first.
+fn mipmaps_from_animation(
+    frames: &[RgbaImage],
+    selection: FrameSelection,
+    size: u32,
+    levels: u32,
+) -> Result<Vec<RgbaImage>, CommandError> {
+    let idx = match selection {
+        FrameSelection::First => 0,
+        FrameSelection::Frame(idx) if idx >= frames.len() => {
+            Err(IconError::FrameOutOfRange(idx, frames.len()))?
+        }
+        FrameSelection::Frame(idx) => idx,
+        FrameSelection::Brightest => frames
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| visible_luma(a).total_cmp(&visible_luma(b)))
+            .map_or(0, |(idx, _)| idx),
+    };
+    debug!("deriving the icon from frame {idx}");
+
+    let frame = &frames[idx];
+    let (min_x, min_y, max_x, max_y) =
+        image_util::alpha_bounds(frame, 0).ok_or(image_util::ImgUtilError::AllImagesEmpty)?;
+
+    // keep the visible pixels centered in a square canvas
+    let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+    let square_size = width.max(height);
+    let mut square = RgbaImage::new(square_size, square_size);
+    imageops::replace(
+        &mut square,
+        &imageops::crop_imm(frame, min_x, min_y, width, height).to_image(),
+        i64::from((square_size - width) / 2),
+        i64::from((square_size - height) / 2),
+    );
+
+    Ok((0..levels)
+        .map_while(|level| {
+            let level_size = size.checked_shr(level).filter(|&size| size > 0)?;
+            Some(imageops::resize(
+                &square,
+                level_size,
+                level_size,
+                imageops::FilterType::Lanczos3,
+            ))
+        })
+        .collect())
+}
+
+/// Composite `icon` over every background and warn if the average contrast ratio of its edge pixels is below `threshold`.
+fn check_contrast(label: &str, icon: &RgbaImage, threshold: f64) -> bool {
+    let (width, height) = icon.dimensions();
+    let visible = |x: u32, y: u32| icon.get_pixel(x, y)[3] > 127;
+
+    // visible pixels next to a hidden pixel or the border of the icon
+    let edges = icon
+        .enumerate_pixels()
+        .filter(|&(x, y, _)| {
+            visible(x, y)
+                && (x == 0
+                    || y == 0
+                    || x == width - 1
+                    || y == height - 1
+                    || !visible(x - 1, y)
+                    || !visible(x + 1, y)
+                    || !visible(x, y - 1)
+                    || !visible(x, y + 1))
+        })
+        .map(|(_, _, pxl)| pxl)
+        .collect::<Vec<_>>();
+
+    if edges.is_empty() {
+        return true;
+    }
+
+    let mut passed = true;
+    for (background_name, background) in BACKGROUNDS {
+        let background_luminance = relative_luminance(background.map(f64::from));
+        let contrast = edges
+            .iter()
+            .map(|pxl| {
+                let alpha = f64::from(pxl[3]) / 255.0;
+                let color = std::array::from_fn(|channel| {
+                    f64::from(pxl[channel])
+                        .mul_add(alpha, f64::from(background[channel]) * (1.0 - alpha))
+                });
+                let luminance = relative_luminance(color);
+                (luminance.max(background_luminance) + 0.05)
+                    / (luminance.min(background_luminance) + 0.05)
+            })
+            .sum::<f64>()
+            / edges.len() as f64;
+
+        if contrast < threshold {
+            warn!("{label}: edge contrast of {contrast:.2} on the {background_name} background is below {threshold}");
+            passed = false;
+        }
+    }
+
+    passed
+}
+
+/// WCAG relative luminance of an sRGB color with channels in 0-255.
+fn relative_luminance(color: [f64; 3]) -> f64 {
+    let linear = color.map(|channel| {
+        let channel = channel / 255.0;
+        if channel <= 0.039_28 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    0.0722f64.mul_add(linear[2], 0.2126f64.mul_add(linear[0], 0.7152 * linear[1]))
+}
+
+/// Average luma of the pixels, weighted by their alpha.
+fn visible_luma(image: &RgbaImage) -> f64 {
+    let (sum, weight) = image.pixels().fold((0.0, 0.0), |(sum, weight), pxl| {
+        let alpha = f64::from(pxl[3]);
+        let luma = 0.0722f64.mul_add(
+            f64::from(pxl[2]),
+            0.2126f64.mul_add(f64::from(pxl[0]), 0.7152 * f64::from(pxl[1])),
+        );
+        (luma.mul_add(alpha, sum), weight + alpha)
+    });
+
+    if weight > 0.0 {
+        sum / weight
+    } else {
+        0.0
     }
+}
+
+/// Pack the source icons side by side into one sheet with a lookup table of their positions.
+fn generate_icon_strip(args: &IconArgs) -> Result<(), CommandError> {
+    let icons = image_util::load_from_path_with_path(&args.source, &args.input_formats())?;
+    let Some((first, _)) = icons.first() else {
+        warn!("no source images found");
+        return Ok(());
+    };
+    let (icon_width, icon_height) = first.dimensions();
+
+    if icons
+        .iter()
+        .any(|(icon, _)| icon.dimensions() != (icon_width, icon_height))
+    {
+        return Err(image_util::ImgUtilError::NotSameSize.into());
+    }
+
+    let width = u32::try_from(icons.len())
+        .ok()
+        .and_then(|count| count.checked_mul(icon_width))
+        .unwrap_or(u32::MAX);
+    if width > MAX_SIZE {
+        Err(IconError::StripTooWide(width))?;
+    }
+
+    let mut provenance = args.provenance_hasher();
+    let mut passed = true;
+    let mut strip = RgbaImage::new(width, icon_height);
+    let mut lookup = LuaOutput::new();
+    for (idx, (icon, path)) in icons.iter().enumerate() {
+        let x = idx as u32 * icon_width;
+        image::imageops::replace(&mut strip, icon, i64::from(x), 0);
+        if let Some(provenance) = &mut provenance {
+            provenance.add(icon);
+        }
+
+        #[allow(clippy::unwrap_used)]
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        if args.contrast_check {
+            passed &= check_contrast(&name, icon, args.contrast_threshold);
+        }
+
+        lookup = lookup.set(
+            name,
+            LuaOutput::new().set("index", idx).set("x", x).set("y", 0),
+        );
+    }
+
+    if args.contrast_check && passed {
+        info!("all icons passed the contrast check");
+    }
+
+    let out = output_name(&args.source, &args.output, None, &args.prefix, "png")?;
+    image_util::save_sheets(
+        &[(strip, out.clone())],
+        args.lossy,
+        true,
+        args.encode.options(),
+        &args.palette(),
+    )?;
+
+    args.save_data(
+        &folder_name(&args.source)?,
+        &LuaOutput::new()
+            .set("width", icon_width)
+            .set("height", icon_height)
+            .set("icon_count", icons.len())
+            .set("icons", lookup)
+            .set_opt(
+                "provenance",
+                provenance.map(|provenance| provenance.finish(&args.source)),
+            ),
+        &[out],
+    )?;
+
+    info!(
+        "packed {} icons into a {width}x{icon_height}px strip",
+        icons.len()
+    );
 
     Ok(())
 }
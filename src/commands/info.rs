@@ -0,0 +1,238 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::ImageFormat;
+
+use super::{human_readable_bytes, CommandError};
+use crate::{
+    image_util,
+    lua::{LuaOutput, LuaValue},
+};
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// Image, spritter data file (lua or json) or folder containing them.
+    pub path: PathBuf,
+}
+
+/// Print details about images and spritter data files to stdout.
+///
+/// # Errors
+///
+/// Fails when the path doesn't exist or an image or data file can't be read.
+pub fn info(args: &InfoArgs) -> Result<(), CommandError> {
+    if !args.path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("path not found: {}", args.path.display()),
+        )
+        .into());
+    }
+
+    if args.path.is_file() {
+        let report = if is_data_file(&args.path) {
+            data_info(&args.path)?
+        } else {
+            image_info(&args.path)?.0
+        };
+
+        print!("{report}");
+        return Ok(());
+    }
+
+    let mut files = fs::read_dir(&args.path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.retain(|path| path.is_file());
+    files.sort_by(|a, b| natord::compare(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    let mut images = 0;
+    let mut vram = 0;
+    for path in files {
+        if is_data_file(&path) {
+            // other lua / json files are silently skipped
+            if let Ok(report) = data_info(&path) {
+                println!("{report}");
+            }
+        } else if ImageFormat::from_path(&path).is_ok_and(|format| format.reading_enabled()) {
+            let (report, size) = image_info(&path)?;
+            println!("{report}");
+            images += 1;
+            vram += size;
+        }
+    }
+
+    println!(
+        "{images} images, estimated VRAM usage: {}",
+        human_readable_bytes(vram)
+    );
+
+    Ok(())
+}
+
+fn is_data_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "lua" || ext == "json")
+}
+
+/// Details of an image and its estimated VRAM usage in bytes.
+fn image_info(path: &Path) -> Result<(String, u64), CommandError> {
+    let image = image_util::load_image_from_file(path)?;
+    let (width, height) = image.dimensions();
+
+    let format = if ImageFormat::from_path(path).ok() == Some(ImageFormat::Png) {
+        let reader = ::png::Decoder::new(fs::File::open(path)?)
+            .read_info()
+            .map_err(image_util::ImgUtilError::from)?;
+        let info = reader.info();
+        let depth = info.bit_depth as u8;
+
+        match (info.color_type, &info.palette) {
+            (::png::ColorType::Indexed, Some(palette)) => {
+                format!("png, {depth} bit indexed, {} colors", palette.len() / 3)
+            }
+            (color, _) => format!("png, {depth} bit {}", color_name(color)),
+        }
+    } else {
+        let format = ImageFormat::from_path(path).map_or_else(
+            |_| "unknown".to_owned(),
+            |format| format!("{format:?}").to_lowercase(),
+        );
+        let color = image::open(path)?.color();
+        format!(
+            "{format}, {} bit {color:?}",
+            color.bits_per_pixel() / u16::from(color.channel_count())
+        )
+    };
+
+    let pixels = image.pixels().len().max(1);
+    let transparent = image.pixels().filter(|pxl| pxl[3] == 0).count();
+    let translucent = image
+        .pixels()
+        .filter(|pxl| pxl[3] != 0 && pxl[3] != 255)
+        .count();
+    let alpha = if transparent == 0 && translucent == 0 {
+        "opaque"
+    } else if translucent == 0 {
+        "binary"
+    } else {
+        "translucent"
+    };
+
+    let vram = image_util::vram_size(width, height);
+
+    let mut report = format!("{}\n", path.display());
+    let _ = writeln!(report, "  size:   {width}x{height}px");
+    let _ = writeln!(report, "  format: {format}");
+    let _ = writeln!(
+        report,
+        "  alpha:  {alpha}, {:.2}% transparent, {:.2}% translucent",
+        transparent as f64 * 100.0 / pixels as f64,
+        translucent as f64 * 100.0 / pixels as f64
+    );
+    let _ = writeln!(report, "  vram:   {}", human_readable_bytes(vram));
+
+    Ok((report, vram))
+}
+
+const fn color_name(color: ::png::ColorType) -> &'static str {
+    match color {
+        ::png::ColorType::Grayscale => "grayscale",
+        ::png::ColorType::GrayscaleAlpha => "grayscale alpha",
+        ::png::ColorType::Rgb => "rgb",
+        ::png::ColorType::Rgba => "rgba",
+        ::png::ColorType::Indexed => "indexed",
+    }
+}
+
+/// Decoded summary of a spritter data file.
+fn data_info(path: &Path) -> Result<String, CommandError> {
+    let data = if path.extension().is_some_and(|ext| ext == "json") {
+        LuaOutput::load_json(path)
+    } else {
+        LuaOutput::load(path)
+    }?;
+
+    let int = |key: &str| data.get(key).and_then(LuaValue::as_int);
+    let mut report = format!("{}\n", path.display());
+
+    if let Some(version) = int("format_version") {
+        let _ = writeln!(report, "  format:   v{version}");
+    }
+    if let Some(kind) = data.get("type").and_then(LuaValue::as_str) {
+        let _ = writeln!(report, "  type:     {kind}");
+    }
+
+    if let Some(icon_size) = int("icon_size") {
+        let _ = writeln!(
+            report,
+            "  icon:     {icon_size}px, {} mipmap levels",
+            int("icon_mipmaps").unwrap_or(1)
+        );
+    }
+
+    if let (Some(width), Some(height)) = (int("width"), int("height")) {
+        let _ = writeln!(report, "  sprite:   {width}x{height}px");
+    }
+    if let Some(scale) = data.get("scale").and_then(LuaValue::as_float) {
+        let _ = writeln!(report, "  scale:    {scale}");
+    }
+    if let Some(shift) = data.get("shift") {
+        let _ = writeln!(report, "  shift:    {} tiles", describe(shift));
+    }
+
+    if let (Some(line_length), Some(lines)) = (int("line_length"), int("lines_per_file")) {
+        let _ = writeln!(
+            report,
+            "  layout:   {line_length}x{lines} per sheet, {} sheets",
+            int("file_count").unwrap_or(1)
+        );
+    }
+
+    let frames = int("frame_count").or_else(|| int("sprite_count"));
+    if let Some(frames) = frames {
+        let _ = writeln!(
+            report,
+            "  frames:   {frames}, {} directions",
+            int("direction_count").unwrap_or(1)
+        );
+    }
+    if let Some(sequence) = data.get("frame_sequence").and_then(LuaValue::as_array) {
+        let _ = writeln!(report, "  sequence: {} frames", sequence.len());
+    }
+
+    if let Some(layers) = data
+        .get("single_sheet_split_layers")
+        .and_then(LuaValue::as_array)
+    {
+        let _ = writeln!(
+            report,
+            "  layers:   {} single sheet split layers",
+            layers.len()
+        );
+    }
+
+    Ok(report)
+}
+
+fn describe(value: &LuaValue) -> String {
+    match value {
+        LuaValue::String(value) => value.clone(),
+        LuaValue::Float(value) => value.to_string(),
+        LuaValue::Int(value) => value.to_string(),
+        LuaValue::Bool(value) => value.to_string(),
+        LuaValue::Shift(x, y, res) => format!("({}, {})", x / *res as f64, y / *res as f64),
+        LuaValue::Array(values) => format!(
+            "({})",
+            values.iter().map(describe).collect::<Vec<_>>().join(", ")
+        ),
+        LuaValue::Table(table) => match (table.get("x"), table.get("y")) {
+            (Some(x), Some(y)) => format!("({}, {})", describe(x), describe(y)),
+            _ => "{...}".to_owned(),
+        },
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::ImageFormat;
+use strum::VariantArray as _;
+
+use super::{output_error, recursive_folders, CommandError, InputFormat};
+use crate::{image_util, summary};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("{0} already exists, use --force to overwrite it")]
+    ConfigExists(PathBuf),
+}
+
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// Graphics folder to inspect, all subfolders are scanned.
+    #[clap(default_value = ".")]
+    pub source: PathBuf,
+
+    /// Path of the generated config file.
+    #[clap(short, long, default_value = "spritter.toml")]
+    pub output: PathBuf,
+
+    /// Overwrite an existing config file.
+    #[clap(long, action)]
+    pub force: bool,
+
+    /// Follow symlinked folders.
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+}
+
+/// Folder with at least two equally sized images.
+struct AnimationFolder {
+    path: PathBuf,
+    frames: usize,
+    size: (u32, u32),
+}
+
+/// Write a starter project config with the detected source formats and animation folders.
+///
+/// # Errors
+///
+/// Fails when the config already exists without `--force` or can't be written.
+pub fn init(args: &InitArgs) -> Result<(), CommandError> {
+    if args.output.exists() && !args.force {
+        Err(InitError::ConfigExists(args.output.clone()))?;
+    }
+
+    let mut visited = HashSet::from([args.source.canonicalize()?]);
+    let mut folders = vec![args.source.clone()];
+    folders.extend(recursive_folders(
+        &args.source,
+        args.follow_symlinks,
+        &mut visited,
+    )?);
+
+    let formats = InputFormat::VARIANTS
+        .iter()
+        .map(|&format| format.into())
+        .collect::<Vec<ImageFormat>>();
+
+    let mut used_formats = HashSet::new();
+    let mut animations = Vec::new();
+    for folder in folders {
+        let paths = image_util::image_paths(&folder, &formats)?;
+        let sizes = paths
+            .iter()
+            .filter_map(|path| {
+                let format = ImageFormat::from_path(path).ok()?;
+                let size = image::image_dimensions(path).ok()?;
+                Some((format, size))
+            })
+            .collect::<Vec<_>>();
+
+        used_formats.extend(sizes.iter().map(|&(format, _)| format));
+
+        let Some(&(_, size)) = sizes.first() else {
+            continue;
+        };
+
+        if sizes.len() < 2 {
+            continue;
+        }
+
+        if sizes.iter().any(|&(_, other)| other != size) {
+            debug!(
+                "{}: images differ in size, not an animation",
+                folder.display()
+            );
+            continue;
+        }
+
+        animations.push(AnimationFolder {
+            path: folder,
+            frames: sizes.len(),
+            size,
+        });
+    }
+
+    let used_formats = InputFormat::VARIANTS
+        .iter()
+        .copied()
+        .filter(|&format| used_formats.contains(&ImageFormat::from(format)))
+        .collect::<Vec<_>>();
+    let config = render(&used_formats, &animations);
+
+    if let Some(parent) = args.output.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(output_error(parent))?;
+        }
+    }
+    fs::write(&args.output, config).map_err(output_error(&args.output))?;
+    summary::record_file(&args.output);
+
+    info!(
+        "detected {} animation folders, config saved to {}",
+        animations.len(),
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+fn render(formats: &[InputFormat], animations: &[AnimationFolder]) -> String {
+    let mut config = format!(
+        "# spritter project config, generated by `{} init`\n",
+        env!("CARGO_PKG_NAME")
+    );
+    config.push_str(
+        "# values are used as defaults, options given on the command line take precedence\n\n",
+    );
+
+    // png is the default, only list the formats when anything else was found
+    if formats.iter().any(|&format| format != InputFormat::Png) {
+        let formats = formats
+            .iter()
+            .map(|format| format!("\"{format}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(config, "input-formats = [{formats}]\n");
+    }
+
+    config.push_str("[spritesheet]\nlua = true\n");
+
+    if animations.is_empty() {
+        return config;
+    }
+
+    // the sheets are saved next to their source folder, run them with `spritter run`
+    for animation in animations {
+        let output = animation.path.parent().unwrap_or_else(|| Path::new("."));
+        let _ = writeln!(
+            config,
+            "\n# {} frames of {}x{}px\n[[jobs]]\ncommand = \"spritesheet\"\nsource = {}\noutput = {}",
+            animation.frames,
+            animation.size.0,
+            animation.size.1,
+            toml_string(&animation.path),
+            toml_string(output)
+        );
+    }
+
+    config
+}
+
+fn toml_string(path: &Path) -> toml::Value {
+    let path = path.display().to_string().replace('\\', "/");
+    toml::Value::String(if path.is_empty() {
+        ".".to_owned()
+    } else {
+        path
+    })
+}
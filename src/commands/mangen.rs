@@ -0,0 +1,75 @@
+use std::{fmt::Write as _, fs, path::PathBuf};
+
+use clap::{Args, Command};
+
+use super::{create_output_dir, output_error, CommandError};
+
+#[derive(Args, Debug)]
+pub struct MangenArgs {
+    /// Output folder for the man pages and the markdown reference.
+    pub output: PathBuf,
+}
+
+/// Write a man page per (sub)command and `cli-reference.md` with the help of all commands.
+///
+/// # Errors
+///
+/// Fails when the pages can't be rendered or written.
+pub fn mangen(args: &MangenArgs, cmd: Command) -> Result<(), CommandError> {
+    create_output_dir(&args.output)?;
+
+    let mut cmd = cmd;
+    cmd.build();
+
+    let name = cmd.get_name().to_owned();
+    let mut pages = vec![(name.clone(), cmd.clone())];
+    pages.extend(
+        cmd.get_subcommands()
+            .filter(|sub| documented(sub))
+            .map(|sub| (format!("{name}-{}", sub.get_name()), sub.clone())),
+    );
+
+    for (page, sub) in &pages {
+        let mut buf = Vec::new();
+        clap_mangen::Man::new(sub.clone())
+            .title(page.to_uppercase())
+            .render(&mut buf)?;
+
+        let path = args.output.join(format!("{page}.1"));
+        fs::write(&path, buf).map_err(output_error(&path))?;
+        info!("wrote {}", path.display());
+    }
+
+    let path = args.output.join("cli-reference.md");
+    fs::write(&path, markdown(&mut cmd)).map_err(output_error(&path))?;
+    info!("wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Hidden commands and the generated `help` command are not documented separately.
+fn documented(cmd: &Command) -> bool {
+    !cmd.is_hide_set() && cmd.get_name() != "help"
+}
+
+/// Markdown reference with the long help of the command and all visible subcommands.
+fn markdown(cmd: &mut Command) -> String {
+    let name = cmd.get_name().to_owned();
+    let mut res = format!("# {name} CLI reference\n\n");
+
+    let help = cmd.render_long_help().to_string();
+    let _ = write!(res, "```\n~$ {name} help\n{}\n```\n", help.trim_end());
+
+    for sub in cmd.get_subcommands_mut().filter(|sub| documented(sub)) {
+        let sub_name = sub.get_name().to_owned();
+        let help = sub.render_long_help().to_string();
+
+        let _ = write!(
+            res,
+            "\n## {sub_name}\n\n```\n~$ {name} help {sub_name}\n{}\n```\n",
+            help.trim_end()
+        );
+    }
+
+    res
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+
+use super::{output_error, output_image_error, recursive_folders, CommandError, InputFormat};
+use crate::{image_util, summary};
+
+#[derive(Args, Debug)]
+pub struct MontageArgs {
+    /// Image or folder of images to put on the contact sheet.
+    pub source: PathBuf,
+
+    /// Path of the generated contact sheet png.
+    pub output: PathBuf,
+
+    /// Width and height of the thumbnail cells in pixels.
+    #[clap(long, default_value_t = 128, value_parser = clap::value_parser!(u32).range(16..=1024))]
+    pub thumb_size: u32,
+
+    /// Number of thumbnails per row [default: as square as possible]
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub columns: Option<u32>,
+
+    /// Include the images of all subfolders, e.g. to review all icons of a mod at once.
+    #[clap(short, long, action)]
+    pub recursive: bool,
+
+    /// Follow symlinked folders when searching recursively.
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+
+    /// Don't label the thumbnails with their file names.
+    #[clap(long, action)]
+    pub no_labels: bool,
+
+    /// Image formats to accept as source images, e.g. `png,tga`.
+    #[clap(long, value_delimiter = ',', default_values_t = [InputFormat::Png])]
+    input_formats: Vec<InputFormat>,
+}
+
+/// Space between and around the cells in pixels.
+const GAP: u32 = 4;
+
+/// Size of the squares of the transparency checkerboard.
+const CHECKER_SIZE: u32 = 8;
+
+const BACKGROUND: Rgba<u8> = Rgba([24, 24, 24, 255]);
+const CHECKER: [Rgba<u8>; 2] = [Rgba([48, 48, 48, 255]), Rgba([64, 64, 64, 255])];
+const LABEL_COLOR: Rgba<u8> = Rgba([230, 230, 230, 255]);
+
+/// Lay out thumbnails of all source images in a labeled grid for a quick visual review.
+///
+/// # Errors
+///
+/// Fails when the images can't be loaded or the montage can't be written.
+pub fn montage(args: &MontageArgs) -> Result<(), CommandError> {
+    let formats = args
+        .input_formats
+        .iter()
+        .map(|&format| format.into())
+        .collect::<Vec<ImageFormat>>();
+
+    let mut paths = image_util::image_paths(&args.source, &formats)?;
+    if args.recursive && args.source.is_dir() {
+        let mut visited = HashSet::from([args.source.canonicalize()?]);
+        for folder in recursive_folders(&args.source, args.follow_symlinks, &mut visited)? {
+            paths.extend(image_util::image_paths(&folder, &formats)?);
+        }
+    }
+
+    let mut entries = paths
+        .iter()
+        .map(|path| (label(&args.source, path), path))
+        .collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| natord::compare(a, b));
+
+    if entries.is_empty() {
+        warn!("no source images found");
+        return Ok(());
+    }
+
+    let thumbs = entries
+        .par_iter()
+        .map(|(_, path)| {
+            image_util::load_image_from_file(path).map(|image| thumbnail(&image, args.thumb_size))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let count = thumbs.len() as u32;
+    let columns = args
+        .columns
+        .unwrap_or_else(|| (f64::from(count).sqrt().ceil() as u32).max(1))
+        .min(count);
+    let rows = count.div_ceil(columns);
+
+    let scale = label_scale(args.thumb_size);
+    let label_height = if args.no_labels {
+        0
+    } else {
+        GLYPH_HEIGHT * scale + GAP
+    };
+    let cell = (args.thumb_size + GAP, args.thumb_size + label_height + GAP);
+
+    let mut sheet = RgbaImage::from_pixel(columns * cell.0 + GAP, rows * cell.1 + GAP, BACKGROUND);
+
+    for (idx, ((label, _), thumb)) in entries.iter().zip(&thumbs).enumerate() {
+        let idx = idx as u32;
+        let x = GAP + (idx % columns) * cell.0;
+        let y = GAP + (idx / columns) * cell.1;
+
+        // center the thumbnail on a checkerboard so transparent areas stay visible
+        let mut tile = RgbaImage::from_fn(args.thumb_size, args.thumb_size, |x, y| {
+            CHECKER[((x / CHECKER_SIZE + y / CHECKER_SIZE) % 2) as usize]
+        });
+        imageops::overlay(
+            &mut tile,
+            thumb,
+            i64::from((args.thumb_size - thumb.width()) / 2),
+            i64::from((args.thumb_size - thumb.height()) / 2),
+        );
+        imageops::replace(&mut sheet, &tile, i64::from(x), i64::from(y));
+
+        if !args.no_labels {
+            let max_chars = (args.thumb_size / (GLYPH_ADVANCE * scale)) as usize;
+            draw_text(
+                &mut sheet,
+                x,
+                y + args.thumb_size + GAP,
+                &truncate(label, max_chars),
+                scale,
+            );
+        }
+    }
+
+    if let Some(parent) = args.output.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(output_error(parent))?;
+        }
+    }
+    sheet
+        .save_with_format(&args.output, ImageFormat::Png)
+        .map_err(output_image_error(&args.output))?;
+    summary::record_file(&args.output);
+
+    info!(
+        "placed {count} images in a {columns}x{rows} grid, saved to {}",
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+/// Path of the image relative to the source without extension, with `/` separators.
+fn label(source: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(source).unwrap_or(path).with_extension("");
+    let label = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if label.is_empty() {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    } else {
+        label
+    }
+}
+
+/// Fit the image into a square of `size` pixels, small images are scaled up without smoothing.
+fn thumbnail(image: &RgbaImage, size: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let factor = f64::from(size) / f64::from(width.max(height));
+    let target = (
+        ((f64::from(width) * factor).round() as u32).clamp(1, size),
+        ((f64::from(height) * factor).round() as u32).clamp(1, size),
+    );
+
+    let filter = if factor >= 1.0 {
+        imageops::FilterType::Nearest
+    } else {
+        imageops::FilterType::Triangle
+    };
+
+    imageops::resize(image, target.0, target.1, filter)
+}
+
+const fn label_scale(thumb_size: u32) -> u32 {
+    if thumb_size >= 96 {
+        2
+    } else {
+        1
+    }
+}
+
+fn truncate(label: &str, max_chars: usize) -> String {
+    if label.chars().count() <= max_chars {
+        return label.to_owned();
+    }
+
+    // keep the end of the name, the folders at the start are usually shared
+    let keep = max_chars.saturating_sub(2);
+    let skip = label.chars().count() - keep;
+    format!("..{}", label.chars().skip(skip).collect::<String>())
+}
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+fn draw_text(image: &mut RgbaImage, x: u32, y: u32, text: &str, scale: u32) {
+    for (idx, c) in text.chars().enumerate() {
+        let glyph_x = x + idx as u32 * GLYPH_ADVANCE * scale;
+
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = glyph_x + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, LABEL_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 3x5 pixel glyph, letters are drawn in upper case.
+const fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        ' ' => [0; 5],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
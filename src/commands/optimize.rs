@@ -1,13 +1,21 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
+    io::{Read as _, Write as _},
     path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
-use clap::Args;
+use clap::{builder::PossibleValue, Args, ValueEnum};
+use image::{ImageFormat, RgbaImage};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+use strum::{EnumIter, VariantArray};
 
-use super::CommandError;
-use crate::image_util::{self, ImageBufferExt as _, ImgUtilError};
+use super::{human_readable_bytes, parse_byte_size, parse_fraction, CommandError, EncodeArgs};
+use crate::image_util::{self, ColorCounts, EncodeOptions, ImageBufferExt as _, ImgUtilError};
+use crate::{progress::Progress, summary};
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Args, Debug)]
 pub struct OptimizeArgs {
     pub target: PathBuf,
@@ -16,125 +24,881 @@ pub struct OptimizeArgs {
     #[clap(short, long, action)]
     pub recursive: bool,
 
+    /// Follow symlinked folders when searching recursively.
+    /// Folders that were already visited (e.g. through a symlink cycle) are skipped.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub follow_symlinks: bool,
+
     /// Treat images as a group and optimize them together instead of individually.
     /// This only has an effect with lossy compression.
     #[clap(short, long, action, verbatim_doc_comment)]
     pub group: bool,
 
+    /// How to group images when optimizing them together.
+    /// `all` uses one shared palette for every image, `folder` builds a separate palette per directory.
+    #[clap(long, default_value_t = GroupBy::All, verbatim_doc_comment)]
+    pub group_by: GroupBy,
+
     /// Allow lossy compression.
     #[clap(long, action)]
     pub lossy: bool,
+
+    /// Target file size for each image, e.g. `500KB` or `1.5MB`.
+    /// Lowers the quantization quality until the image fits or the quality floor is reached.
+    /// This only has an effect with lossy compression and is slow since every step is fully encoded.
+    #[clap(long, value_parser = parse_byte_size, verbatim_doc_comment)]
+    pub target_size: Option<u64>,
+
+    /// Lowest quantization quality [0-100] to try when searching for the target size.
+    #[clap(long, default_value_t = 30, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub min_quality: u8,
+
+    /// Lowest scaling factor to try when the quality floor still exceeds the target size.
+    /// Values < 1.0 allow shrinking the images, 1.0 disables scaling.
+    /// Note: scaled images change their dimensions, make sure to adjust your prototypes accordingly.
+    #[clap(long, default_value_t = 1.0, value_parser = parse_min_scale, verbatim_doc_comment)]
+    pub min_scale: f64,
+
+    /// Report the structural similarity (SSIM) and PSNR of lossy results compared to the original image.
+    #[clap(long, action)]
+    pub quality_report: bool,
+
+    /// Minimum structural similarity [0.0-1.0] a lossy result must reach.
+    /// Images below this threshold are reverted to lossless compression. Implies `--quality-report`.
+    #[clap(long, value_parser = parse_fraction, verbatim_doc_comment)]
+    pub min_ssim: Option<f64>,
+
+    /// Try lossless and lossy compression for each image and keep the lossy result
+    /// only when it stays above `--min-ssim` (default 0.99) and is at least `--auto-lossy-min-gain` smaller.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub auto_lossy: bool,
+
+    /// Minimum size reduction in percent the lossy result must achieve over lossless compression with `--auto-lossy`.
+    #[clap(long, default_value_t = 10.0, value_parser = parse_min_gain)]
+    pub auto_lossy_min_gain: f64,
+
+    /// Keep the original modification time of optimized images.
+    /// Useful for build systems that detect changes based on file timestamps.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub preserve_mtime: bool,
+
+    /// Keep running after the initial pass and optimize new or changed images as they appear.
+    /// Images are picked up once they were left unchanged for `--debounce` seconds.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub watch: bool,
+
+    /// Seconds an image has to stay unchanged before it is optimized in watch mode.
+    #[clap(long, default_value_t = 1.0, value_parser = parse_debounce, requires = "watch")]
+    pub debounce: f64,
+
+    /// Write the optimized image to stdout instead of overwriting the target.
+    /// Use `-` as target to read the image from stdin, logs are written to stderr.
+    #[clap(
+        long,
+        action,
+        conflicts_with_all = ["recursive", "group", "watch", "preserve_mtime"],
+        verbatim_doc_comment
+    )]
+    pub stdout: bool,
+
+    /// Only optimize images modified after the given point in time.
+    /// Accepts a unix timestamp, a UTC date or date time (e.g. `2024-05-01` or `2024-05-01T12:00:00Z`)
+    /// or the path of a file whose modification time is used.
+    #[clap(long, value_parser = parse_changed_since, verbatim_doc_comment)]
+    pub changed_since: Option<SystemTime>,
+
+    /// Only optimize images that changed compared to a git revision, e.g. `HEAD~1`.
+    /// Untracked images count as changed.
+    #[cfg(feature = "git")]
+    #[clap(long, verbatim_doc_comment)]
+    pub since_git: Option<String>,
+
+    /// Look for pixel identical images after optimizing.
+    /// `report` only lists them, `hardlink` replaces duplicates with hard links to a single copy.
+    #[clap(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "report",
+        conflicts_with = "stdout",
+        verbatim_doc_comment
+    )]
+    pub dedupe: Option<DedupeMode>,
+
+    // encoding args
+    #[clap(flatten)]
+    encode: EncodeArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
+pub enum GroupBy {
+    All,
+    Folder,
+}
+
+impl std::fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Folder => write!(f, "folder"),
+        }
+    }
+}
+
+impl ValueEnum for GroupBy {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::All => "all",
+            Self::Folder => "folder",
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
+pub enum DedupeMode {
+    Report,
+    Hardlink,
+}
+
+impl std::fmt::Display for DedupeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Report => write!(f, "report"),
+            Self::Hardlink => write!(f, "hardlink"),
+        }
+    }
 }
 
+impl ValueEnum for DedupeMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Report => "report",
+            Self::Hardlink => "hardlink",
+        }))
+    }
+}
+
+impl OptimizeArgs {
+    const fn check_quality(&self) -> bool {
+        self.quality_report || self.min_ssim.is_some()
+    }
+}
+
+/// Optimize existing images in place.
+///
+/// # Errors
+///
+/// Fails when the target can't be read, an image can't be optimized or written back.
 pub fn optimize(args: &OptimizeArgs) -> Result<(), CommandError> {
-    let mut paths = Vec::new();
+    let opts = args.encode.options();
 
-    if args.target.is_dir() {
-        paths.extend(pngs_in_folder(&args.target)?);
+    if args.stdout || args.target == Path::new(STDIN_TARGET) {
+        return optimize_stdout(args, opts);
+    }
 
-        if args.recursive {
-            let folders = recursive_folders(&args.target)?;
+    if !args.target.is_dir() && args.recursive {
+        warn!("target is not a directory, recursive search disabled");
+    }
 
-            for folder in &folders {
-                paths.extend(pngs_in_folder(folder)?);
+    let (found_paths, folder_count) = target_paths(args)?;
+    let mut paths = found_paths.clone();
+    if args.recursive && args.target.is_dir() {
+        info!(
+            "found {} images after searching through {folder_count} folders",
+            paths.len()
+        );
+    }
+
+    let found = paths.len();
+    if let Some(since) = args.changed_since {
+        paths.retain(|path| {
+            fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|mtime| mtime > since)
+        });
+    }
+
+    #[cfg(feature = "git")]
+    if let Some(rev) = &args.since_git {
+        let changed = git_changed(&args.target, rev)?;
+        paths.retain(|path| {
+            path.canonicalize()
+                .is_ok_and(|path| changed.contains(&path))
+        });
+    }
+    if paths.len() < found {
+        info!("skipping {} unchanged images", found - paths.len());
+    }
+
+    let (apngs, paths): (Vec<_>, Vec<_>) = paths.into_iter().partition(|path| is_apng_file(path));
+    if !apngs.is_empty() {
+        if args.lossy {
+            warn!("{APNG_LOSSY_WARNING}");
+        }
+
+        info!("optimizing {} animated pngs", apngs.len());
+        optimize_seq_runner(&apngs, args, |path| optimize_apng(path, opts));
+    }
+
+    if paths.is_empty() && apngs.is_empty() {
+        warn!("no source images found");
+    } else if !paths.is_empty() {
+        optimize_paths(&paths, args, opts)?;
+    }
+
+    // unchanged images can still be duplicates of changed ones
+    if let Some(mode) = args.dedupe {
+        dedupe(&found_paths, mode)?;
+    }
+
+    if args.watch {
+        if args.target.is_dir() {
+            watch(args, opts)?;
+        } else {
+            warn!("target is not a directory, watch mode disabled");
+        }
+    }
+
+    Ok(())
+}
+
+/// Report or hard link images with identical pixels, the first image of each group is kept.
+fn dedupe(paths: &[PathBuf], mode: DedupeMode) -> Result<(), CommandError> {
+    use sha2::Digest as _;
+
+    info!("looking for duplicate images");
+    let hashes = paths
+        .par_iter()
+        .filter_map(|path| {
+            let mut hasher = sha2::Sha256::new();
+
+            // only the first frame gets decoded, animations have to match byte for byte
+            let res = if is_apng_file(path) {
+                fs::read(path)
+                    .map(|data| hasher.update(data))
+                    .map_err(Into::into)
+            } else {
+                image_util::load_image_from_file(path).map(|img| {
+                    hasher.update(img.width().to_le_bytes());
+                    hasher.update(img.height().to_le_bytes());
+                    hasher.update(img.as_raw());
+                })
+            };
+
+            if let Err(err) = res {
+                warn!("{}: {err}", path.display());
+                return None;
             }
 
-            info!(
-                "found {} images after searching through {} folders",
-                paths.len(),
-                folders.len()
-            );
+            Some((hasher.finalize(), path))
+        })
+        .collect::<Vec<_>>();
+
+    let mut groups = BTreeMap::<_, Vec<&PathBuf>>::new();
+    for (hash, path) in hashes {
+        groups.entry(hash).or_default().push(path);
+    }
+
+    let mut duplicates = 0;
+    let mut wasted = 0;
+    for mut group in groups.into_values().filter(|group| group.len() > 1) {
+        group.sort_by(|a, b| natord::compare(&a.to_string_lossy(), &b.to_string_lossy()));
+        let Some((kept, others)) = group.split_first() else {
+            continue;
+        };
+
+        // already linked copies don't take up any space
+        let others = others
+            .iter()
+            .filter(|path| !is_hard_link(kept, path))
+            .collect::<Vec<_>>();
+        if others.is_empty() {
+            continue;
+        }
+
+        warn!(
+            "{}: identical to {}",
+            kept.display(),
+            others
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        for &path in others {
+            duplicates += 1;
+            wasted += fs::metadata(path).map_or(0, |meta| meta.len());
+
+            if mode == DedupeMode::Hardlink {
+                // link next to the duplicate first so it is only replaced once the link exists
+                let tmp = path.with_extension("png.dedupe");
+                fs::hard_link(kept, &tmp)
+                    .and_then(|()| fs::rename(&tmp, path))
+                    .map_err(image_util::write_error(path))?;
+                summary::record_file(path);
+            }
+        }
+    }
+
+    match (duplicates, mode) {
+        (0, _) => info!("no duplicate images found"),
+        (_, DedupeMode::Report) => info!(
+            "found {duplicates} duplicate images, {} could be saved",
+            human_readable_bytes(wasted)
+        ),
+        (_, DedupeMode::Hardlink) => info!(
+            "linked {duplicates} duplicate images, saved {}",
+            human_readable_bytes(wasted)
+        ),
+    }
+
+    Ok(())
+}
+
+/// Whether both paths already point to the same file.
+#[cfg(unix)]
+fn is_hard_link(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt as _;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+const fn is_hard_link(_: &Path, _: &Path) -> bool {
+    false
+}
+
+/// Size reduction in percent, `[0.0, 100.0]`.
+fn parse_min_gain(value: &str) -> Result<f64, String> {
+    let gain = value
+        .parse::<f64>()
+        .map_err(|_| format!("invalid percentage: {value}"))?;
+
+    if (0.0..=100.0).contains(&gain) {
+        Ok(gain)
+    } else {
+        Err(format!("{value} is not in 0.0..=100.0"))
+    }
+}
+
+/// Scaling factor in `(0.0, 1.0]`, images are only ever shrunk.
+fn parse_min_scale(value: &str) -> Result<f64, String> {
+    let scale = value
+        .parse::<f64>()
+        .map_err(|_| format!("invalid scale: {value}"))?;
+
+    if scale > 0.0 && scale <= 1.0 {
+        Ok(scale)
+    } else {
+        Err(format!(
+            "{value} is not in (0.0, 1.0], images are only shrunk"
+        ))
+    }
+}
+
+fn parse_changed_since(value: &str) -> Result<SystemTime, String> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    }
+
+    if let Some(secs) = parse_utc_date(value) {
+        return Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    }
+
+    let path = Path::new(value);
+    if path.exists() {
+        return fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .map_err(|err| format!("unable to read modification time of {value}: {err}"));
+    }
+
+    Err(format!(
+        "{value} is neither a timestamp, a date nor an existing file"
+    ))
+}
+
+/// Seconds since the unix epoch of `YYYY-MM-DD` with an optional `THH:MM[:SS][Z]` time.
+fn parse_utc_date(value: &str) -> Option<u64> {
+    let (date, time) = value
+        .split_once(['T', ' '])
+        .map_or((value, None), |(date, time)| (date, Some(time)));
+
+    let mut date = date.splitn(3, '-').map(str::parse::<u64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=month_days).contains(&day) {
+        return None;
+    }
+
+    let secs = time.map_or(Some(0), |time| {
+        let time = time.strip_suffix('Z').unwrap_or(time);
+        let mut parts = time.split(':').map(str::parse::<u64>);
+        let hours = parts.next()?.ok()?;
+        let minutes = parts.next()?.ok()?;
+        let seconds = parts.next().transpose().ok()?.unwrap_or(0);
+        if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+            return None;
         }
+        Some(hours * 3600 + minutes * 60 + seconds)
+    })?;
+
+    // days since the epoch in the proleptic gregorian calendar
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
     } else {
-        if args.recursive {
-            warn!("target is not a directory, recursive search disabled");
+        (year, month - 3)
+    };
+    let era_day = 365 * year + year / 4 - year / 100 + year / 400 + (153 * month + 2) / 5 + day - 1;
+    let days = era_day.checked_sub(719_468)?;
+
+    Some(days * 86_400 + secs)
+}
+
+/// Canonical paths of all files that differ from `rev` or are untracked.
+#[cfg(feature = "git")]
+fn git_changed(target: &Path, rev: &str) -> Result<HashSet<PathBuf>, CommandError> {
+    use std::process::Command;
+
+    let dir = if target.is_dir() {
+        target
+    } else {
+        target
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+    };
+
+    let run = |git_args: &[&str]| -> std::io::Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(git_args)
+            .output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "git {}: {}",
+                git_args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
         }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let changed = run(&["diff", "--name-only", "--relative", rev, "--"])?;
+    let untracked = run(&["ls-files", "--others", "--exclude-standard"])?;
+
+    Ok(changed
+        .lines()
+        .chain(untracked.lines())
+        .filter_map(|file| dir.join(file).canonicalize().ok())
+        .collect())
+}
+
+/// Target that reads the image from stdin, the result is always written to stdout.
+const STDIN_TARGET: &str = "-";
+
+/// Optimize a single image read from the target or stdin and write the result to stdout.
+fn optimize_stdout(args: &OptimizeArgs, opts: EncodeOptions) -> Result<(), CommandError> {
+    // the summary would end up in the middle of the image data
+    if summary::disable() {
+        warn!("no summary is printed when writing the image to stdout");
+    }
+
+    if args.auto_lossy || args.target_size.is_some() {
+        warn!("auto lossy and target size are not supported with stdout, ignoring them");
+    }
 
+    let (name, orig) = if args.target == Path::new(STDIN_TARGET) {
+        let mut orig = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut orig)?;
+        (Path::new("<stdin>"), orig)
+    } else {
+        let orig = fs::read(&args.target).map_err(image_util::read_error(&args.target))?;
+        (args.target.as_path(), orig)
+    };
+
+    let mut data = if image_util::is_apng(orig.as_slice()) {
+        if args.lossy {
+            warn!("{APNG_LOSSY_WARNING}");
+        }
+        image_util::optimize_apng(&orig, opts)?
+    } else {
+        encode_still(name, &orig, args, opts)?
+    };
+
+    // other formats always have to be converted
+    let is_png = image::guess_format(&orig).is_ok_and(|format| format == ImageFormat::Png);
+    if is_png && data.len() >= orig.len() && !opts.interlace {
+        info!("{}: could not optimize further", name.display());
+        data = orig;
+    } else if is_png {
+        let percent = ((data.len() as f64 / orig.len() as f64) - 1.0) * 100.0;
+        info!("{}: {percent:.2}% smaller", name.display());
+    }
+
+    std::io::stdout().lock().write_all(&data)?;
+
+    Ok(())
+}
+
+fn encode_still(
+    name: &Path,
+    orig: &[u8],
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<Vec<u8>, CommandError> {
+    if !args.lossy {
+        if let Some(data) = image_util::optimize_encoded_png(orig, opts)? {
+            return Ok(data);
+        }
+    }
+
+    let img = image_util::load_image_from_memory(orig, name)?;
+    let data = img.to_optimized_png(args.lossy, opts)?;
+
+    if args.lossy && args.check_quality() {
+        let res = image::load_from_memory(&data)?.to_rgba8();
+
+        if !lossy_result_acceptable(name, &img, &res, args) {
+            return Ok(img.to_optimized_png(false, opts)?);
+        }
+    }
+
+    Ok(data)
+}
+
+const APNG_LOSSY_WARNING: &str =
+    "lossy compression is not supported for animated pngs, they are optimized losslessly";
+
+fn is_apng_file(path: &Path) -> bool {
+    fs::File::open(path)
+        .map(std::io::BufReader::new)
+        .is_ok_and(image_util::is_apng)
+}
+
+/// Recompress all frames of an animated png, decoding it would only keep the first frame.
+fn optimize_apng(path: &PathBuf, opts: EncodeOptions) -> Result<(u64, u64), ImgUtilError> {
+    let orig = std::fs::read(path).map_err(image_util::read_error(path))?;
+    let data = image_util::optimize_apng(&orig, opts)?;
+    std::fs::write(path, &data).map_err(image_util::write_error(path))?;
+
+    optimize_common_res(path, &orig, orig.len() as u64, data.len() as u64, opts)
+}
+
+/// All pngs of the target and the amount of searched subfolders.
+fn target_paths(args: &OptimizeArgs) -> Result<(Vec<PathBuf>, usize), CommandError> {
+    let mut paths = Vec::new();
+
+    if !args.target.is_dir() {
         if args.target.extension().is_some_and(|ext| ext == "png") {
             paths.push(args.target.clone());
         }
+
+        return Ok((paths, 0));
     }
 
-    if paths.is_empty() {
-        warn!("no source images found");
+    paths.extend(pngs_in_folder(&args.target)?);
+
+    if !args.recursive {
+        return Ok((paths, 0));
+    }
+
+    let mut visited = HashSet::from([args.target.canonicalize()?]);
+    let folders = recursive_folders(&args.target, args.follow_symlinks, &mut visited)?;
+    for folder in &folders {
+        paths.extend(pngs_in_folder(folder)?);
+    }
+
+    Ok((paths, folders.len()))
+}
+
+/// Poll interval of `--watch`.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Longest debounce in seconds, anything above is most likely a typo.
+const MAX_DEBOUNCE: f64 = 3600.0;
+
+/// Debounce in `[0, MAX_DEBOUNCE]` seconds.
+fn parse_debounce(value: &str) -> Result<f64, String> {
+    let debounce = value
+        .parse::<f64>()
+        .map_err(|_| format!("invalid debounce: {value}"))?;
+
+    if (0.0..=MAX_DEBOUNCE).contains(&debounce) {
+        Ok(debounce)
+    } else {
+        Err(format!("{value} is not in 0..={MAX_DEBOUNCE} seconds"))
+    }
+}
+
+/// Modification time and size of a watched image.
+type FileState = (SystemTime, u64);
+
+fn file_state(path: &Path) -> Option<FileState> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Poll the target for new or changed images and optimize them once they stopped changing, runs until interrupted.
+fn watch(args: &OptimizeArgs, opts: EncodeOptions) -> Result<(), CommandError> {
+    let debounce = Duration::from_secs_f64(args.debounce);
+    let snapshot = || -> Result<HashMap<PathBuf, FileState>, CommandError> {
+        Ok(target_paths(args)?
+            .0
+            .into_iter()
+            .filter_map(|path| file_state(&path).map(|state| (path, state)))
+            .collect())
+    };
+
+    let mut known = snapshot()?;
+    let mut pending = HashMap::<PathBuf, (FileState, Instant)>::new();
+
+    info!(
+        "watching {} for new images, press Ctrl+C to stop",
+        args.target.display()
+    );
+
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+
+        let current = snapshot()?;
+        known.retain(|path, _| current.contains_key(path));
+        pending.retain(|path, _| current.contains_key(path));
+
+        for (path, state) in current {
+            if known.get(&path) == Some(&state) {
+                pending.remove(&path);
+                continue;
+            }
+
+            // restart the debounce while the image is still being written
+            if pending
+                .get(&path)
+                .is_none_or(|(pending, _)| *pending != state)
+            {
+                pending.insert(path, (state, Instant::now()));
+            }
+        }
+
+        let ready = pending
+            .iter()
+            .filter(|(_, (_, since))| since.elapsed() >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        for path in ready {
+            pending.remove(&path);
+            info!("{}: changed, optimizing", path.display());
+            optimize_seq_runner(std::slice::from_ref(&path), args, |path| {
+                optimize_file(path, args, opts)
+            });
+
+            // our own write must not trigger another run
+            if let Some(state) = file_state(&path) {
+                known.insert(path, state);
+            }
+        }
+    }
+}
+
+/// Optimize a single image on its own, with the per image strategy selected by the flags.
+fn optimize_file(
+    path: &PathBuf,
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<(u64, u64), ImgUtilError> {
+    if is_apng_file(path) {
+        if args.lossy {
+            warn!("{APNG_LOSSY_WARNING}");
+        }
+        optimize_apng(path, opts)
+    } else if args.auto_lossy {
+        optimize_single_auto(path, args, opts)
+    } else if let Some(target_size) = args.target_size.filter(|_| args.lossy) {
+        optimize_single_target_size(path, target_size, args, opts)
+    } else {
+        optimize_single(path, args, opts)
+    }
+}
+
+fn optimize_paths(
+    paths: &[PathBuf],
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<(), CommandError> {
+    if args.auto_lossy {
+        if args.group || args.target_size.is_some() {
+            warn!("auto lossy decides per image, ignoring group and target size flags");
+        }
+
+        optimize_seq_runner(paths, args, |path| optimize_single_auto(path, args, opts));
+
         return Ok(());
     }
 
+    if let Some(target_size) = args.target_size {
+        if args.lossy {
+            if args.group {
+                warn!("target size is applied per image, ignoring group flag");
+            }
+
+            optimize_seq_runner(paths, args, |path| {
+                optimize_single_target_size(path, target_size, args, opts)
+            });
+
+            return Ok(());
+        }
+
+        warn!("target size only has an effect with lossy compression, ignoring target size");
+    }
+
     if args.group {
         if args.lossy {
-            return optimize_lossy_grouped(&paths);
+            return match args.group_by {
+                GroupBy::All => optimize_lossy_grouped(paths, args, opts),
+                GroupBy::Folder => {
+                    for (folder, group) in group_by_folder(paths) {
+                        info!("optimizing group {}", folder.display());
+                        optimize_lossy_grouped(&group, args, opts)?;
+                    }
+
+                    Ok(())
+                }
+            };
         }
 
         warn!("group optimization only has an effect with lossy compression, ignoring group flag");
     }
 
-    optimize_seq_runner(&paths, |path| optimize_single(path, args.lossy));
+    optimize_seq_runner(paths, args, |path| optimize_single(path, args, opts));
 
     Ok(())
 }
 
-fn optimize_lossy_grouped(paths: &[PathBuf]) -> Result<(), CommandError> {
-    let quant = image_util::quantization_attributes()?;
+fn optimize_lossy_grouped(
+    paths: &[PathBuf],
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<(), CommandError> {
+    let quant = image_util::quantization_attributes(opts.quant_speed)?;
     let mut histo = imagequant::Histogram::new(&quant);
 
     info!("generating histogram of all images");
-    let known_good_paths = paths
-        .iter()
-        .filter(|path| match image_util::load_image_from_file(path) {
-            Ok(img) => {
-                if let Err(err) = histo.add_colors(&img.get_histogram(), 0.0) {
-                    warn!("{}: {err}", path.display());
-                    false
-                } else {
-                    true
+
+    // every thread counts the colors of its images, the counts are merged in path order
+    let (known_good_paths, counts) = paths
+        .par_iter()
+        .fold(
+            || (Vec::new(), ColorCounts::default()),
+            |(mut good, mut counts), path| {
+                match image_util::load_image_from_file(path) {
+                    Ok(img) => {
+                        counts.add(&img.get_histogram());
+                        good.push(path.clone());
+                    }
+                    Err(err) => warn!("{}: {err}", path.display()),
                 }
-            }
-            Err(err) => {
-                warn!("{}: {err}", path.display());
-                false
-            }
-        })
-        .cloned()
-        .collect::<Box<_>>();
+                (good, counts)
+            },
+        )
+        .reduce(
+            || (Vec::new(), ColorCounts::default()),
+            |(mut good, counts), (other_good, other_counts)| {
+                good.extend(other_good);
+                (good, counts.merge(other_counts))
+            },
+        );
 
     if known_good_paths.is_empty() {
         warn!("no source images found");
         return Ok(());
     }
 
+    histo
+        .add_colors(&counts.histogram(), 0.0)
+        .map_err(ImgUtilError::from)?;
+
     let mut qres = histo.quantize(&quant).map_err(ImgUtilError::from)?;
     qres.set_dithering_level(1.0).map_err(ImgUtilError::from)?;
     let palette = image_util::convert_palette(qres.palette());
 
     info!("optimizing images");
 
-    optimize_seq_runner(&known_good_paths, |path| {
-        optimize_single_quantized(path, &quant, &mut qres, &palette)
+    optimize_seq_runner(&known_good_paths, args, |path| {
+        optimize_single_quantized(path, &quant, &mut qres, &palette, args, opts)
     });
 
     Ok(())
 }
 
-fn optimize_seq_runner<S>(paths: &[PathBuf], mut step: S)
+fn group_by_folder(paths: &[PathBuf]) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut groups = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+
+    for path in paths {
+        let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        groups.entry(folder).or_default().push(path.clone());
+    }
+
+    groups
+}
+
+fn optimize_seq_runner<S>(paths: &[PathBuf], args: &OptimizeArgs, mut step: S)
 where
     S: FnMut(&PathBuf) -> Result<(u64, u64), ImgUtilError>,
 {
     let mut total_in = 0;
     let mut total_out = 0;
+    let progress = Progress::new("optimized", paths.len());
 
     for path in paths {
+        let mtime = if args.preserve_mtime {
+            fs::metadata(path).and_then(|meta| meta.modified()).ok()
+        } else {
+            None
+        };
+
         match step(path) {
             Ok((b_in, b_out)) => {
                 total_in += b_in;
                 total_out += b_out;
+
+                if let Some(mtime) = mtime {
+                    if let Err(err) = restore_mtime(path, mtime) {
+                        warn!(
+                            "{}: unable to restore modification time: {err}",
+                            path.display()
+                        );
+                    }
+                }
             }
             Err(err) => {
                 error!("{}: {err}", path.display());
             }
         }
+
+        progress.inc();
     }
 
-    let reduced_by = total_in - total_out;
+    progress.finish();
+
+    let reduced_by = total_in.saturating_sub(total_out);
     let percent = ((total_out as f64 / total_in as f64) - 1.0) * 100.0;
     info!(
         "total: {percent:.2}%, saved {}",
@@ -142,12 +906,33 @@ where
     );
 }
 
-fn optimize_single(path: &PathBuf, lossy: bool) -> Result<(u64, u64), ImgUtilError> {
-    let orig = std::fs::read(path)?;
+fn optimize_single(
+    path: &PathBuf,
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<(u64, u64), ImgUtilError> {
+    let orig = std::fs::read(path).map_err(image_util::read_error(path))?;
     let orig_size = orig.len() as u64;
-    let res_size = image_util::load_image_from_file(path)?.save_optimized_png(path, lossy)?;
 
-    optimize_common_res(path, &orig, orig_size, res_size)
+    if !args.lossy {
+        if let Some(data) = image_util::optimize_encoded_png(&orig, opts)? {
+            std::fs::write(path, &data).map_err(image_util::write_error(path))?;
+            return optimize_common_res(path, &orig, orig_size, data.len() as u64, opts);
+        }
+    }
+
+    let img = image_util::load_image_from_file(path)?;
+    let mut res_size = img.save_optimized_png(path, args.lossy, opts)?;
+
+    if args.lossy && args.check_quality() {
+        let res = image_util::load_image_from_file(path)?;
+
+        if !lossy_result_acceptable(path, &img, &res, args) {
+            res_size = img.save_optimized_png(path, false, opts)?;
+        }
+    }
+
+    optimize_common_res(path, &orig, orig_size, res_size, opts)
 }
 
 fn optimize_single_quantized(
@@ -155,27 +940,223 @@ fn optimize_single_quantized(
     quant: &imagequant::Attributes,
     qres: &mut imagequant::QuantizationResult,
     palette: &[[u8; 4]],
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
 ) -> Result<(u64, u64), ImgUtilError> {
-    let orig = std::fs::read(path)?;
+    let orig = std::fs::read(path).map_err(image_util::read_error(path))?;
     let orig_size = orig.len() as u64;
 
-    let img = image_util::load_image_from_file(path)?;
-    let (width, height) = img.dimensions();
+    let original = image_util::load_image_from_file(path)?;
+    let (width, height) = original.dimensions();
     let w_usize = width as usize;
     let h_usize = height as usize;
-    let mut img = quant.new_image(img.to_quant_img(), w_usize, h_usize, 0.0)?;
+    let mut img = quant.new_image(original.to_quant_img(), w_usize, h_usize, 0.0)?;
 
     let mut pxls = Vec::with_capacity(w_usize * h_usize);
     qres.remap_into_vec(&mut img, &mut pxls)?;
 
-    let res_size = image_util::optimize_png(
-        &image_util::image_buf_from_palette(width, height, palette, &pxls),
-        width,
-        height,
-        path,
+    let buf = image_util::image_buf_from_palette(width, height, palette, &pxls);
+
+    if args.check_quality() {
+        if let Some(res) = RgbaImage::from_raw(width, height, buf.to_vec()) {
+            if !lossy_result_acceptable(path, &original, &res, args) {
+                let res_size = original.save_optimized_png(path, false, opts)?;
+                return optimize_common_res(path, &orig, orig_size, res_size, opts);
+            }
+        }
+    }
+
+    let res_size = image_util::optimize_png(&buf, width, height, path, opts)?;
+
+    optimize_common_res(path, &orig, orig_size, res_size, opts)
+}
+
+fn optimize_single_target_size(
+    path: &PathBuf,
+    target_size: u64,
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<(u64, u64), ImgUtilError> {
+    let min_quality = args.min_quality;
+    let orig = std::fs::read(path).map_err(image_util::read_error(path))?;
+    let orig_size = orig.len() as u64;
+    let img = image_util::load_image_from_file(path)?;
+
+    // binary search for the highest quality that still fits
+    let mut best: Option<(u8, Vec<u8>)> = None;
+    let mut smallest: Option<(u8, f64, Vec<u8>)> = None;
+    let mut low = min_quality;
+    let mut high = 100;
+
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let data = image_util::encode_quantized_png(&img, quality, opts)?;
+        trace!("{}: quality {quality} -> {}", path.display(), data.len());
+
+        if data.len() as u64 <= target_size {
+            best = Some((quality, data));
+            low = quality + 1;
+        } else {
+            if smallest
+                .as_ref()
+                .is_none_or(|(_, _, smallest)| data.len() < smallest.len())
+            {
+                smallest = Some((quality, 1.0, data));
+            }
+
+            if quality == 0 {
+                break;
+            }
+
+            high = quality - 1;
+        }
+    }
+
+    let (quality, scale, data) = if let Some((quality, data)) = best {
+        (quality, 1.0, data)
+    } else {
+        // quality floor reached, try shrinking the image
+        let (width, height) = img.dimensions();
+        let mut fitting = None;
+
+        for step in 1..10 {
+            let scale = f64::from(step).mul_add(-0.1, 1.0);
+            if scale < args.min_scale - f64::EPSILON {
+                break;
+            }
+
+            let scaled = image::imageops::resize(
+                &img,
+                ((f64::from(width) * scale).round() as u32).max(1),
+                ((f64::from(height) * scale).round() as u32).max(1),
+                image::imageops::FilterType::Lanczos3,
+            );
+            let data = image_util::encode_quantized_png(&scaled, min_quality, opts)?;
+            trace!("{}: scale {scale:.1} -> {}", path.display(), data.len());
+
+            if data.len() as u64 <= target_size {
+                fitting = Some((min_quality, scale, data));
+                break;
+            }
+
+            smallest = Some((min_quality, scale, data));
+        }
+
+        #[allow(clippy::unwrap_used)]
+        fitting.unwrap_or_else(|| {
+            let smallest = smallest.unwrap();
+            warn!(
+                "{}: could not reach target size of {}, smallest result is {}",
+                path.display(),
+                human_readable_bytes(target_size),
+                human_readable_bytes(smallest.2.len() as u64)
+            );
+            smallest
+        })
+    };
+
+    let mut data = data;
+
+    if (scale - 1.0).abs() > f64::EPSILON {
+        info!(
+            "{}: using quality {quality} at scale {scale:.1}",
+            path.display()
+        );
+    } else {
+        info!("{}: using quality {quality}", path.display());
+
+        if args.check_quality() {
+            let res = image::load_from_memory(&data)?.to_rgba8();
+
+            if !lossy_result_acceptable(path, &img, &res, args) {
+                let (width, height) = img.dimensions();
+                data = image_util::encode_optimized_png(img.as_raw(), width, height, opts)?;
+            }
+        }
+    }
+
+    std::fs::write(path, &data).map_err(image_util::write_error(path))?;
+
+    optimize_common_res(path, &orig, orig_size, data.len() as u64, opts)
+}
+
+fn optimize_single_auto(
+    path: &PathBuf,
+    args: &OptimizeArgs,
+    opts: EncodeOptions,
+) -> Result<(u64, u64), ImgUtilError> {
+    const DEFAULT_MIN_SSIM: f64 = 0.99;
+
+    let orig = std::fs::read(path).map_err(image_util::read_error(path))?;
+    let orig_size = orig.len() as u64;
+    let img = image_util::load_image_from_file(path)?;
+    let (width, height) = img.dimensions();
+
+    let lossless = image_util::optimize_encoded_png(&orig, opts)?.map_or_else(
+        || image_util::encode_optimized_png(img.as_raw(), width, height, opts),
+        Ok,
     )?;
+    let lossy = image_util::encode_quantized_png(&img, 100, opts)?;
+
+    let ssim = image_util::ssim(&img, &image::load_from_memory(&lossy)?.to_rgba8());
+    let min_ssim = args.min_ssim.unwrap_or(DEFAULT_MIN_SSIM);
+    let gain = (1.0 - lossy.len() as f64 / lossless.len() as f64) * 100.0;
 
-    optimize_common_res(path, &orig, orig_size, res_size)
+    let data = if ssim < min_ssim {
+        info!(
+            "{}: using lossless, lossy ssim {ssim:.4} is below {min_ssim}",
+            path.display()
+        );
+        lossless
+    } else if gain < args.auto_lossy_min_gain {
+        info!(
+            "{}: using lossless, lossy is only {gain:.2}% smaller",
+            path.display()
+        );
+        lossless
+    } else {
+        info!(
+            "{}: using lossy, {gain:.2}% smaller with ssim {ssim:.4}",
+            path.display()
+        );
+        lossy
+    };
+
+    std::fs::write(path, &data).map_err(image_util::write_error(path))?;
+
+    optimize_common_res(path, &orig, orig_size, data.len() as u64, opts)
+}
+
+/// Log the similarity of a lossy result compared to the original image.
+/// Returns `false` when the result falls below `--min-ssim` and should be reverted.
+fn lossy_result_acceptable(
+    path: &Path,
+    original: &RgbaImage,
+    res: &RgbaImage,
+    args: &OptimizeArgs,
+) -> bool {
+    if original.dimensions() != res.dimensions() {
+        debug!(
+            "{}: dimensions changed, skipping quality check",
+            path.display()
+        );
+        return true;
+    }
+
+    let ssim = image_util::ssim(original, res);
+    let psnr = image_util::psnr(original, res);
+    info!("{}: ssim {ssim:.4}, psnr {psnr:.2}dB", path.display());
+
+    match args.min_ssim {
+        Some(min_ssim) if ssim < min_ssim => {
+            warn!(
+                "{}: ssim {ssim:.4} is below {min_ssim}, reverting to lossless compression",
+                path.display()
+            );
+            false
+        }
+        _ => true,
+    }
 }
 
 fn optimize_common_res(
@@ -183,10 +1164,21 @@ fn optimize_common_res(
     orig: &[u8],
     orig_size: u64,
     res_size: u64,
+    opts: EncodeOptions,
 ) -> Result<(u64, u64), ImgUtilError> {
-    if res_size >= orig_size {
+    crate::summary::record_file(path);
+
+    if res_size >= orig_size && opts.interlace {
+        // interlacing was explicitly requested, keep the result even if it grew
+        let percent = ((res_size as f64 / orig_size as f64) - 1.0) * 100.0;
+        info!(
+            "{}: {percent:.2}% larger due to interlacing",
+            path.display()
+        );
+        Ok((orig_size, res_size))
+    } else if res_size >= orig_size {
         info!("{}: could not optimize further", path.display());
-        std::fs::write(path, orig)?;
+        std::fs::write(path, orig).map_err(image_util::write_error(path))?;
         Ok((orig_size, orig_size))
     } else {
         let reduced_by = orig_size - res_size;
@@ -202,27 +1194,68 @@ fn optimize_common_res(
     }
 }
 
-fn recursive_folders(path: impl AsRef<Path>) -> std::io::Result<Box<[PathBuf]>> {
-    let mut folders = Vec::new();
-
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let path = entry.path();
+fn restore_mtime(path: &Path, mtime: std::time::SystemTime) -> std::io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(mtime)
+}
 
-        if path.is_dir() {
-            folders.push(path);
-        }
-    }
+pub(super) fn recursive_folders(
+    path: impl AsRef<Path>,
+    follow_symlinks: bool,
+    visited: &mut HashSet<PathBuf>,
+) -> std::io::Result<Box<[PathBuf]>> {
+    let mut folders = subfolders(path, follow_symlinks, visited)?;
 
     let mut descent = Vec::new();
     for folder in &folders {
-        descent.extend(recursive_folders(folder)?);
+        descent.extend(recursive_folders(folder, follow_symlinks, visited)?);
     }
 
     folders.extend(descent);
     Ok(folders.into_boxed_slice())
 }
 
+/// Direct subfolders of `path` that are not part of `visited` yet, they are added to it.
+pub(super) fn subfolders(
+    path: impl AsRef<Path>,
+    follow_symlinks: bool,
+    visited: &mut HashSet<PathBuf>,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(path)?.collect::<std::io::Result<Vec<_>>>()?;
+    // real folders come before symlinks to them in a stable order
+    entries.sort_by_key(|entry| {
+        (
+            entry.file_type().is_ok_and(|kind| kind.is_symlink()),
+            entry.path(),
+        )
+    });
+
+    let mut folders = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+
+        if !path.is_dir() {
+            continue;
+        }
+
+        if entry.file_type()?.is_symlink() && !follow_symlinks {
+            debug!("{}: skipping symlinked folder", path.display());
+            continue;
+        }
+
+        if !visited.insert(path.canonicalize()?) {
+            warn!("{}: folder was already visited, skipping", path.display());
+            continue;
+        }
+
+        folders.push(path);
+    }
+
+    Ok(folders)
+}
+
 fn pngs_in_folder(path: impl AsRef<Path>) -> std::io::Result<Box<[PathBuf]>> {
     let mut pngs = Vec::new();
 
@@ -238,20 +1271,34 @@ fn pngs_in_folder(path: impl AsRef<Path>) -> std::io::Result<Box<[PathBuf]>> {
     Ok(pngs.into_boxed_slice())
 }
 
-fn human_readable_bytes(bytes: u64) -> String {
-    static UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"]; // wtf are you doing if this saves you petabytes -.-
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::parse_utc_date;
 
-    if bytes < 1000 {
-        return format!("{bytes}{}", UNITS[0]);
+    #[test]
+    fn utc_dates() {
+        assert_eq!(parse_utc_date("1970-01-01"), Some(0));
+        assert_eq!(parse_utc_date("2024-03-01"), Some(1_709_251_200));
+        assert_eq!(parse_utc_date("2023-12-31T23:59:59Z"), Some(1_704_067_199));
+        assert_eq!(parse_utc_date("2023-12-31 23:59"), Some(1_704_067_140));
     }
 
-    let mut size = bytes as f64;
-    let mut unit = 0;
-
-    while size >= 1000.0 && unit < UNITS.len() - 1 {
-        size /= 1000.0;
-        unit += 1;
+    #[test]
+    fn leap_days() {
+        assert_eq!(parse_utc_date("2024-02-29"), Some(1_709_164_800));
+        assert_eq!(parse_utc_date("2000-02-29"), Some(951_782_400));
+        assert_eq!(parse_utc_date("2023-02-29"), None);
+        assert_eq!(parse_utc_date("2100-02-29"), None);
     }
 
-    format!("{:.2}{}", size, UNITS[unit])
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(parse_utc_date("1969-12-31"), None);
+        assert_eq!(parse_utc_date("2024-13-01"), None);
+        assert_eq!(parse_utc_date("2024-04-31"), None);
+        assert_eq!(parse_utc_date("2024-01-01T24:00"), None);
+        assert_eq!(parse_utc_date("2024-01-01T12:00:00:00"), None);
+        assert_eq!(parse_utc_date("yesterday"), None);
+    }
 }
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+use serde_json::json;
+
+use super::{
+    create_output_dir, folder_name, output_error, output_image_error, recursive_folders,
+    CommandError, InputFormat,
+};
+use crate::{
+    image_util::{self, ColorCounts, ImageBufferExt as _, ImgUtilError},
+    summary,
+};
+
+#[derive(Args, Debug)]
+pub struct PaletteArgs {
+    /// Image or folder of images to extract the palette from.
+    pub source: PathBuf,
+
+    /// Output folder for the swatch image and the palette data.
+    pub output: PathBuf,
+
+    /// Number of colors to extract.
+    #[clap(short, long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(2..=256))]
+    pub colors: u32,
+
+    /// Include the images of all subfolders.
+    #[clap(short, long, action)]
+    pub recursive: bool,
+
+    /// Follow symlinked folders when searching recursively.
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+
+    /// Also write the palette as raw palette file that can be used with `--palette-in`.
+    #[clap(long, action)]
+    pub raw: bool,
+
+    /// Image or folder of images to compare the palette with.
+    /// Colors of the source without a close match in the other palette are reported,
+    /// the command fails when any are found.
+    #[clap(long, value_name = "PATH", verbatim_doc_comment)]
+    pub compare: Option<PathBuf>,
+
+    /// Max distance of two colors in RGBA space that still counts as a match when comparing.
+    #[clap(long, default_value_t = 32.0, requires = "compare")]
+    pub max_distance: f64,
+
+    /// Image formats to accept as source images, e.g. `png,tga`.
+    #[clap(long, value_delimiter = ',', default_values_t = [InputFormat::Png])]
+    input_formats: Vec<InputFormat>,
+}
+
+/// Size of a single color of the swatch image in pixels.
+const SWATCH_SIZE: u32 = 32;
+
+/// Colors per row of the swatch image.
+const SWATCH_COLUMNS: u32 = 16;
+
+/// Color of the palette with its share of all visible pixels.
+struct PaletteColor {
+    color: [u8; 4],
+    share: f64,
+}
+
+/// Extract the dominant colors of the source, write them as swatch image and json
+/// and optionally compare them with the palette of other images.
+///
+/// # Errors
+///
+/// Fails when the images can't be loaded, the palette can't be written
+/// or [`CommandError::PalettesDiffer`] when the compared palettes differ.
+pub fn palette(args: &PaletteArgs) -> Result<(), CommandError> {
+    create_output_dir(&args.output)?;
+
+    let palette = extract_palette(&args.source, args)?;
+    if palette.is_empty() {
+        warn!("no visible pixels found");
+        return Ok(());
+    }
+
+    let name = if args.source.is_file() {
+        Path::new(&folder_name(&args.source)?)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    } else {
+        folder_name(&args.source)?
+    };
+
+    let swatch_path = args.output.join(format!("{name}_palette.png"));
+    swatch(&palette)
+        .save_with_format(&swatch_path, ImageFormat::Png)
+        .map_err(output_image_error(&swatch_path))?;
+    summary::record_file(&swatch_path);
+
+    let data = json!({
+        "source": args.source.display().to_string(),
+        "colors": palette
+            .iter()
+            .map(|color| json!({
+                "hex": hex(color.color),
+                "rgba": color.color,
+                "share": color.share,
+            }))
+            .collect::<Vec<_>>(),
+    });
+    let data_path = args.output.join(format!("{name}_palette.json"));
+    fs::write(
+        &data_path,
+        serde_json::to_string_pretty(&data).unwrap_or_default(),
+    )
+    .map_err(output_error(&data_path))?;
+    summary::record_file(&data_path);
+
+    if args.raw {
+        let raw_path = args.output.join(format!("{name}.palette"));
+        let colors = palette.iter().map(|color| color.color).collect::<Vec<_>>();
+        image_util::save_palette(&raw_path, &colors)?;
+        summary::record_file(&raw_path);
+    }
+
+    info!(
+        "extracted {} colors, saved to {}",
+        palette.len(),
+        swatch_path.display()
+    );
+    for color in &palette {
+        debug!("{}: {:.2}%", hex(color.color), color.share * 100.0);
+    }
+
+    let Some(other) = &args.compare else {
+        return Ok(());
+    };
+
+    let other_palette = extract_palette(other, args)?;
+    let mut unmatched = Vec::new();
+    let mut weighted_distance = 0.0;
+    for color in &palette {
+        let (nearest, distance) = other_palette
+            .iter()
+            .map(|other| (other.color, distance(color.color, other.color)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap_or((color.color, f64::INFINITY));
+
+        weighted_distance += distance * color.share;
+        if distance > args.max_distance {
+            unmatched.push(format!(
+                "{} ({:.2}%) has no close match, nearest is {} at a distance of {distance:.1}",
+                hex(color.color),
+                color.share * 100.0,
+                hex(nearest)
+            ));
+        }
+    }
+
+    info!(
+        "average distance to the palette of {}: {weighted_distance:.1}",
+        other.display()
+    );
+
+    if unmatched.is_empty() {
+        info!("all colors have a close match");
+        return Ok(());
+    }
+
+    for msg in &unmatched {
+        warn!("{}: {msg}", args.source.display());
+        summary::record_failure(Some(&args.source), msg);
+    }
+
+    Err(CommandError::PalettesDiffer(unmatched.len()))
+}
+
+/// Quantize the visible pixels of all images in `source` to `args.colors` colors, most used first.
+fn extract_palette(source: &Path, args: &PaletteArgs) -> Result<Vec<PaletteColor>, CommandError> {
+    let formats = args
+        .input_formats
+        .iter()
+        .map(|&format| format.into())
+        .collect::<Vec<ImageFormat>>();
+
+    let mut paths = image_util::image_paths(source, &formats)?;
+    if args.recursive && source.is_dir() {
+        let mut visited = HashSet::from([source.canonicalize()?]);
+        for folder in recursive_folders(source, args.follow_symlinks, &mut visited)? {
+            paths.extend(image_util::image_paths(&folder, &formats)?);
+        }
+    }
+
+    let counts = paths
+        .par_iter()
+        .map(|path| {
+            let mut counts = ColorCounts::default();
+            counts.add(&image_util::load_image_from_file(path)?.get_histogram());
+            Ok::<_, ImgUtilError>(counts)
+        })
+        .try_reduce(ColorCounts::default, |a, b| Ok(a.merge(b)))?;
+
+    // fully transparent pixels would easily become the most used color
+    let histogram = counts
+        .histogram()
+        .iter()
+        .filter(|entry| entry.color.a > 0)
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut quant = image_util::quantization_attributes(1)?;
+    quant
+        .set_max_colors(args.colors)
+        .map_err(ImgUtilError::from)?;
+
+    let mut histo = imagequant::Histogram::new(&quant);
+    histo
+        .add_colors(&histogram, 0.0)
+        .map_err(ImgUtilError::from)?;
+    let mut qres = histo.quantize(&quant).map_err(ImgUtilError::from)?;
+    let colors = image_util::convert_palette(qres.palette());
+
+    // assign every counted color to its nearest palette color
+    let mut totals = vec![0u64; colors.len()];
+    for entry in &histogram {
+        let color = [entry.color.r, entry.color.g, entry.color.b, entry.color.a];
+        let nearest = colors
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| distance(color, **a).total_cmp(&distance(color, **b)))
+            .map(|(idx, _)| idx);
+
+        if let Some(idx) = nearest {
+            totals[idx] += u64::from(entry.count);
+        }
+    }
+
+    let total = totals.iter().sum::<u64>().max(1) as f64;
+    let mut palette = colors
+        .iter()
+        .zip(totals)
+        .map(|(&color, count)| PaletteColor {
+            color,
+            share: count as f64 / total,
+        })
+        .collect::<Vec<_>>();
+    palette.sort_by(|a, b| b.share.total_cmp(&a.share));
+
+    Ok(palette)
+}
+
+fn distance(a: [u8; 4], b: [u8; 4]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| (f64::from(a) - f64::from(b)).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn hex(color: [u8; 4]) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        color[0], color[1], color[2], color[3]
+    )
+}
+
+/// Swatches of all colors, most used first.
+fn swatch(palette: &[PaletteColor]) -> RgbaImage {
+    let count = palette.len() as u32;
+    let columns = count.min(SWATCH_COLUMNS);
+    let mut image = RgbaImage::new(columns * SWATCH_SIZE, count.div_ceil(columns) * SWATCH_SIZE);
+
+    for (idx, color) in palette.iter().enumerate() {
+        let idx = idx as u32;
+        let tile = RgbaImage::from_pixel(SWATCH_SIZE, SWATCH_SIZE, Rgba(color.color));
+        imageops::replace(
+            &mut image,
+            &tile,
+            i64::from(idx % columns * SWATCH_SIZE),
+            i64::from(idx / columns * SWATCH_SIZE),
+        );
+    }
+
+    image
+}
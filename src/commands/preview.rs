@@ -0,0 +1,141 @@
+use std::{fs, io::Cursor, num::NonZeroUsize, path::PathBuf};
+
+use clap::Args;
+use image::{ImageFormat, RgbaImage};
+
+use super::{frames_from_data, output_error, CommandError, InputFormat};
+use crate::{image_util, summary};
+
+/// Page template, the frames are embedded as data URIs.
+const TEMPLATE: &str = include_str!("preview.html");
+
+#[derive(Args, Debug)]
+pub struct PreviewArgs {
+    /// Folder containing the individual frames.
+    /// When using `--data` this is the folder containing the sheets (or one of the sheets).
+    #[clap(verbatim_doc_comment)]
+    pub source: PathBuf,
+
+    /// Output html file.
+    pub output: PathBuf,
+
+    /// Spritter generated data file (lua or json) describing the sheet layout.
+    /// The frames are cut from the sheets next to it, the same way the split command does.
+    #[clap(long, verbatim_doc_comment)]
+    pub data: Option<PathBuf>,
+
+    /// Image formats to accept as source frames, e.g. `png,tga`.
+    #[clap(long, value_delimiter = ',', default_values_t = [InputFormat::Png], conflicts_with = "data")]
+    input_formats: Vec<InputFormat>,
+
+    /// Initial animation speed of the preview, it can be changed on the page.
+    /// This is identical to in-game speed. 1.0 means 60 frames per second.
+    #[clap(short = 's', long, default_value_t = 1.0, verbatim_doc_comment)]
+    pub animation_speed: f64,
+
+    /// Blend each frame with tinted ghosts of its N previous (red) and next (blue) frames.
+    #[clap(long, value_name = "N")]
+    pub onion_skin: Option<NonZeroUsize>,
+}
+
+/// Write a self-contained html page that plays the animation with speed and zoom controls.
+///
+/// # Errors
+///
+/// Fails when the frames can't be loaded or the page can't be written.
+pub fn preview(args: &PreviewArgs) -> Result<(), CommandError> {
+    let frames = if let Some(data) = &args.data {
+        frames_from_data(data, &args.source)?
+    } else {
+        let formats = args
+            .input_formats
+            .iter()
+            .map(|&format| format.into())
+            .collect::<Vec<ImageFormat>>();
+        image_util::load_from_path(&args.source, &formats)?
+    };
+
+    let Some(first) = frames.first() else {
+        warn!("no source images found");
+        return Ok(());
+    };
+    let (width, height) = first.dimensions();
+
+    if frames
+        .iter()
+        .any(|frame| frame.dimensions() != (width, height))
+    {
+        return Err(image_util::ImgUtilError::NotSameSize.into());
+    }
+
+    let frames = match args.onion_skin {
+        Some(range) => super::gif::onion_skin(&frames, range.get()),
+        None => frames,
+    };
+
+    let frame_uris = frames
+        .iter()
+        .map(png_data_uri)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let title = args.data.as_ref().unwrap_or(&args.source);
+    let title = title.file_stem().map_or_else(
+        || title.display().to_string(),
+        |name| name.to_string_lossy().to_string(),
+    );
+
+    let mut context = tera::Context::new();
+    context.insert("title", &title);
+    context.insert("width", &width);
+    context.insert("height", &height);
+    context.insert("speed", &args.animation_speed);
+    context.insert("frames", &frame_uris);
+
+    let rendered = tera::Tera::one_off(TEMPLATE, &context, true)?;
+
+    if let Some(parent) = args.output.parent() {
+        fs::create_dir_all(parent).map_err(output_error(parent))?;
+    }
+    fs::write(&args.output, rendered).map_err(output_error(&args.output))?;
+    summary::record_file(&args.output);
+
+    info!(
+        "saved preview of {} frames to {}",
+        frames.len(),
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+fn png_data_uri(frame: &RgbaImage) -> Result<String, CommandError> {
+    let mut png = Vec::new();
+    frame.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    Ok(format!("data:image/png;base64,{}", base64(&png)))
+}
+
+/// Standard base64 encoding with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or_default(),
+            chunk.get(2).copied().unwrap_or_default(),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                res.push(ALPHABET[(bits >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+
+    res
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use super::CommandError;
+use crate::config::{self, Job, PROJECT_CONFIG};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunError {
+    #[error("invalid project config {path}: {1}", path = .0.display())]
+    InvalidConfig(PathBuf, String),
+
+    #[error("no job named {0}")]
+    UnknownJob(String),
+
+    #[error("invalid arguments for job {0}: {1}")]
+    InvalidJob(String, String),
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Names of the jobs to run, the source of a job is used when it has no name [default: all]
+    pub jobs: Vec<String>,
+}
+
+/// Jobs of the project config selected by `args`, in the order of the config.
+///
+/// # Errors
+///
+/// Fails when the project config can't be read or a selected job doesn't exist.
+pub fn selected_jobs(args: &RunArgs) -> Result<Vec<Job>, CommandError> {
+    let path = PathBuf::from(PROJECT_CONFIG);
+    let jobs = config::jobs(&path).map_err(|err| RunError::InvalidConfig(path.clone(), err))?;
+
+    if let Some(unknown) = args
+        .jobs
+        .iter()
+        .find(|name| !jobs.iter().any(|job| &job.name == *name))
+    {
+        Err(RunError::UnknownJob(unknown.clone()))?;
+    }
+
+    if jobs.is_empty() {
+        warn!("{}: no jobs found", path.display());
+    }
+
+    Ok(jobs
+        .into_iter()
+        .filter(|job| args.jobs.is_empty() || args.jobs.contains(&job.name))
+        .collect())
+}
@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+
+use super::{create_output_dir, output_image_error, parse_fraction, CommandError, InputFormat};
+use crate::{image_util, summary};
+
+#[derive(Args, Debug)]
+pub struct ShadowArgs {
+    /// Image or folder of animation frames to derive the shadows from.
+    pub source: PathBuf,
+
+    /// Output folder for the shadow frames, named like the source frames.
+    pub output: PathBuf,
+
+    /// Horizontal offset of the shadow per pixel of height above the ground line.
+    /// Positive values cast the shadow to the right, like the vanilla shadows.
+    #[clap(
+        long,
+        default_value_t = 1.0,
+        allow_negative_numbers = true,
+        verbatim_doc_comment
+    )]
+    pub skew: f64,
+
+    /// Length of the shadow relative to the height of the sprite [0.0-1.0].
+    #[clap(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    pub length: f64,
+
+    /// Radius of the gaussian blur applied to the silhouette in pixels.
+    #[clap(long, default_value_t = 2.0)]
+    pub blur: f32,
+
+    /// Opacity of the shadow [0.0-1.0].
+    #[clap(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    pub opacity: f64,
+
+    /// Row of the ground line the shadow is cast from, counted from the top in pixels.
+    /// Defaults to the lowest non transparent row of all frames.
+    #[clap(long, verbatim_doc_comment)]
+    pub ground: Option<u32>,
+
+    /// Image formats to accept as source images, e.g. `png,tga`.
+    #[clap(long, value_delimiter = ',', default_values_t = [InputFormat::Png])]
+    input_formats: Vec<InputFormat>,
+}
+
+/// Derive blurred, skewed shadow frames from the alpha of the source frames.
+///
+/// The canvas grows evenly on both sides so the shadows keep the shift of the source frames.
+///
+/// # Errors
+///
+/// Fails when the frames can't be loaded, differ in size or the shadows can't be written.
+pub fn generate_shadow(args: &ShadowArgs) -> Result<(), CommandError> {
+    create_output_dir(&args.output)?;
+
+    let formats = args
+        .input_formats
+        .iter()
+        .map(|&format| format.into())
+        .collect::<Vec<ImageFormat>>();
+    let frames = image_util::load_from_path_with_path(&args.source, &formats)?;
+
+    let Some((first, _)) = frames.first() else {
+        warn!("no source images found");
+        return Ok(());
+    };
+    let (width, height) = first.dimensions();
+
+    if frames
+        .iter()
+        .any(|(frame, _)| frame.dimensions() != (width, height))
+    {
+        return Err(image_util::ImgUtilError::NotSameSize.into());
+    }
+
+    let bounds = frames.iter().fold(None, |bounds, (frame, _)| {
+        image_util::union_bounds(bounds, image_util::alpha_bounds(frame, 0))
+    });
+    let Some((_, _, _, bottom)) = bounds else {
+        warn!("all source images are empty");
+        return Ok(());
+    };
+    let ground = args
+        .ground
+        .map_or(bottom, |ground| ground.min(height.saturating_sub(1)));
+
+    // room for the skewed silhouette and the blur on both sides
+    let reach = (f64::from(ground + 1) * args.length * args.skew.abs()).ceil() as u32;
+    let padding = reach + (args.blur * 3.0).ceil() as u32;
+    let canvas = (
+        width + 2 * padding,
+        height + 2 * (args.blur * 3.0).ceil() as u32,
+    );
+
+    frames.par_iter().try_for_each(|(frame, path)| {
+        let shadow = shadow(frame, args, ground, canvas);
+
+        // the paths are image files, they always have a file name
+        let mut out = args.output.join(path.file_name().unwrap_or_default());
+        out.set_extension("png");
+        shadow.save(&out).map_err(output_image_error(&out))?;
+        summary::record_file(out);
+
+        Ok::<_, CommandError>(())
+    })?;
+
+    info!(
+        "generated {} shadow frames, canvas size: ({}px, {}px), use `spritesheet --draw-as-shadow` to turn them into a sheet",
+        frames.len(),
+        canvas.0,
+        canvas.1
+    );
+
+    Ok(())
+}
+
+fn shadow(frame: &RgbaImage, args: &ShadowArgs, ground: u32, canvas: (u32, u32)) -> RgbaImage {
+    let offset_x = f64::from((canvas.0 - frame.width()) / 2);
+    let offset_y = f64::from((canvas.1 - frame.height()) / 2);
+    let ground = f64::from(ground) + 0.5;
+
+    let silhouette = RgbaImage::from_fn(canvas.0, canvas.1, |x, y| {
+        if args.length <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        let x = f64::from(x) + 0.5 - offset_x;
+        let y = f64::from(y) + 0.5 - offset_y;
+
+        // project the shadow position back onto the upright sprite
+        let height = (ground - y) / args.length;
+        let src_x = (-height).mul_add(args.skew * args.length, x);
+        let src_y = ground - height;
+
+        if height < 0.0
+            || src_x < 0.0
+            || src_y < 0.0
+            || src_x >= f64::from(frame.width())
+            || src_y >= f64::from(frame.height())
+        {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        let alpha = f64::from(frame.get_pixel(src_x as u32, src_y as u32)[3]);
+        Rgba([0, 0, 0, (alpha * args.opacity).round() as u8])
+    });
+
+    if args.blur > 0.0 {
+        imageops::blur(&silhouette, args.blur)
+    } else {
+        silhouette
+    }
+}
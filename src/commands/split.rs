@@ -0,0 +1,717 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use image::{imageops, RgbaImage};
+
+use super::{
+    create_output_dir, direction_name, output_error, output_image_error, output_name, save_gif,
+    CommandError, EncodeArgs, FrameTimings, GIF_ALPHA_THRESHOLD,
+};
+use crate::{
+    image_util::{self, PaletteOptions, Rect, Symmetry},
+    lua::{LuaOutput, LuaParseError, LuaStyle, LuaValue},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SplitError {
+    #[error("columns and rows must be greater than 0")]
+    NoFrames,
+
+    #[error("sheet size {0}x{1} can not be divided evenly into {2} columns and {3} rows")]
+    UnevenDivision(u32, u32, u32, u32),
+
+    #[error("margin and spacing exceed the sheet size")]
+    GuttersTooLarge,
+
+    #[error("sheet only has space for {1} frames, {0} requested")]
+    TooManyFrames(u32, u32),
+
+    #[error(
+        "unsupported data file format, only spritter generated lua and json files are supported"
+    )]
+    UnsupportedDataFormat,
+
+    #[error("lua error: {0}")]
+    LuaError(#[from] LuaParseError),
+
+    #[error("data file is missing the {0} key")]
+    MissingDataKey(&'static str),
+
+    #[error("data file uses single sheet split layers which can not be split")]
+    SplitLayersNotSupported,
+
+    #[error("sheet {0} is too small for the layout described by the data file")]
+    SheetTooSmall(PathBuf),
+
+    #[error("frame_sequence references sprite {0} but only {1} sprites exist")]
+    InvalidFrameSequence(i64, u32),
+
+    #[error("{0} frames can not be divided evenly into {1} directions")]
+    UnevenDirections(usize, u32),
+
+    #[error("data file has an invalid value for the {0} key")]
+    InvalidDataValue(&'static str),
+
+    #[error("data file lists {0} sheets but {1} are needed for the layout")]
+    FileCountMismatch(u32, u32),
+
+    #[error("sprite {0} is not on any of the sheets")]
+    SpriteOutOfBounds(u32),
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug)]
+pub struct SplitArgs {
+    /// Sprite sheet to split into individual frames.
+    /// When using `--data` this is the folder containing the sheets (or one of the sheets).
+    #[clap(verbatim_doc_comment)]
+    pub source: PathBuf,
+
+    /// Output folder.
+    pub output: PathBuf,
+
+    /// Number of frame columns in the sheet.
+    #[clap(short, long, required_unless_present_any = ["data", "animated"])]
+    pub columns: Option<u32>,
+
+    /// Number of frame rows in the sheet.
+    #[clap(short, long, required_unless_present_any = ["data", "animated"])]
+    pub rows: Option<u32>,
+
+    /// Number of frames to extract, for sheets where the last row is not filled completely.
+    /// Defaults to all frames of the sheet.
+    #[clap(short = 'n', long, verbatim_doc_comment)]
+    pub count: Option<u32>,
+
+    /// Spritter generated data file (lua or json) describing the sheet layout.
+    /// The sheets are expected next to each other, named like the data file.
+    /// Frames are restored in animation order, including repeated frames from a `frame_sequence`.
+    #[clap(
+        long,
+        conflicts_with_all = ["columns", "rows", "count", "margin", "spacing"],
+        verbatim_doc_comment
+    )]
+    pub data: Option<PathBuf>,
+
+    /// Treat SOURCE as an animated GIF, PNG or WebP and extract its frames.
+    /// The frame delays are written to a lua file next to the frames, including the matching animation speed.
+    #[clap(
+        long,
+        action,
+        conflicts_with_all = ["columns", "rows", "count", "data", "margin", "spacing"],
+        verbatim_doc_comment
+    )]
+    pub animated: bool,
+
+    /// Empty border around the frames in pixels, as used by some sheet packers.
+    #[clap(long, default_value_t = 0)]
+    pub margin: u32,
+
+    /// Empty space between neighboring frames in pixels.
+    #[clap(long, default_value_t = 0)]
+    pub spacing: u32,
+
+    /// Prefix to add to the output file names.
+    #[clap(short, long, default_value_t = String::new())]
+    pub prefix: String,
+
+    /// Template for the output file names (without extension).
+    /// `{index}` is replaced with the frame index, `{index:04}` pads it with zeros to 4 digits.
+    #[clap(long, default_value = "{index}", value_parser = parse_name_template, verbatim_doc_comment)]
+    pub name_template: String,
+
+    /// Interpret the frames as a rotated animation with this many directions.
+    /// The frames of each direction are written into their own subfolder (`north`, `north-east`, ...).
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..), verbatim_doc_comment)]
+    pub directions: Option<u32>,
+
+    /// Index of the first extracted frame.
+    #[clap(long, default_value_t = 0)]
+    pub start_index: usize,
+
+    /// Also write an animated gif preview of the extracted frames.
+    #[clap(long, action)]
+    pub gif: bool,
+
+    /// Also write an animated PNG preview of the extracted frames.
+    #[clap(long, action)]
+    pub apng: bool,
+
+    /// Animation speed of the previews.
+    /// This is identical to in-game speed. 1.0 means 60 frames per second.
+    #[clap(short = 's', long, default_value_t = 1.0, verbatim_doc_comment)]
+    pub animation_speed: f64,
+
+    /// Crop the transparent border of the extracted frames.
+    /// All frames are cropped consistently, the same way the spritesheet command does.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub trim: bool,
+
+    /// Sets the max alpha value to consider a pixel as transparent when trimming [0-255].
+    #[clap(long, default_value_t = 0, requires = "trim")]
+    pub trim_alpha: u8,
+
+    /// Resolution of the extracted frames in pixels / tile, used for the shift written when trimming.
+    #[clap(short, long, default_value_t = 64, requires = "trim")]
+    pub tile_resolution: usize,
+
+    /// Optimize the extracted frames the same way generated sheets are optimized.
+    /// The encoding options below only apply when this is enabled.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub optimize: bool,
+
+    /// Allow lossy compression for the extracted frames.
+    #[clap(long, action, requires = "optimize")]
+    pub lossy: bool,
+
+    /// Quantize all frames with a shared palette when using lossy compression.
+    #[clap(long, action, requires = "lossy")]
+    pub group: bool,
+
+    #[clap(flatten)]
+    pub encode: EncodeArgs,
+}
+
+/// Layout of the frames spread over one or more sheets.
+struct SheetLayout {
+    sheets: Vec<(RgbaImage, PathBuf)>,
+    frame_width: u32,
+    frame_height: u32,
+    line_length: u32,
+    lines_per_file: u32,
+    sprite_count: u32,
+    margin: u32,
+    spacing: u32,
+
+    /// 0-based sprite index for every frame of the animation.
+    frame_sequence: Option<Vec<u32>>,
+}
+
+/// Split a sprite sheet, animation or data file layout into individual frames.
+///
+/// # Errors
+///
+/// Fails when the sheet can't be loaded, doesn't match the requested layout or the frames can't be written.
+pub fn split(args: &SplitArgs) -> Result<(), CommandError> {
+    create_output_dir(&args.output)?;
+
+    let mut delays = None;
+    let mut images = if args.animated {
+        let (images, frame_delays): (Vec<_>, Vec<_>) = image_util::load_animation(&args.source)?
+            .into_iter()
+            .unzip();
+        delays = Some(frame_delays);
+        images
+    } else {
+        let layout = if let Some(data) = &args.data {
+            layout_from_data(data, &args.source)?
+        } else {
+            layout_from_grid(args)?
+        };
+
+        debug!(
+            "frame size: {}x{}, {} per line, {} lines per file",
+            layout.frame_width, layout.frame_height, layout.line_length, layout.lines_per_file
+        );
+
+        layout.frames()?
+    };
+
+    let directions = args.directions.unwrap_or(1);
+    if !images.len().is_multiple_of(directions as usize) {
+        Err(SplitError::UnevenDirections(images.len(), directions))?;
+    }
+    let per_direction = (images.len() / directions as usize).max(1);
+
+    let original_size = images.first().map_or((0, 0), RgbaImage::dimensions);
+    let crop = if args.trim && !images.is_empty() {
+        let crop = image_util::crop_images(&mut images, args.trim_alpha, Symmetry::default())?;
+        Some(crop.unwrap_or(((0, 0, original_size.0, original_size.1), (0.0, 0.0))))
+    } else {
+        None
+    };
+
+    if delays.is_some() || crop.is_some() {
+        save_data(args, delays.as_deref(), crop, original_size)?;
+    }
+
+    write_previews(args, &images)?;
+
+    let frame_count = images.len();
+    let (width, height) = images.first().map_or((0, 0), RgbaImage::dimensions);
+
+    let frames = images
+        .into_iter()
+        .enumerate()
+        .map(|(idx, frame)| {
+            let folder = if args.directions.is_some() {
+                args.output
+                    .join(direction_name(idx / per_direction, directions))
+            } else {
+                args.output.clone()
+            };
+
+            let name =
+                render_name_template(&args.name_template, args.start_index + idx % per_direction);
+            (frame, folder.join(format!("{}{name}.png", args.prefix)))
+        })
+        .collect::<Vec<_>>();
+
+    if args.directions.is_some() {
+        for direction in 0..directions as usize {
+            create_output_dir(&args.output.join(direction_name(direction, directions)))?;
+        }
+    }
+
+    if args.optimize {
+        image_util::save_sheets(
+            &frames,
+            args.lossy,
+            args.group,
+            args.encode.options(),
+            &PaletteOptions::default(),
+        )?;
+    } else {
+        for (frame, path) in &frames {
+            frame.save(path).map_err(output_image_error(path))?;
+            crate::summary::record_file(path);
+        }
+    }
+
+    if let Some((_, (shift_x, shift_y))) = crop {
+        info!(
+            "split {} into {} frames, size: ({width}px, {height}px), shift: ({shift_x}px, {shift_y}px)",
+            args.source.display(),
+            frame_count,
+        );
+    } else {
+        info!(
+            "split {} into {} frames, size: ({width}px, {height}px)",
+            args.source.display(),
+            frame_count,
+        );
+    }
+
+    Ok(())
+}
+
+/// Frames of the sheets described by a spritter generated data file, in animation order.
+pub(super) fn frames_from_data(data: &Path, source: &Path) -> Result<Vec<RgbaImage>, CommandError> {
+    Ok(layout_from_data(data, source)?.frames()?)
+}
+
+impl SheetLayout {
+    /// All frames in animation order, including repeated frames from the `frame_sequence`.
+    fn frames(&self) -> Result<Vec<RgbaImage>, SplitError> {
+        self.frame_sequence
+            .clone()
+            .unwrap_or_else(|| (0..self.sprite_count).collect())
+            .into_iter()
+            .map(|idx| self.sprite(idx))
+            .collect()
+    }
+
+    fn sprite(&self, idx: u32) -> Result<RgbaImage, SplitError> {
+        let per_sheet = self.line_length * self.lines_per_file;
+        let sheet = self
+            .sheets
+            .get((idx / per_sheet) as usize)
+            .ok_or(SplitError::SpriteOutOfBounds(idx))?;
+        let sprite_idx = idx % per_sheet;
+
+        let x = self.margin + (sprite_idx % self.line_length) * (self.frame_width + self.spacing);
+        let y = self.margin + (sprite_idx / self.line_length) * (self.frame_height + self.spacing);
+
+        Ok(imageops::crop_imm(&sheet.0, x, y, self.frame_width, self.frame_height).to_image())
+    }
+}
+
+/// Write the frame delays of an extracted animation and the trim offsets into a lua file next to the frames.
+fn save_data(
+    args: &SplitArgs,
+    delays: Option<&[f64]>,
+    crop: Option<(Rect, (f64, f64))>,
+    original_size: (u32, u32),
+) -> Result<(), CommandError> {
+    let out = output_name(&args.source, &args.output, None, &args.prefix, "lua")?;
+    let mut data = LuaOutput::new();
+
+    if let Some(delays) = delays {
+        data = data.set("frame_count", delays.len()).set(
+            "delays",
+            LuaValue::Array(delays.iter().map(|delay| LuaValue::from(*delay)).collect()),
+        );
+
+        // factorio only supports a constant animation speed, suggest the one matching the average delay
+        let average = delays.iter().sum::<f64>() / delays.len().max(1) as f64;
+        if average > 0.0 {
+            data = data.set("animation_speed", 1000.0 / (60.0 * average));
+        }
+    }
+
+    if let Some(((crop_x, crop_y, _, _), (shift_x, shift_y))) = crop {
+        data = data
+            .set("shift", (shift_x, shift_y, args.tile_resolution))
+            .set("crop_x", crop_x)
+            .set("crop_y", crop_y)
+            .set("original_width", original_size.0)
+            .set("original_height", original_size.1);
+    }
+
+    data.save(&out, LuaStyle::default())
+        .map_err(output_error(&out))?;
+    crate::summary::record_file(&out);
+    debug!("saved frame data to {}", out.display());
+
+    Ok(())
+}
+
+fn write_previews(args: &SplitArgs, images: &[RgbaImage]) -> Result<(), CommandError> {
+    if !(args.gif || args.apng) || images.is_empty() {
+        return Ok(());
+    }
+
+    if args.animation_speed <= 0.0 {
+        warn!("animation speed must be greater than 0, skipping previews");
+        return Ok(());
+    }
+
+    if args.gif {
+        let out = output_name(&args.source, &args.output, None, &args.prefix, "gif")?;
+        save_gif(
+            images,
+            &out,
+            args.animation_speed,
+            GIF_ALPHA_THRESHOLD,
+            &FrameTimings::new(),
+        )?;
+        info!("saved gif preview to {}", out.display());
+    }
+
+    if args.apng {
+        let out = output_name(&args.source, &args.output, None, &args.prefix, "apng")?;
+        image_util::save_apng(images, &out, args.animation_speed)?;
+        info!("saved apng preview to {}", out.display());
+    }
+
+    Ok(())
+}
+
+/// Splits a name template into the literal parts and the zero padding width of each `{index}` placeholder.
+fn name_template_parts(template: &str) -> Result<Vec<(&str, Option<usize>)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{index") {
+        let after = &rest[start + "{index".len()..];
+        let Some(end) = after.find('}') else {
+            return Err(format!("unclosed placeholder in name template: {template}"));
+        };
+
+        let width = match &after[..end] {
+            "" => 0,
+            spec => spec
+                .strip_prefix(':')
+                .and_then(|width| width.parse::<usize>().ok())
+                .ok_or_else(|| format!("invalid placeholder in name template: {{index{spec}}}"))?,
+        };
+
+        parts.push((&rest[..start], Some(width)));
+        rest = &after[end + 1..];
+    }
+
+    if parts.is_empty() {
+        return Err(format!("name template must contain {{index}}: {template}"));
+    }
+
+    parts.push((rest, None));
+    Ok(parts)
+}
+
+fn parse_name_template(template: &str) -> Result<String, String> {
+    name_template_parts(template)?;
+    Ok(template.to_owned())
+}
+
+fn render_name_template(template: &str, index: usize) -> String {
+    // template was already validated by the arg parser
+    name_template_parts(template)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(literal, width)| {
+            width.map_or_else(
+                || literal.to_owned(),
+                |width| format!("{literal}{index:0width$}"),
+            )
+        })
+        .collect()
+}
+
+fn layout_from_grid(args: &SplitArgs) -> Result<SheetLayout, CommandError> {
+    let columns = args.columns.unwrap_or_default();
+    let rows = args.rows.unwrap_or_default();
+
+    if columns == 0 || rows == 0 {
+        Err(SplitError::NoFrames)?;
+    }
+
+    let sheet = image_util::load_image_from_file(&args.source)?;
+    let (width, height) = sheet.dimensions();
+    let (frame_width, frame_height, count) = divide_grid(
+        (width, height),
+        (columns, rows),
+        args.margin,
+        args.spacing,
+        args.count,
+    )?;
+
+    Ok(SheetLayout {
+        sheets: vec![(sheet, args.source.clone())],
+        frame_width,
+        frame_height,
+        line_length: columns,
+        lines_per_file: rows,
+        sprite_count: count,
+        margin: args.margin,
+        spacing: args.spacing,
+        frame_sequence: None,
+    })
+}
+
+/// Frame size and frame count of a sheet divided into a `columns` x `rows` grid.
+fn divide_grid(
+    (width, height): (u32, u32),
+    (columns, rows): (u32, u32),
+    margin: u32,
+    spacing: u32,
+    count: Option<u32>,
+) -> Result<(u32, u32, u32), SplitError> {
+    if columns == 0 || rows == 0 {
+        return Err(SplitError::NoFrames);
+    }
+
+    // size without the outer margin and the spacing between frames
+    let gutters = |frames: u32| {
+        (frames - 1)
+            .checked_mul(spacing)
+            .and_then(|spacing| spacing.checked_add(margin.checked_mul(2)?))
+    };
+    let (Some(inner_width), Some(inner_height)) = (
+        gutters(columns).and_then(|gutters| width.checked_sub(gutters)),
+        gutters(rows).and_then(|gutters| height.checked_sub(gutters)),
+    ) else {
+        return Err(SplitError::GuttersTooLarge);
+    };
+
+    if !inner_width.is_multiple_of(columns) || !inner_height.is_multiple_of(rows) {
+        return Err(SplitError::UnevenDivision(width, height, columns, rows));
+    }
+
+    let max_count = columns.saturating_mul(rows);
+    let count = count.unwrap_or(max_count);
+
+    if count > max_count {
+        return Err(SplitError::TooManyFrames(count, max_count));
+    }
+
+    Ok((inner_width / columns, inner_height / rows, count))
+}
+
+/// 0-based sprite indices of the `frame_sequence` in a data file, if it has one.
+fn frame_sequence(lua: &LuaOutput, sprite_count: u32) -> Result<Option<Vec<u32>>, SplitError> {
+    lua.get("frame_sequence")
+        .and_then(LuaValue::as_array)
+        .map(|sequence| {
+            sequence
+                .iter()
+                .map(|value| {
+                    // frame_sequence is 1-based
+                    let idx = value.as_int().unwrap_or_default();
+                    if idx < 1 || idx > i64::from(sprite_count) {
+                        Err(SplitError::InvalidFrameSequence(idx, sprite_count))
+                    } else {
+                        Ok(idx as u32 - 1)
+                    }
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+fn layout_from_data(data: &Path, source: &Path) -> Result<SheetLayout, CommandError> {
+    let lua = match data.extension().and_then(|ext| ext.to_str()) {
+        Some("lua") => LuaOutput::load(data),
+        Some("json") => LuaOutput::load_json(data),
+        _ => Err(SplitError::UnsupportedDataFormat)?,
+    }
+    .map_err(SplitError::from)?;
+
+    if lua.get("single_sheet_split_layers").is_some() {
+        Err(SplitError::SplitLayersNotSupported)?;
+    }
+
+    let get_int = |key: &'static str| -> Result<u32, SplitError> {
+        let value = lua
+            .get(key)
+            .and_then(LuaValue::as_int)
+            .ok_or(SplitError::MissingDataKey(key))?;
+        u32::try_from(value).map_err(|_| SplitError::InvalidDataValue(key))
+    };
+
+    // v2 data files don't contain any sheet layout for static sprites
+    let is_sprite = lua.get("type").and_then(LuaValue::as_str) == Some("sprite");
+    let get_layout = |key: &'static str| match get_int(key) {
+        Err(SplitError::MissingDataKey(_)) if is_sprite => Ok(1),
+        value => value,
+    };
+
+    let frame_width = get_int("width")?;
+    let frame_height = get_int("height")?;
+    let line_length = get_layout("line_length")?;
+    let lines_per_file = get_layout("lines_per_file")?;
+    // v2 data files only contain the frame count per direction
+    let sprite_count = get_layout("sprite_count").or_else(|err| {
+        let frames = get_int("frame_count").map_err(|_| err)?;
+        frames
+            .checked_mul(get_int("direction_count").unwrap_or(1))
+            .ok_or(SplitError::InvalidDataValue("direction_count"))
+    })?;
+    let file_count = match get_int("file_count") {
+        Err(SplitError::MissingDataKey(_)) => 1,
+        value => value?,
+    };
+
+    if frame_width == 0 || frame_height == 0 || line_length == 0 || lines_per_file == 0 {
+        Err(SplitError::NoFrames)?;
+    }
+
+    let per_sheet = line_length
+        .checked_mul(lines_per_file)
+        .ok_or(SplitError::InvalidDataValue("lines_per_file"))?;
+    let needed_files = sprite_count.div_ceil(per_sheet).max(1);
+    if file_count != needed_files {
+        Err(SplitError::FileCountMismatch(file_count, needed_files))?;
+    }
+
+    let frame_sequence = frame_sequence(&lua, sprite_count)?;
+
+    let folder = if source.is_dir() {
+        source
+    } else {
+        source.parent().unwrap_or_else(|| Path::new("."))
+    };
+
+    #[allow(clippy::unwrap_used)]
+    let name = data.file_stem().unwrap().to_string_lossy();
+
+    let mut sheets = Vec::with_capacity(file_count as usize);
+    for idx in 0..file_count {
+        let path = if file_count == 1 {
+            folder.join(format!("{name}.png"))
+        } else {
+            folder.join(format!("{name}-{idx}.png"))
+        };
+
+        let sheet = image_util::load_image_from_file(&path)?;
+
+        let sprites_on_sheet = sprite_count
+            .checked_sub(idx.saturating_mul(per_sheet))
+            .ok_or(SplitError::FileCountMismatch(file_count, needed_files))?
+            .min(per_sheet);
+        let needed_lines = sprites_on_sheet.div_ceil(line_length);
+        let fits = line_length
+            .min(sprites_on_sheet)
+            .checked_mul(frame_width)
+            .zip(needed_lines.checked_mul(frame_height))
+            .is_some_and(|(width, height)| sheet.width() >= width && sheet.height() >= height);
+
+        if !fits {
+            Err(SplitError::SheetTooSmall(path.clone()))?;
+        }
+
+        sheets.push((sheet, path));
+    }
+
+    Ok(SheetLayout {
+        sheets,
+        frame_width,
+        frame_height,
+        line_length,
+        lines_per_file,
+        sprite_count,
+        margin: 0,
+        spacing: 0,
+        frame_sequence,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{divide_grid, SplitError};
+
+    #[test]
+    fn even_division() {
+        assert_eq!(
+            divide_grid((256, 128), (4, 2), 0, 0, None).unwrap(),
+            (64, 64, 8)
+        );
+        assert_eq!(
+            divide_grid((256, 128), (4, 2), 0, 0, Some(5)).unwrap(),
+            (64, 64, 5)
+        );
+    }
+
+    #[test]
+    fn even_division_with_gutters() {
+        // 2px margin on each side, 4px between the 3 columns and 2 rows
+        assert_eq!(
+            divide_grid((102, 72), (3, 2), 2, 4, None).unwrap(),
+            (30, 32, 6)
+        );
+    }
+
+    #[test]
+    fn uneven_division() {
+        assert!(matches!(
+            divide_grid((100, 64), (3, 2), 0, 0, None),
+            Err(SplitError::UnevenDivision(100, 64, 3, 2))
+        ));
+        assert!(matches!(
+            divide_grid((96, 65), (3, 2), 0, 0, None),
+            Err(SplitError::UnevenDivision(96, 65, 3, 2))
+        ));
+    }
+
+    #[test]
+    fn too_many_frames() {
+        assert!(matches!(
+            divide_grid((128, 128), (2, 2), 0, 0, Some(5)),
+            Err(SplitError::TooManyFrames(5, 4))
+        ));
+    }
+
+    #[test]
+    fn zero_columns_or_rows() {
+        assert!(matches!(
+            divide_grid((128, 128), (0, 2), 0, 0, None),
+            Err(SplitError::NoFrames)
+        ));
+        assert!(matches!(
+            divide_grid((128, 128), (2, 0), 0, 0, None),
+            Err(SplitError::NoFrames)
+        ));
+    }
+
+    #[test]
+    fn gutters_too_large() {
+        assert!(matches!(
+            divide_grid((16, 16), (2, 2), 8, 4, None),
+            Err(SplitError::GuttersTooLarge)
+        ));
+        assert!(matches!(
+            divide_grid((16, 16), (2, 2), 0, u32::MAX, None),
+            Err(SplitError::GuttersTooLarge)
+        ));
+    }
+}
@@ -1,30 +1,75 @@
 use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::Write as _,
     fs,
+    num::NonZeroU32,
     path::{Path, PathBuf},
 };
 
-use clap::{builder::PossibleValue, Args, ValueEnum};
+use clap::{
+    builder::{PossibleValue, Resettable},
+    ArgAction, Args, FromArgMatches as _, ValueEnum,
+};
 use image::{
     imageops::{self, FilterType},
-    RgbaImage,
+    ImageFormat, RgbaImage,
+};
+use rayon::iter::{
+    IndexedParallelIterator as _, IntoParallelIterator as _, IntoParallelRefIterator as _,
+    ParallelIterator as _,
 };
-use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+use serde_json::json;
 use strum::{EnumIter, VariantArray};
 
-use super::{CommandError, SharedArgs};
-use crate::{commands::output_name, image_util, lua::LuaOutput};
+use super::{
+    create_output_dir, direction_index, human_readable_bytes, output_error, parse_byte_size,
+    parse_fraction, subfolders, CommandError, SharedArgs,
+};
+use crate::{
+    commands::{folder_name, output_path},
+    config,
+    image_util::{self, ColorAdjustment, ImgUtilError, Symmetry},
+    lua::LuaOutput,
+    lut::Lut,
+    summary,
+};
+
+/// Max number of packed batches of folders waiting to be saved in recursive mode.
+const PIPELINE_DEPTH: usize = 1;
+
+/// Optional file in a source folder overriding options for that folder in recursive mode.
+const OVERRIDE_FILE: &str = "spritter.override.toml";
 
 #[allow(clippy::struct_excessive_bools)]
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone)]
 pub struct SpritesheetArgs {
     // shared args
     #[clap(flatten)]
     shared: SharedArgs,
 
-    /// Recursive search for images. Each folder will be a separate sprite sheet
-    #[clap(short, long, action)]
+    /// Recursive search for images. Each folder will be a separate sprite sheet.
+    /// A `spritter.override.toml` in a folder overrides options for that folder only, e.g. `no-crop = true`.
+    #[clap(short, long, action, verbatim_doc_comment)]
     pub recursive: bool,
 
+    /// Include symlinked folders when searching recursively
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+
+    /// Write one combined data file (`spritesheets.lua`, ...) keyed by folder name when searching recursively.
+    #[clap(long, action, requires = "recursive")]
+    pub merge_data: bool,
+
+    /// Only write the combined data file, skip the data files of the individual folders.
+    #[clap(long, action, requires = "merge_data")]
+    pub no_folder_data: bool,
+
+    /// Write a report of all folders (frames, cropped size, shift, sheets, bytes) when searching recursively.
+    /// The format is picked by the extension, `.csv` or `.json`.
+    #[clap(long, value_name = "PATH", value_parser = parse_report_path, requires = "recursive", verbatim_doc_comment)]
+    pub report: Option<PathBuf>,
+
     /// Resolution of the input sprites in pixels / tile
     #[clap(short, long, default_value_t = 64)]
     pub tile_resolution: usize,
@@ -38,6 +83,79 @@ pub struct SpritesheetArgs {
     #[clap(short = 'a', long, default_value_t = 0, verbatim_doc_comment)]
     pub crop_alpha: u8,
 
+    /// Make a background color fully transparent, e.g. `#ff00ff` or `#ff00ff±8`.
+    /// The optional tolerance is the max difference per color channel [0-255].
+    #[clap(long, value_name = "COLOR", value_parser = parse_transparent_color, verbatim_doc_comment)]
+    pub transparent_color: Option<([u8; 3], u8)>,
+
+    /// Fix dark or bright halos around semi transparent edges, caused by renders that blend
+    /// the edges with the background.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub clean_fringe: bool,
+
+    /// The source frames were exported with premultiplied alpha as straight alpha (common with Blender),
+    /// restore their straight colors before the fringe cleanup and color adjustments.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub premultiplied: bool,
+
+    /// Brightness offset added to all color channels before packing, e.g. `0.1` or `-0.05`.
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub brightness: f32,
+
+    /// Contrast factor applied before packing, values > 1.0 increase the contrast.
+    #[clap(long, default_value_t = 1.0)]
+    pub contrast: f32,
+
+    /// Saturation factor applied before packing, 0.0 results in grayscale.
+    #[clap(long, default_value_t = 1.0)]
+    pub saturation: f32,
+
+    /// 3D LUT (`.cube` file) to color grade all frames with before packing.
+    /// Applied after the brightness, contrast and saturation adjustments.
+    #[clap(long, value_name = "PATH", value_parser = parse_lut, verbatim_doc_comment)]
+    pub lut: Option<Lut>,
+
+    /// Binarize the alpha channel, values below the threshold become 0 and all others 255 [0-255].
+    #[clap(long, conflicts_with = "alpha_curve")]
+    pub alpha_threshold: Option<u8>,
+
+    /// Remap the alpha channel, e.g. `16:240` turns values up to 16 into 0, values from 240 into 255
+    /// and stretches everything in between. Useful to clean up renderer noise that defeats cropping.
+    #[clap(long, value_name = "LOW:HIGH", value_parser = parse_alpha_curve, verbatim_doc_comment)]
+    pub alpha_curve: Option<(u8, u8)>,
+
+    /// Keep the crop box horizontally centered so the horizontal shift stays 0.
+    /// Use this for sprites that are mirrored horizontally in-game, like turrets and vehicles.
+    #[clap(long, action, conflicts_with = "no_crop", verbatim_doc_comment)]
+    pub keep_horizontal_symmetry: bool,
+
+    /// Keep the crop box vertically centered so the vertical shift stays 0.
+    #[clap(long, action, conflicts_with = "no_crop")]
+    pub keep_vertical_symmetry: bool,
+
+    /// Treat the center of the content as the sprite origin, for renders where the camera wasn't centered.
+    /// `centroid` uses the alpha weighted center of all frames, `bbox` the center of their bounding box.
+    /// The shift compensates the offset of the content to the canvas center.
+    #[clap(
+        long,
+        value_name = "MODE",
+        conflicts_with_all = ["no_crop", "keep_horizontal_symmetry", "keep_vertical_symmetry"],
+        verbatim_doc_comment
+    )]
+    pub auto_center: Option<AutoCenter>,
+
+    /// Color of an opaque marker pixel in every frame that defines the sprite origin, e.g. `#ff00ff`.
+    /// The frames are aligned so their markers coincide, the markers are painted over with their
+    /// surroundings and the shift places the sprite origin at the center of the marker.
+    #[clap(
+        long,
+        value_name = "COLOR",
+        value_parser = parse_transparent_color,
+        conflicts_with_all = ["auto_center", "keep_horizontal_symmetry", "keep_vertical_symmetry", "low_memory"],
+        verbatim_doc_comment
+    )]
+    pub anchor_marker: Option<([u8; 3], u8)>,
+
     /// Set a scaling factor to rescale the used sprites by.
     /// Values < 1.0 will shrink the sprites. Values > 1.0 will enlarge them.
     #[clap(short, long, default_value_t = 1.0, verbatim_doc_comment)]
@@ -47,10 +165,126 @@ pub struct SpritesheetArgs {
     #[clap(long, default_value_t = ScaleFilter::CatmullRom, verbatim_doc_comment)]
     pub scale_filter: ScaleFilter,
 
+    /// Treat the source as a rotated animation with one subfolder per direction.
+    /// The subfolders are named like the split command writes them (`north`, `north-east`, ... or `direction-0`, ...),
+    /// all directions are placed on the same sheet(s) and share the same shift.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub rotated: bool,
+
     /// Automatically split each frame into multiple subframes if the frames would not fit on a single sheet.
     /// This allows you to use large sprites for graphic types that do not allow to specify multiple files for a single layer.
     #[clap(long, action, verbatim_doc_comment)]
     pub single_sheet_split_mode: bool,
+
+    /// Keep the frames whole and let the game slice them into pieces of at most SIZE pixels instead,
+    /// like vanilla does for huge sprites. Adds `dice` / `dice_x` / `dice_y` to the data output.
+    /// Sheets may exceed the size limit when a single frame does.
+    #[clap(
+        long,
+        value_name = "SIZE",
+        num_args = 0..=1,
+        default_missing_value = "1024",
+        conflicts_with = "single_sheet_split_mode",
+        verbatim_doc_comment
+    )]
+    pub dice: Option<NonZeroU32>,
+
+    /// Force the amount of columns per sheet instead of arranging the sprites as square as possible.
+    /// The last line is padded and additional sheets are used when the lines don't fit on a single sheet.
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with = "single_sheet_split_mode",
+        verbatim_doc_comment
+    )]
+    pub line_length: Option<u32>,
+
+    /// Search all column and row counts for the layout with the fewest sheets and the least empty space,
+    /// instead of filling the sheets line by line. Ties are broken by the most square sheets.
+    #[clap(long, action, conflicts_with_all = ["line_length", "single_sheet_split_mode"], verbatim_doc_comment)]
+    pub optimize_layout: bool,
+
+    /// Decode the frames again for every sheet instead of keeping all of them in memory.
+    /// Trades speed for bounded memory usage, with `--lossy` every sheet is quantized on its own.
+    #[clap(long, action, conflicts_with_all = ["rotated", "single_sheet_split_mode"], verbatim_doc_comment)]
+    pub low_memory: bool,
+
+    /// Memory budget per sheet generation, e.g. `8GB`.
+    /// Switches to `--low-memory` when the estimated peak memory usage exceeds it and aborts if even that doesn't fit.
+    #[clap(long, value_name = "SIZE", value_parser = parse_byte_size, verbatim_doc_comment)]
+    pub max_memory: Option<u64>,
+
+    /// Only report isolated pixels with a tiny alpha value that grow the crop box, no sheets are generated.
+    /// Pixels count as stray when their alpha is above `--crop-alpha` but at most 16 and all neighbors are transparent.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub lint: bool,
+
+    /// Mark the sprites as shadows, adds `draw_as_shadow = true` to the data output.
+    /// Use this for sheets of frames generated with the shadow command.
+    #[clap(long, action, verbatim_doc_comment)]
+    pub draw_as_shadow: bool,
+
+    /// Also export a single frame as standalone sprite cropped to its own content, e.g. for remnants or map icons.
+    /// Accepts `first`, `last` or the frame index starting at 0, the sprite is added as `still_frame` to the data output.
+    #[clap(long, value_name = "FRAME", value_parser = parse_frame_index, verbatim_doc_comment)]
+    pub still_frame: Option<FrameIndex>,
+
+    /// Also export a remnant sprite of a single frame, desaturated, darkened and optionally scorched.
+    /// Defaults to the last frame, accepts `first`, `last` or the frame index starting at 0.
+    /// The sprite is added as `remnant` to the data output.
+    #[clap(
+        long,
+        value_name = "FRAME",
+        num_args = 0..=1,
+        default_missing_value = "last",
+        value_parser = parse_frame_index,
+        verbatim_doc_comment
+    )]
+    pub remnant: Option<FrameIndex>,
+
+    /// Amount of saturation removed from the remnant [0.0-1.0].
+    #[clap(long, default_value_t = 0.7, value_parser = parse_fraction, requires = "remnant")]
+    pub remnant_desaturate: f64,
+
+    /// Amount of brightness removed from the remnant [0.0-1.0].
+    #[clap(long, default_value_t = 0.4, value_parser = parse_fraction, requires = "remnant")]
+    pub remnant_darken: f64,
+
+    /// Scorch mark image at the output resolution, drawn over the remnant centered on the sprite origin.
+    /// Only the parts covering the remnant are visible.
+    #[clap(long, value_name = "PATH", requires = "remnant", verbatim_doc_comment)]
+    pub remnant_scorch: Option<PathBuf>,
+}
+
+/// Frame of an animation selected on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameIndex {
+    First,
+    Last,
+    Nth(usize),
+}
+
+impl FrameIndex {
+    /// Index of the frame in an animation of `count` frames, `None` if it is out of range.
+    const fn resolve(self, count: usize) -> Option<usize> {
+        match self {
+            _ if count == 0 => None,
+            Self::First => Some(0),
+            Self::Last => Some(count - 1),
+            Self::Nth(idx) if idx < count => Some(idx),
+            Self::Nth(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::First => write!(f, "first"),
+            Self::Last => write!(f, "last"),
+            Self::Nth(idx) => write!(f, "{idx}"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
@@ -62,10 +296,45 @@ pub enum ScaleFilter {
     Lanczos3,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, VariantArray)]
+pub enum AutoCenter {
+    Centroid,
+    Bbox,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum SpriteSheetError {
     #[error("all source images must be the same size")]
     ImagesNotSameSize,
+
+    #[error("no direction folders found")]
+    NoDirections,
+
+    #[error("unknown direction folder: {0}")]
+    UnknownDirection(String),
+
+    #[error("direction {0} has {1} frames, expected {2}")]
+    DirectionFrameCountMismatch(String, usize, usize),
+
+    #[error("estimated memory usage of {0} exceeds the limit of {1}")]
+    MemoryLimitExceeded(String, String),
+
+    #[error("line length of {0} needs a sheet width of {1}px, the limit is {max}px", max = MAX_SIZE)]
+    LineLengthTooLarge(u32, u64),
+
+    #[error("invalid override file {0}: {1}")]
+    InvalidOverride(PathBuf, String),
+
+    #[error("frame {0} has {1} anchor marker pixels, expected exactly 1")]
+    AnchorMarkerCount(usize, usize),
+
+    #[error("frame {0} is out of range, the animation has {1} frames")]
+    FrameOutOfRange(FrameIndex, usize),
+
+    #[error(
+        "--palette-out can not be used with --recursive, every folder is quantized on its own"
+    )]
+    PaletteOutRecursive,
 }
 
 impl std::fmt::Display for ScaleFilter {
@@ -108,6 +377,28 @@ impl ValueEnum for ScaleFilter {
     }
 }
 
+impl std::fmt::Display for AutoCenter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Centroid => write!(f, "centroid"),
+            Self::Bbox => write!(f, "bbox"),
+        }
+    }
+}
+
+impl ValueEnum for AutoCenter {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Centroid => "centroid",
+            Self::Bbox => "bbox",
+        }))
+    }
+}
+
 impl std::ops::Deref for SpritesheetArgs {
     type Target = SharedArgs;
 
@@ -116,26 +407,34 @@ impl std::ops::Deref for SpritesheetArgs {
     }
 }
 
+impl std::ops::DerefMut for SpritesheetArgs {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shared
+    }
+}
+
 impl SpritesheetArgs {
+    /// Generate the sheets of the source folder, or of every subfolder with `--recursive`.
+    ///
+    /// # Errors
+    ///
+    /// Fails when a folder can't be generated, with `--recursive` [`CommandError::FoldersFailed`]
+    /// is returned after all other folders were generated.
     pub fn execute(&self) -> Result<(), CommandError> {
-        fs::create_dir_all(&self.output)?;
+        if self.check {
+            crate::check::enable();
+        }
 
-        if !self.output.is_dir() {
-            return Err(CommandError::OutputPathNotDir);
+        if self.recursive && self.palette_out.is_some() {
+            Err(SpriteSheetError::PaletteOutRecursive)?;
         }
 
-        let sources = if self.recursive {
-            fs::read_dir(&self.source)?
-                .filter_map(|entry| {
-                    let path = entry.ok()?.path();
+        create_output_dir(&self.output)?;
 
-                    if path.is_dir() {
-                        Some(path)
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>()
+        let sources = if self.recursive {
+            // symlinked folders may point to the same folder or the source itself
+            let mut visited = HashSet::from([self.source.canonicalize()?]);
+            subfolders(&self.source, self.follow_symlinks, &mut visited)?
         } else {
             vec![self.source.clone()]
         };
@@ -145,98 +444,582 @@ impl SpritesheetArgs {
             return Ok(());
         }
 
-        let _ = sources
-            .par_iter()
-            .filter_map(|source| match generate_spritesheet(self, source) {
-                Ok(res_name) => {
-                    if res_name.is_empty() {
-                        None
-                    } else {
-                        Some(res_name)
-                    }
+        let names = self.unique_names(&sources)?;
+        let lock = self.lock_sources(
+            &names
+                .iter()
+                .cloned()
+                .zip(sources.iter().map(PathBuf::as_path))
+                .collect::<Vec<_>>(),
+            self.rotated,
+        )?;
+
+        // only work on a single folder at a time to keep the memory bounded
+        let results = if self.low_memory {
+            sources
+                .iter()
+                .zip(&names)
+                .map(|(source, name)| {
+                    generate_folder(&*self.folder_args(source)?, source, name.clone())
+                })
+                .collect::<Vec<_>>()
+        } else {
+            self.pipelined(&sources, &names)
+        };
+
+        let mut generated = Vec::with_capacity(results.len());
+        let mut reports = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for (source, res) in sources.iter().zip(results) {
+            match res {
+                Ok(Some((name, data, report))) => {
+                    reports.push((name.clone(), report));
+                    generated.push((name, data));
                 }
-                Err(err) => {
-                    error!("{}: {err}", source.display());
-                    None
+                Ok(None) => {}
+                Err(err) => errors.push((source, err)),
+            }
+        }
+
+        if !self.recursive && !errors.is_empty() {
+            return Err(errors.swap_remove(0).1);
+        }
+
+        if self.merge_data && !self.lint {
+            let merged = generated
+                .into_iter()
+                .fold(LuaOutput::new(), |merged, (name, data)| {
+                    merged.set(name, data)
+                });
+
+            self.save_data_to(
+                |extension| {
+                    let mut out = self.output.join(format!("{}spritesheets", self.prefix));
+                    out.set_extension(extension);
+                    Ok(out)
+                },
+                &merged,
+                &[],
+            )?;
+        }
+
+        if let Some(path) = &self.report {
+            write_report(path, &reports)?;
+        }
+
+        for (source, err) in &errors {
+            error!("{}: {err}", source.display());
+            summary::record_failure(Some(source), err);
+        }
+
+        let failed = errors.len();
+        if let Some((_, first)) = errors.into_iter().next() {
+            return Err(CommandError::FoldersFailed(
+                failed,
+                sources.len(),
+                Box::new(first),
+            ));
+        }
+
+        if let Some(lock) = lock {
+            let generated = reports
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            lock.save(&generated)?;
+        }
+
+        Ok(())
+    }
+
+    /// Generate the folders in two stages, the next folders are loaded and packed
+    /// while the sheets of the previous ones are compressed and saved.
+    ///
+    /// Both stages work on a batch of folders in parallel, at most [`PIPELINE_DEPTH`]
+    /// packed batches wait for saving to keep the memory bounded.
+    fn pipelined(&self, sources: &[PathBuf], names: &[String]) -> Vec<FolderResult> {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(PIPELINE_DEPTH);
+
+        // as many folders as generating all of them in parallel keeps in memory at once,
+        // only the plain threads of both stages block on the channel, never the rayon workers
+        let batch = rayon::current_num_threads().max(1);
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for (sources, names) in sources.chunks(batch).zip(names.chunks(batch)) {
+                    let packed = sources
+                        .par_iter()
+                        .zip(names.par_iter())
+                        .map(|(source, name)| -> Result<_, CommandError> {
+                            let args = self.folder_args(source)?;
+                            let packed = pack_folder(&args, source, name.clone())?;
+                            Ok(packed.map(|packed| (args, packed)))
+                        })
+                        .collect::<Vec<_>>();
+
+                    // the receiver only hangs up on panics
+                    if sender.send(packed).is_err() {
+                        break;
+                    }
                 }
-            })
+            });
+
+            receiver
+                .into_iter()
+                .flat_map(|packed| {
+                    packed
+                        .into_par_iter()
+                        .map(|packed| match packed? {
+                            Some((args, packed)) => save_folder(&args, packed).map(Some),
+                            None => Ok(None),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        })
+    }
+
+    /// Output names of the source folders.
+    ///
+    /// Folders resolving to the same name (symlinks, names only differing in case) are named
+    /// after their path relative to the source instead, so no sheet overwrites another one.
+    fn unique_names(&self, sources: &[PathBuf]) -> Result<Vec<String>, CommandError> {
+        let names = sources
+            .iter()
+            .map(folder_name)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // compare case insensitive, not every file system tells the names apart
+        let count = |names: &[String], name: &str| {
+            names
+                .iter()
+                .filter(|other| other.to_lowercase() == name.to_lowercase())
+                .count()
+        };
+
+        let colliding = names
+            .iter()
+            .map(|name| count(&names, name) > 1)
             .collect::<Vec<_>>();
 
-        Ok(())
+        let mut unique = names.clone();
+        for (idx, source) in sources.iter().enumerate() {
+            if colliding[idx] {
+                let relative = source.strip_prefix(&self.source).unwrap_or(source);
+                unique[idx] = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("_");
+            }
+        }
+
+        // relative paths can still collide in case
+        for idx in 0..unique.len() {
+            let base = unique[idx].clone();
+            let mut suffix = 1;
+            while count(&unique[..idx], &unique[idx]) > 0 {
+                suffix += 1;
+                unique[idx] = format!("{base}_{suffix}");
+            }
+        }
+
+        for ((source, name), unique) in sources.iter().zip(&names).zip(&unique) {
+            if name != unique {
+                warn!(
+                    "{}: output name {name} is used by multiple folders, using {unique} instead",
+                    source.display()
+                );
+            }
+        }
+
+        Ok(unique)
+    }
+
+    /// Args for `source` with the options of its override file applied, in recursive mode only.
+    fn folder_args(&self, source: &Path) -> Result<Cow<'_, Self>, CommandError> {
+        let path = source.join(OVERRIDE_FILE);
+        if !self.recursive || !path.is_file() {
+            return Ok(Cow::Borrowed(self));
+        }
+
+        let invalid = |msg: String| SpriteSheetError::InvalidOverride(path.clone(), msg);
+        let table = fs::read_to_string(&path)?
+            .parse::<toml::Table>()
+            .map_err(|err| invalid(err.message().to_owned()))?;
+
+        let mut tokens = Vec::new();
+        for (key, value) in &table {
+            let values = match value {
+                toml::Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+
+            for value in values {
+                let value = config::value_to_string(value)
+                    .ok_or_else(|| invalid(format!("unsupported value for {key}")))?;
+                tokens.push(format!("--{key}"));
+                tokens.push(value);
+            }
+        }
+
+        // without defaults only the overridden options are present in the matches,
+        // flags take an explicit value so they can be turned off as well
+        let cmd =
+            Self::augment_args(clap::Command::new(env!("CARGO_PKG_NAME")).no_binary_name(true))
+                .mut_args(|arg| {
+                    let arg = arg
+                        .required(false)
+                        .default_values(Vec::<&str>::new())
+                        .requires(Resettable::Reset)
+                        .conflicts_with(Resettable::Reset);
+
+                    if matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse) {
+                        arg.action(ArgAction::Set)
+                            .value_parser(clap::value_parser!(bool))
+                    } else {
+                        arg
+                    }
+                });
+
+        let clap_error = |err: clap::Error| {
+            let msg = err.to_string();
+            let msg = msg.lines().next().unwrap_or_default();
+            invalid(msg.trim_start_matches("error: ").to_owned())
+        };
+
+        let matches = cmd.try_get_matches_from(tokens).map_err(clap_error)?;
+        let mut args = self.clone();
+        args.update_from_arg_matches(&matches).map_err(clap_error)?;
+
+        debug!("{}: applied {OVERRIDE_FILE}", source.display());
+        Ok(Cow::Owned(args))
+    }
+
+    const fn symmetry(&self) -> Symmetry {
+        Symmetry {
+            horizontal: self.keep_horizontal_symmetry,
+            vertical: self.keep_vertical_symmetry,
+        }
     }
 
     fn tile_res(&self) -> usize {
         (self.tile_resolution as f64 * self.scale).round() as usize
     }
+
+    /// Apply the color keying, unpremultiplying, fringe cleanup, color adjustments, LUT, scaling and alpha remapping to a source frame.
+    fn prepared(&self, mut image: RgbaImage) -> RgbaImage {
+        if let Some((color, tolerance)) = self.transparent_color {
+            image_util::key_color(&mut image, color, tolerance);
+        }
+
+        if self.premultiplied {
+            image_util::unpremultiply(&mut image);
+        }
+
+        if self.clean_fringe {
+            image_util::clean_fringe(&mut image);
+        }
+
+        let adjustment = ColorAdjustment {
+            brightness: self.brightness,
+            contrast: self.contrast,
+            saturation: self.saturation,
+        };
+        if !adjustment.is_identity() {
+            adjustment.apply(&mut image);
+        }
+
+        if let Some(lut) = &self.lut {
+            lut.apply(&mut image);
+        }
+
+        let mut image = self.scaled(image);
+        if let Some(lut) = self.alpha_lut() {
+            image_util::map_alpha(&mut image, &lut);
+        }
+
+        image
+    }
+
+    /// Lookup table for `--alpha-threshold` / `--alpha-curve`.
+    fn alpha_lut(&self) -> Option<[u8; 256]> {
+        let lut = match (self.alpha_threshold, self.alpha_curve) {
+            (Some(threshold), _) => {
+                std::array::from_fn(|alpha| if alpha < threshold.into() { 0 } else { 255 })
+            }
+            (None, Some((low, high))) => {
+                let (low, high) = (usize::from(low), usize::from(high));
+                std::array::from_fn(|alpha| {
+                    ((alpha.clamp(low, high) - low) * 255 / (high - low)) as u8
+                })
+            }
+            (None, None) => return None,
+        };
+
+        Some(lut)
+    }
+
+    /// Rescale `image` by the configured scaling factor.
+    fn scaled(&self, image: RgbaImage) -> RgbaImage {
+        if (self.scale - 1.0).abs() <= f64::EPSILON {
+            return image;
+        }
+
+        let (width, height) = image.dimensions();
+        let width = (f64::from(width) * self.scale).round() as u32;
+        let height = (f64::from(height) * self.scale).round() as u32;
+
+        imageops::resize(&image, width, height, self.scale_filter.into())
+    }
+}
+
+/// Parse `#rrggbb` with an optional `±tolerance` (or `+-tolerance`).
+fn parse_transparent_color(value: &str) -> Result<([u8; 3], u8), String> {
+    let (color, tolerance) = value
+        .split_once('±')
+        .or_else(|| value.split_once("+-"))
+        .unwrap_or((value, "0"));
+
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("invalid color: {color}, expected #rrggbb"));
+    }
+
+    let channel = |idx: usize| {
+        u8::from_str_radix(&hex[idx..idx + 2], 16)
+            .map_err(|_| format!("invalid color: {color}, expected #rrggbb"))
+    };
+    let tolerance = tolerance
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| format!("invalid tolerance: {tolerance}"))?;
+
+    Ok(([channel(0)?, channel(2)?, channel(4)?], tolerance))
+}
+
+fn parse_frame_index(value: &str) -> Result<FrameIndex, String> {
+    match value.trim() {
+        "first" => Ok(FrameIndex::First),
+        "last" => Ok(FrameIndex::Last),
+        idx => idx
+            .parse()
+            .map(FrameIndex::Nth)
+            .map_err(|_| format!("invalid frame: {value}, expected first, last or an index")),
+    }
+}
+
+fn parse_report_path(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv" | "json") => Ok(path),
+        _ => Err("the report must be a .csv or .json file".to_owned()),
+    }
+}
+
+fn parse_lut(path: &str) -> Result<Lut, String> {
+    Lut::load(path).map_err(|err| format!("{path}: {err}"))
+}
+
+fn parse_alpha_curve(value: &str) -> Result<(u8, u8), String> {
+    let invalid = || format!("invalid alpha curve: {value}, expected LOW:HIGH");
+    let (low, high) = value.split_once(':').ok_or_else(invalid)?;
+    let low = low.trim().parse::<u8>().map_err(|_| invalid())?;
+    let high = high.trim().parse::<u8>().map_err(|_| invalid())?;
+
+    if low >= high {
+        return Err(format!(
+            "alpha curve low value {low} must be below the high value {high}"
+        ));
+    }
+
+    Ok((low, high))
 }
 
 /// Maximum side length of a single graphic file to load in Factorio
-static MAX_SIZE: u32 = 8192;
+pub(super) static MAX_SIZE: u32 = 8192;
 
-#[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
-fn generate_spritesheet(
+/// Name and data of a generated sprite, `None` if the source folder had no images.
+pub type SheetResult = Option<(String, LuaOutput)>;
+
+/// Generate the sheet(s) for a single folder, returns the sheet name and its data.
+///
+/// # Errors
+///
+/// Fails when the frames can't be loaded, don't fit the sheet limits or the outputs can't be written.
+pub fn generate_spritesheet(
     args: &SpritesheetArgs,
     path: impl AsRef<Path>,
-) -> Result<String, CommandError> {
+) -> Result<SheetResult, CommandError> {
     let source = path.as_ref();
-    let mut images = image_util::load_from_path(source)?;
+    Ok(generate_folder(args, source, folder_name(source)?)?.map(|(name, data, _)| (name, data)))
+}
 
-    if images.is_empty() {
-        warn!("{}: no source images found", source.display());
-        return Ok(String::new());
+/// Stats of a generated folder for `--report`.
+struct FolderReport {
+    frames: u32,
+    size: (u32, u32),
+    shift: (f64, f64),
+    files: Vec<PathBuf>,
+}
+
+/// Name, data and report of a generated folder.
+type FolderResult = Result<Option<(String, LuaOutput, FolderReport)>, CommandError>;
+
+type SpriteSummary = (String, (u32, u32), (f64, f64));
+
+/// Arranged sheets of a folder that still have to be saved.
+struct PackedFolder {
+    name: String,
+    data: LuaOutput,
+    report: FolderReport,
+
+    /// Sheets not saved during packing, empty with `--low-memory`.
+    sheets: Vec<(RgbaImage, PathBuf)>,
+
+    /// Name, size and shift of the generated sprites for the run summary.
+    sprites: Vec<SpriteSummary>,
+    completed: String,
+}
+
+fn generate_folder(args: &SpritesheetArgs, source: &Path, name: String) -> FolderResult {
+    pack_folder(args, source, name)?.map_or(Ok(None), |packed| save_folder(args, packed).map(Some))
+}
+
+/// Save the sheets and data of a packed folder.
+fn save_folder(
+    args: &SpritesheetArgs,
+    packed: PackedFolder,
+) -> Result<(String, LuaOutput, FolderReport), CommandError> {
+    if !packed.sheets.is_empty() {
+        image_util::save_sheets(
+            &packed.sheets,
+            args.lossy,
+            true,
+            args.encode.options(),
+            &args.palette(),
+        )?;
     }
 
-    // scale images
-    if (args.scale - 1.0).abs() > f64::EPSILON {
-        for image in &mut images {
-            let (width, height) = image.dimensions();
-            let width = (f64::from(width) * args.scale).round() as u32;
-            let height = (f64::from(height) * args.scale).round() as u32;
+    if !args.no_folder_data {
+        args.save_data(&packed.name, &packed.data, &packed.report.files)?;
+    }
 
-            *image = imageops::resize(image, width, height, args.scale_filter.into());
-        }
+    for (name, (width, height), shift) in &packed.sprites {
+        summary::record_sprite(name, *width, *height, *shift);
+    }
+
+    info!("{}", packed.completed);
+    Ok((packed.name, packed.data, packed.report))
+}
+
+/// Load the frames of a folder and arrange them on sheets.
+#[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
+fn pack_folder(
+    args: &SpritesheetArgs,
+    source: &Path,
+    name: String,
+) -> Result<Option<PackedFolder>, CommandError> {
+    if args.lint {
+        lint(args, source)?;
+        return Ok(None);
     }
 
-    let (shift_x, shift_y) = if args.no_crop {
-        (0.0, 0.0)
+    let low_memory = use_low_memory(args, source)?;
+    let frames = if low_memory {
+        scan_frames(args, source)?
     } else {
-        image_util::crop_images(&mut images, args.crop_alpha)?
+        load_frames(args, source)?
     };
 
-    #[allow(clippy::unwrap_used)]
-    let (sprite_width, sprite_height) = images.first().unwrap().dimensions();
-    let sprite_count = images.len() as u32;
-
-    let max_cols_per_sheet = MAX_SIZE / sprite_width;
-    let max_rows_per_sheet = MAX_SIZE / sprite_height;
+    let Some((frames, direction_count, crop, provenance)) = frames else {
+        warn!("{}: no source images found", source.display());
+        return Ok(None);
+    };
+    let (shift_x, shift_y) = crop.shift;
+
+    let (sprite_width, sprite_height) = frames.size();
+    let sprite_count = frames.len() as u32;
+    let singles = single_frames(args, &frames, crop, &name)?;
+
+    // rotated animations put every direction on its own line, so each direction is a single stripe
+    let line_length = args.line_length.or_else(|| {
+        let frame_count = sprite_count / direction_count?;
+        let fits = frame_count
+            .checked_mul(sprite_width)
+            .is_some_and(|width| width <= MAX_SIZE);
+        (!args.optimize_layout && fits).then_some(frame_count)
+    });
+
+    // diced frames may exceed the size limit, the game slices them into smaller pieces
+    let min_per_sheet = u32::from(args.dice.is_some());
+    let max_cols_per_sheet = match line_length {
+        Some(cols)
+            if cols > min_per_sheet
+                && cols
+                    .checked_mul(sprite_width)
+                    .is_none_or(|width| width > MAX_SIZE) =>
+        {
+            Err(SpriteSheetError::LineLengthTooLarge(
+                cols,
+                u64::from(cols) * u64::from(sprite_width),
+            ))?
+        }
+        Some(cols) => cols,
+        None => (MAX_SIZE / sprite_width).max(min_per_sheet),
+    };
+    let max_rows_per_sheet = (MAX_SIZE / sprite_height).max(min_per_sheet);
+
+    let optimized = args.optimize_layout.then(|| {
+        let layout = Layout::optimized(
+            sprite_count,
+            (sprite_width, sprite_height),
+            max_cols_per_sheet,
+            max_rows_per_sheet,
+        );
+        info!(
+            "{}: optimized layout: {}x{} sprites per sheet, {} sheets, {} empty slots",
+            source.display(),
+            layout.cols,
+            layout.rows,
+            layout.sheets,
+            layout.empty
+        );
+        layout
+    });
+    let (max_cols_per_sheet, max_rows_per_sheet) = optimized
+        .map_or((max_cols_per_sheet, max_rows_per_sheet), |layout| {
+            (layout.cols, layout.rows)
+        });
     let max_per_sheet = max_rows_per_sheet * max_cols_per_sheet;
 
-    let sheet_count = images.len() / max_per_sheet as usize
-        + usize::from(images.len().rem_euclid(max_per_sheet as usize) > 0);
-
-    #[allow(clippy::unwrap_used)]
-    let name = source
-        .canonicalize()?
-        .components()
-        .last()
-        .unwrap()
-        .as_os_str()
-        .to_string_lossy()
-        .to_string();
+    let sheet_count = frames.len() / max_per_sheet as usize
+        + usize::from(frames.len().rem_euclid(max_per_sheet as usize) > 0);
 
     if args.single_sheet_split_mode && sheet_count > 1 {
         debug!("sprites don't fit on a single sheet, splitting into multiple layers");
+        let Frames::Loaded(images) = &frames else {
+            unreachable!("--low-memory conflicts with --single-sheet-split-mode");
+        };
         let layers =
-            generate_subframe_sheets(args, &images, sprite_width, sprite_height, shift_x, shift_y);
+            generate_subframe_sheets(args, images, sprite_width, sprite_height, shift_x, shift_y);
         let mut lua_layers = Vec::with_capacity(layers.len());
         let mut sheets = Vec::with_capacity(layers.len());
 
         for (idx, layer) in layers.iter().enumerate() {
-            let (sheet, (width, height), (shift_x, shift_y), (cols, rows)) = layer;
-            let out = output_name(source, &args.output, Some(idx), &args.prefix, "png")?;
+            let (sheet, (width, height), (shift_x, shift_y), (cols, rows), (frag_x, frag_y)) =
+                layer;
+            let out = output_path(&name, &args.output, Some(idx), &args.prefix, "png");
 
-            lua_layers.push(
+            #[allow(clippy::unwrap_used)]
+            let filename = out.file_name().unwrap().to_string_lossy().to_string();
+
+            lua_layers.push(rotation_data(
                 LuaOutput::new()
+                    .set("index", idx)
+                    .set("filename", filename)
+                    .set("frag_x", *frag_x)
+                    .set("frag_y", *frag_y)
                     .set("width", *width)
                     .set("height", *height)
                     .set("shift", (*shift_x, *shift_y, args.tile_res()))
@@ -244,31 +1027,52 @@ fn generate_spritesheet(
                     .set("sprite_count", sprite_count)
                     .set("line_length", *cols)
                     .set("lines_per_file", *rows),
-            );
+                sprite_count,
+                direction_count,
+            ));
 
             sheets.push((sheet.clone(), out));
         }
 
-        image_util::save_sheets(&sheets, args.lossy, true)?;
-
-        if args.lua {
+        let mut data = crop.data(
             LuaOutput::new()
+                .set("type", "animation")
                 .set("single_sheet_split_layers", lua_layers.into_boxed_slice())
-                .save(output_name(
-                    source,
-                    &args.output,
-                    None,
-                    &args.prefix,
-                    "lua",
-                )?)?;
+                .set_opt("draw_as_shadow", args.draw_as_shadow.then_some(true))
+                .set_opt("provenance", provenance),
+        );
+        let report = FolderReport {
+            frames: sprite_count,
+            size: (sprite_width, sprite_height),
+            shift: (shift_x, shift_y),
+            files: sheets.iter().map(|(_, path)| path.clone()).collect(),
+        };
+        let mut sprites = layers
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, size, shift, _, _))| {
+                (format!("{}{name}-{idx}", args.prefix), *size, *shift)
+            })
+            .collect::<Vec<_>>();
+        for single in singles {
+            data = data.set(single.key, single.data);
+            sheets.push(single.sheet);
+            sprites.push(single.sprite);
         }
-
-        info!(
+        let completed = format!(
             "completed {}{name}, split into {} layers",
             args.prefix,
             layers.len()
         );
-        return Ok(name);
+
+        return Ok(Some(PackedFolder {
+            name,
+            data,
+            report,
+            sheets,
+            sprites,
+            completed,
+        }));
     }
 
     // unnecessarily overengineered PoS to calculate special sheet sizes if only 1 sheet is needed
@@ -283,6 +1087,27 @@ fn generate_spritesheet(
                 max_rows_per_sheet,
                 max_per_sheet,
             )
+        } else if let Some(layout) = optimized {
+            debug!("singular optimized sheet: {}x{}", layout.cols, layout.rows);
+
+            (
+                sprite_width * layout.cols,
+                sprite_height * layout.rows,
+                layout.cols,
+                layout.rows,
+                layout.cols * layout.rows,
+            )
+        } else if let Some(cols) = line_length {
+            let rows = sprite_count.div_ceil(cols);
+            debug!("singular sheet with fixed line length: {cols}x{rows}");
+
+            (
+                sprite_width * cols,
+                sprite_height * rows,
+                cols,
+                rows,
+                cols * rows,
+            )
         } else {
             // everything can fit 1 sheet -> custom arrange in as square as possible
             let mut cols = 1;
@@ -318,79 +1143,109 @@ fn generate_spritesheet(
 
     debug!("sheet size: {sheet_width}x{sheet_height}");
 
+    // last sheet can be smaller
+    let mut last_count = sprite_count % max_per_sheet;
+    if last_count == 0 {
+        last_count = max_per_sheet;
+    }
+    let last_height =
+        sprite_height * (f64::from(last_count) / f64::from(max_cols_per_sheet)).ceil() as u32;
+
     let mut sheets: Vec<(RgbaImage, PathBuf)> = Vec::with_capacity(sheet_count);
+    let mut files = Vec::with_capacity(sheet_count);
 
-    if sheet_count == 1 {
-        sheets.push((
-            RgbaImage::new(sheet_width, sheet_height),
-            output_name(source, &args.output, None, &args.prefix, "png")?,
-        ));
-    } else {
-        for idx in 0..(sheet_count - 1) {
-            sheets.push((
+    for sheet_idx in 0..sheet_count {
+        let (mut sheet, path) = if sheet_count == 1 {
+            (
                 RgbaImage::new(sheet_width, sheet_height),
-                output_name(source, &args.output, Some(idx), &args.prefix, "png")?,
-            ));
-        }
+                output_path(&name, &args.output, None, &args.prefix, "png"),
+            )
+        } else {
+            let height = if sheet_idx == sheet_count - 1 {
+                last_height
+            } else {
+                sheet_height
+            };
 
-        // last sheet can be smaller
-        let mut last_count = sprite_count % max_per_sheet;
-        if last_count == 0 {
-            last_count = max_per_sheet;
-        }
+            (
+                RgbaImage::new(sheet_width, height),
+                output_path(&name, &args.output, Some(sheet_idx), &args.prefix, "png"),
+            )
+        };
 
-        sheets.push((
-            RgbaImage::new(
-                sheet_width,
-                sprite_height
-                    * (f64::from(last_count) / f64::from(max_cols_per_sheet)).ceil() as u32,
-            ),
-            output_name(
-                source,
-                &args.output,
-                Some(sheet_count - 1),
-                &args.prefix,
-                "png",
-            )?,
-        ));
-    }
+        // arrange sprites on sheet
+        let first = sheet_idx * max_per_sheet as usize;
+        let last = frames.len().min(first + max_per_sheet as usize);
+        for idx in first..last {
+            let sprite = frames.get(args, idx)?;
+            if sprite.width() != sprite_width || sprite.height() != sprite_height {
+                Err(SpriteSheetError::ImagesNotSameSize)?;
+            }
 
-    // arrange sprites on sheets
-    for (idx, sprite) in images.iter().enumerate() {
-        if sprite.width() != sprite_width || sprite.height() != sprite_height {
-            Err(SpriteSheetError::ImagesNotSameSize)?;
-        }
+            let sprite_idx = (idx - first) as u32;
 
-        let sheet_idx = idx / max_per_sheet as usize;
-        let sprite_idx = idx as u32 % max_per_sheet;
+            let row = sprite_idx % cols_per_sheet;
+            let line = sprite_idx / cols_per_sheet;
 
-        let row = sprite_idx % cols_per_sheet;
-        let line = sprite_idx / cols_per_sheet;
+            let x = row * sprite_width;
+            let y = line * sprite_height;
 
-        let x = row * sprite_width;
-        let y = line * sprite_height;
+            imageops::replace(&mut sheet, sprite.as_ref(), i64::from(x), i64::from(y));
+        }
 
-        imageops::replace(&mut sheets[sheet_idx].0, sprite, i64::from(x), i64::from(y));
+        files.push(path.clone());
+
+        if low_memory {
+            // save right away so only a single sheet is kept in memory
+            image_util::save_sheets(
+                &[(sheet, path)],
+                args.lossy,
+                true,
+                args.encode.options(),
+                &args.palette(),
+            )?;
+        } else {
+            sheets.push((sheet, path));
+        }
     }
 
-    // save sheets
-    image_util::save_sheets(&sheets, args.lossy, true)?;
+    let mut sprites = vec![(
+        format!("{}{name}", args.prefix),
+        (sprite_width, sprite_height),
+        (shift_x, shift_y),
+    )];
 
-    if args.no_crop {
-        info!(
+    let completed = if args.no_crop {
+        format!(
             "completed {}{name}, size: ({sprite_width}px, {sprite_height}px)",
             args.prefix
-        );
+        )
     } else {
-        info!(
+        format!(
             "completed {}{name}, size: ({sprite_width}px, {sprite_height}px), shift: ({shift_x}px, {shift_y}px)",
             args.prefix
-        );
+        )
+    };
+
+    let dice = args.dice.map(|size| {
+        (
+            sprite_width.div_ceil(size.get()),
+            sprite_height.div_ceil(size.get()),
+        )
+    });
+    if let Some((x, y)) = dice {
+        debug!("{}: dicing frames into {x}x{y} slices", source.display());
     }
 
-    if args.lua {
-        let out = output_name(source, &args.output, None, &args.prefix, "lua")?;
-        LuaOutput::new()
+    let kind = if sprite_count == 1 && direction_count.is_none() {
+        "sprite"
+    } else {
+        "animation"
+    };
+
+    let mut data = rotation_data(
+        crop.data(LuaOutput::new())
+            .set("type", kind)
             .set("width", sprite_width)
             .set("height", sprite_height)
             .set("shift", (shift_x, shift_y, args.tile_res()))
@@ -399,13 +1254,908 @@ fn generate_spritesheet(
             .set("line_length", cols_per_sheet)
             .set("lines_per_file", rows_per_sheet)
             .set("file_count", sheet_count)
-            .save(out)?;
+            .set_opt("draw_as_shadow", args.draw_as_shadow.then_some(true))
+            .set_opt(
+                "dice",
+                dice.filter(|(x, y)| x == y && *x > 1).map(|(x, _)| x),
+            )
+            .set_opt(
+                "dice_x",
+                dice.filter(|(x, y)| x != y && *x > 1).map(|(x, _)| x),
+            )
+            .set_opt(
+                "dice_y",
+                dice.filter(|(x, y)| x != y && *y > 1).map(|(_, y)| y),
+            )
+            .set_opt("provenance", provenance)
+            .set_opt(
+                "stripes",
+                direction_stripes(
+                    &files,
+                    sprite_count,
+                    direction_count,
+                    (cols_per_sheet, rows_per_sheet),
+                    sprite_height,
+                ),
+            ),
+        sprite_count,
+        direction_count,
+    );
+
+    for single in singles {
+        data = data.set(single.key, single.data);
+        if low_memory {
+            image_util::save_sheets(
+                &[single.sheet],
+                args.lossy,
+                true,
+                args.encode.options(),
+                &args.palette(),
+            )?;
+        } else {
+            sheets.push(single.sheet);
+        }
+        sprites.push(single.sprite);
+    }
+
+    let report = FolderReport {
+        frames: sprite_count,
+        size: (sprite_width, sprite_height),
+        shift: (shift_x, shift_y),
+        files,
+    };
+    Ok(Some(PackedFolder {
+        name,
+        data,
+        report,
+        sheets,
+        sprites,
+        completed,
+    }))
+}
+
+/// Sprite arrangement on the sheets of a folder.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    cols: u32,
+    rows: u32,
+    sheets: u32,
+
+    /// Unused sprite slots over all sheets, the last sheet only has as many rows as needed.
+    empty: u32,
+}
+
+impl Layout {
+    fn new(count: u32, cols: u32, max_rows: u32) -> Self {
+        let rows = count.div_ceil(cols).min(max_rows);
+        let per_sheet = cols * rows;
+        let sheets = count.div_ceil(per_sheet);
+        let last_rows = (count - (sheets - 1) * per_sheet).div_ceil(cols);
+
+        Self {
+            cols,
+            rows,
+            sheets,
+            empty: ((sheets - 1) * rows + last_rows) * cols - count,
+        }
+    }
+
+    /// Layout with the fewest sheets, then the fewest empty slots, then the most square sheets in pixels.
+    fn optimized(count: u32, sprite_size: (u32, u32), max_cols: u32, max_rows: u32) -> Self {
+        (1..=max_cols.min(count))
+            .map(|cols| Self::new(count, cols, max_rows))
+            .min_by_key(|layout| {
+                let squareness =
+                    (layout.cols * sprite_size.0).abs_diff(layout.rows * sprite_size.1);
+
+                // prefer wide sheets on ties, like the greedy layout
+                (layout.sheets, layout.empty, squareness, layout.rows)
+            })
+            .unwrap_or_else(|| Self::new(count, 1, max_rows))
+    }
+}
+
+/// Write the `--report` of all generated folders, sorted by folder name.
+fn write_report(path: &Path, reports: &[(String, FolderReport)]) -> Result<(), CommandError> {
+    let mut reports = reports.iter().collect::<Vec<_>>();
+    reports.sort_by(|(a, _), (b, _)| natord::compare(a, b));
+
+    let bytes = |report: &FolderReport| {
+        report
+            .files
+            .iter()
+            .map(|file| fs::metadata(file).map_or(0, |meta| meta.len()))
+            .sum::<u64>()
+    };
+
+    let content = if path.extension().is_some_and(|ext| ext == "json") {
+        let folders = reports
+            .iter()
+            .map(|(name, report)| {
+                json!({
+                    "folder": name,
+                    "frames": report.frames,
+                    "width": report.size.0,
+                    "height": report.size.1,
+                    "shift": [report.shift.0, report.shift.1],
+                    "sheets": report.files.len(),
+                    "bytes": bytes(report),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&folders).unwrap_or_default()
+    } else {
+        let mut csv = String::from("folder,frames,width,height,shift_x,shift_y,sheets,bytes\n");
+        for (name, report) in &reports {
+            let name = if name.contains([',', '"', '\n']) {
+                format!("\"{}\"", name.replace('"', "\"\""))
+            } else {
+                name.clone()
+            };
+
+            let _ = writeln!(
+                csv,
+                "{name},{},{},{},{},{},{},{}",
+                report.frames,
+                report.size.0,
+                report.size.1,
+                report.shift.0,
+                report.shift.1,
+                report.files.len(),
+                bytes(report)
+            );
+        }
+        csv
+    };
+
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            create_output_dir(parent)?;
+        }
+    }
+    crate::check::write(path, content.as_bytes()).map_err(output_error(path))?;
+    info!(
+        "report of {} folders saved to {}",
+        reports.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Estimate the peak memory usage for `source` and check if the low memory path has to be used.
+fn use_low_memory(args: &SpritesheetArgs, source: &Path) -> Result<bool, CommandError> {
+    if args.low_memory {
+        return Ok(true);
+    }
+
+    let formats = args.input_formats();
+    let files = if args.rotated {
+        fs::read_dir(source)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                path.is_dir().then_some(path)
+            })
+            .map(|path| image_util::image_paths(&path, &formats))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat()
+    } else {
+        image_util::image_paths(source, &formats)?
+    };
+
+    let Some(first) = files.first() else {
+        return Ok(false);
+    };
+
+    let (width, height) = image::image_dimensions(first).map_err(ImgUtilError::from)?;
+    let frame = u64::from(width) * u64::from(height) * 4;
+    let scaled = (frame as f64 * args.scale * args.scale) as u64;
+    let count = files.len() as u64;
+
+    // all decoded frames, their scaled copies and the sheets
+    let full = count * (frame.max(scaled) + scaled);
+    // a single decoded and scaled frame plus one sheet and its encoding buffer
+    let low = frame + scaled + 2 * (count * scaled).min(u64::from(MAX_SIZE).pow(2) * 4);
+
+    debug!(
+        "{}: estimated memory usage {} ({} with --low-memory)",
+        source.display(),
+        human_readable_bytes(full),
+        human_readable_bytes(low)
+    );
+
+    let Some(limit) = args.max_memory.or_else(available_memory) else {
+        return Ok(false);
+    };
+
+    if full <= limit {
+        return Ok(false);
+    }
+
+    if args.max_memory.is_none() {
+        warn!(
+            "{}: estimated memory usage of {} exceeds the available memory of {}, consider using --low-memory or --max-memory",
+            source.display(),
+            human_readable_bytes(full),
+            human_readable_bytes(limit)
+        );
+        return Ok(false);
+    }
+
+    if args.rotated || args.single_sheet_split_mode || args.anchor_marker.is_some() || low > limit {
+        Err(SpriteSheetError::MemoryLimitExceeded(
+            human_readable_bytes(full),
+            human_readable_bytes(limit),
+        ))?;
+    }
+
+    warn!(
+        "{}: estimated memory usage of {} exceeds the limit, switching to --low-memory",
+        source.display(),
+        human_readable_bytes(full)
+    );
+
+    Ok(true)
+}
+
+/// Available system memory, only known on linux.
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Frames to place on the sheet(s).
+enum Frames {
+    /// All frames are decoded, scaled and cropped up front.
+    Loaded(Vec<RgbaImage>),
+
+    /// Frames are decoded again while assembling their sheet, see `--low-memory`.
+    Streamed {
+        files: Vec<PathBuf>,
+        crop: Option<image_util::Rect>,
+        size: (u32, u32),
+    },
+}
+
+impl Frames {
+    const fn len(&self) -> usize {
+        match self {
+            Self::Loaded(images) => images.len(),
+            Self::Streamed { files, .. } => files.len(),
+        }
+    }
+
+    /// Size of the (scaled and cropped) frames.
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Self::Loaded(images) => images.first().map_or((0, 0), RgbaImage::dimensions),
+            Self::Streamed { size, .. } => *size,
+        }
+    }
+
+    fn get(&self, args: &SpritesheetArgs, idx: usize) -> Result<Cow<'_, RgbaImage>, CommandError> {
+        match self {
+            Self::Loaded(images) => Ok(Cow::Borrowed(&images[idx])),
+            Self::Streamed { files, crop, .. } => {
+                let image = args.prepared(image_util::load_image_from_file(&files[idx])?);
+
+                Ok(Cow::Owned(match crop {
+                    Some((x, y, width, height)) => {
+                        imageops::crop_imm(&image, *x, *y, *width, *height).to_image()
+                    }
+                    None => image,
+                }))
+            }
+        }
+    }
+}
+
+/// Position of the cropped frames within the (scaled) source frames and the resulting shift.
+#[derive(Debug, Clone, Copy)]
+struct Crop {
+    x: u32,
+    y: u32,
+    original_size: (u32, u32),
+    shift: (f64, f64),
+}
+
+impl Crop {
+    /// Crop of frames with `original_size` as returned by [`image_util::crop_rect`].
+    fn new(original_size: (u32, u32), crop: Option<(image_util::Rect, (f64, f64))>) -> Self {
+        let ((x, y, _, _), shift) = crop.unwrap_or(((0, 0, 0, 0), (0.0, 0.0)));
+        Self {
+            x,
+            y,
+            original_size,
+            shift,
+        }
+    }
+
+    /// Recenter the sprite on its content for `--auto-center`.
+    ///
+    /// `moments` are the summed [`image_util::alpha_moments`] of all uncropped frames,
+    /// the offset of their centroid to the canvas center is rounded to whole pixels.
+    fn auto_centered(
+        self,
+        mode: Option<AutoCenter>,
+        moments: (f64, f64, f64),
+        source: &Path,
+    ) -> Self {
+        let offset = match mode {
+            None => return self,
+            // the crop box is the bounding box, its shift is exactly the offset
+            Some(AutoCenter::Bbox) => self.shift,
+            Some(AutoCenter::Centroid) => {
+                let (sum_x, sum_y, weight) = moments;
+                if weight <= 0.0 {
+                    return self;
+                }
+
+                let (width, height) = self.original_size;
+                (
+                    (sum_x / weight - f64::from(width) / 2.0).round(),
+                    (sum_y / weight - f64::from(height) / 2.0).round(),
+                )
+            }
+        };
+
+        info!(
+            "{}: content is offset by ({}px, {}px) from the canvas center",
+            source.display(),
+            offset.0,
+            offset.1
+        );
+
+        self.with_origin(offset)
+    }
+
+    /// Move the sprite origin from the canvas center by `offset` pixels of the uncropped frames.
+    fn with_origin(mut self, offset: (f64, f64)) -> Self {
+        // avoid -0.0 in the data output
+        self.shift = (self.shift.0 - offset.0 + 0.0, self.shift.1 - offset.1 + 0.0);
+        self
+    }
+
+    /// Add the crop rectangle to the data output.
+    fn data(self, data: LuaOutput) -> LuaOutput {
+        data.set("crop_x", self.x)
+            .set("crop_y", self.y)
+            .set("original_width", self.original_size.0)
+            .set("original_height", self.original_size.1)
+    }
+}
+
+/// Max alpha value of a pixel reported by `--lint`.
+const LINT_MAX_ALPHA: u8 = 16;
+
+/// Max number of stray pixel positions listed per file.
+const LINT_MAX_LISTED: usize = 8;
+
+/// Report the stray pixels of every frame in `source`.
+fn lint(args: &SpritesheetArgs, source: &Path) -> Result<(), CommandError> {
+    let formats = args.input_formats();
+    let files = if args.rotated {
+        let mut directions = fs::read_dir(source)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        directions.retain(|path| path.is_dir());
+        directions.sort();
+
+        let mut files = Vec::new();
+        for direction in directions {
+            files.extend(image_util::image_paths(&direction, &formats)?);
+        }
+        files
+    } else {
+        image_util::image_paths(source, &formats)?
+    };
+
+    let mut affected = 0;
+    for file in &files {
+        let image = args.prepared(image_util::load_image_from_file(file)?);
+        let stray = image_util::stray_pixels(&image, args.crop_alpha, LINT_MAX_ALPHA);
+        if stray.is_empty() {
+            continue;
+        }
+
+        let mut listed = stray
+            .iter()
+            .take(LINT_MAX_LISTED)
+            .map(|(x, y)| format!("({x}, {y})"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if stray.len() > LINT_MAX_LISTED {
+            listed.push_str(", ...");
+        }
+
+        warn!(
+            "{}: {} stray pixels at {listed}",
+            file.display(),
+            stray.len()
+        );
+        affected += 1;
+    }
+
+    if affected == 0 {
+        info!(
+            "{}: no stray pixels in {} files",
+            source.display(),
+            files.len()
+        );
+    } else {
+        info!(
+            "{}: {affected} of {} files contain stray pixels, remove them or raise --crop-alpha",
+            source.display(),
+            files.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Name the frames that differ in size from the first one before cropping fails on them.
+fn report_size_mismatch(frames: &[(RgbaImage, PathBuf)]) {
+    let Some((first, _)) = frames.first() else {
+        return;
+    };
+
+    for (image, path) in frames {
+        if image.dimensions() != first.dimensions() {
+            warn!(
+                "{}: {}x{}px, the first frame is {}x{}px",
+                path.display(),
+                image.width(),
+                image.height(),
+                first.width(),
+                first.height()
+            );
+        }
+    }
+}
+
+/// Standalone sprite of a single frame exported with `--still-frame` or `--remnant`.
+struct SingleFrame {
+    /// Key of the sprite in the data output.
+    key: &'static str,
+    data: LuaOutput,
+    sheet: (RgbaImage, PathBuf),
+    sprite: SpriteSummary,
+}
+
+/// Export the `--still-frame` and `--remnant` sprites of an animation.
+fn single_frames(
+    args: &SpritesheetArgs,
+    frames: &Frames,
+    crop: Crop,
+    name: &str,
+) -> Result<Vec<SingleFrame>, CommandError> {
+    let mut singles = Vec::new();
+
+    if let Some(selected) = args.still_frame {
+        singles.push(single_frame(
+            args,
+            frames,
+            crop,
+            selected,
+            ("still_frame", format!("{name}-still")),
+            |_, _| {},
+        )?);
+    }
+
+    if let Some(selected) = args.remnant {
+        let scorch = args
+            .remnant_scorch
+            .as_deref()
+            .map(image_util::load_image_from_file)
+            .transpose()?;
+
+        singles.push(single_frame(
+            args,
+            frames,
+            crop,
+            selected,
+            ("remnant", format!("{name}-remnant")),
+            |frame, origin| remnant(args, frame, scorch.as_ref(), origin),
+        )?);
+    }
+
+    Ok(singles)
+}
+
+/// Cut a single frame out of the animation, `edit` it and crop it to its own content.
+///
+/// `edit` gets the position of the sprite origin within the frame.
+fn single_frame(
+    args: &SpritesheetArgs,
+    frames: &Frames,
+    crop: Crop,
+    selected: FrameIndex,
+    (key, single_name): (&'static str, String),
+    edit: impl FnOnce(&mut RgbaImage, (f64, f64)),
+) -> Result<SingleFrame, CommandError> {
+    let idx = selected
+        .resolve(frames.len())
+        .ok_or(SpriteSheetError::FrameOutOfRange(selected, frames.len()))?;
+
+    let mut frame = frames.get(args, idx)?.into_owned();
+    let origin = (
+        f64::from(frame.width()) / 2.0 - crop.shift.0,
+        f64::from(frame.height()) / 2.0 - crop.shift.1,
+    );
+    edit(&mut frame, origin);
+
+    let own_crop = if args.no_crop {
+        None
+    } else {
+        image_util::alpha_bounds(&frame, args.crop_alpha)
+            .and_then(|bounds| image_util::crop_rect(frame.dimensions(), bounds))
+    };
+
+    let (shift_x, shift_y) = crop.shift;
+    let (frame, shift) = match own_crop {
+        Some(((x, y, width, height), (own_x, own_y))) => (
+            imageops::crop_imm(&frame, x, y, width, height).to_image(),
+            (shift_x + own_x, shift_y + own_y),
+        ),
+        None => (frame, (shift_x, shift_y)),
+    };
+
+    let path = output_path(&single_name, &args.output, None, &args.prefix, "png");
+    #[allow(clippy::unwrap_used)]
+    let filename = path.file_name().unwrap().to_string_lossy().to_string();
+
+    let (width, height) = frame.dimensions();
+    debug!("{key} of frame {idx}: {width}x{height}px, shift: {shift:?}");
+
+    Ok(SingleFrame {
+        key,
+        data: LuaOutput::new()
+            .set("type", "sprite")
+            .set("filename", filename)
+            .set("width", width)
+            .set("height", height)
+            .set("shift", (shift.0, shift.1, args.tile_res()))
+            .set("scale", 32.0 / args.tile_res() as f64)
+            .set_opt("draw_as_shadow", args.draw_as_shadow.then_some(true)),
+        sheet: (frame, path),
+        sprite: (
+            format!("{}{single_name}", args.prefix),
+            (width, height),
+            shift,
+        ),
+    })
+}
+
+/// Turn a frame into a remnant: desaturate and darken it and draw the scorch marks
+/// centered on `origin` over it.
+fn remnant(
+    args: &SpritesheetArgs,
+    frame: &mut RgbaImage,
+    scorch: Option<&RgbaImage>,
+    origin: (f64, f64),
+) {
+    ColorAdjustment {
+        brightness: 0.0,
+        contrast: 1.0,
+        saturation: (1.0 - args.remnant_desaturate) as f32,
+    }
+    .apply(frame);
+
+    let brightness = 1.0 - args.remnant_darken;
+    for pxl in frame.pixels_mut() {
+        for channel in &mut pxl.0[..3] {
+            *channel = (f64::from(*channel) * brightness).round() as u8;
+        }
+    }
+
+    let Some(scorch) = scorch else {
+        return;
+    };
+
+    let offset_x = (origin.0 - f64::from(scorch.width()) / 2.0).round() as i64;
+    let offset_y = (origin.1 - f64::from(scorch.height()) / 2.0).round() as i64;
+    for (x, y, pxl) in frame.enumerate_pixels_mut() {
+        let (Ok(scorch_x), Ok(scorch_y)) = (
+            u32::try_from(i64::from(x) - offset_x),
+            u32::try_from(i64::from(y) - offset_y),
+        ) else {
+            continue;
+        };
+        let Some(mark) = scorch.get_pixel_checked(scorch_x, scorch_y) else {
+            continue;
+        };
+
+        // the alpha of the remnant is kept, so the marks never extend beyond it
+        let strength = f64::from(mark[3]) / 255.0;
+        for (channel, &value) in pxl.0[..3].iter_mut().zip(&mark.0[..3]) {
+            *channel = (f64::from(value) - f64::from(*channel))
+                .mul_add(strength, f64::from(*channel))
+                .round() as u8;
+        }
+    }
+}
+
+/// Frames, direction count, crop and provenance of a source folder.
+type SourceFrames = (Frames, Option<u32>, Crop, Option<LuaOutput>);
+
+/// Load, scale and crop all frames of `source`.
+fn load_frames(
+    args: &SpritesheetArgs,
+    source: &Path,
+) -> Result<Option<SourceFrames>, CommandError> {
+    let (mut images, direction_count) = if args.rotated {
+        let (images, direction_count) = load_directions(source, &args.input_formats())?;
+        (images, Some(direction_count))
+    } else {
+        let frames = image_util::load_from_path_with_path(source, &args.input_formats())?;
+        report_size_mismatch(&frames);
+        (frames.into_iter().map(|(image, _)| image).collect(), None)
+    };
+
+    if images.is_empty() {
+        return Ok(None);
+    }
+
+    let provenance = args.provenance(source, &images);
+    let anchor = args
+        .anchor_marker
+        .map(|(color, tolerance)| align_on_marker(&mut images, color, tolerance))
+        .transpose()?;
+
+    let mut images = images
+        .into_par_iter()
+        .map(|image| args.prepared(image))
+        .collect::<Vec<_>>();
+
+    let original_size = images[0].dimensions();
+    let moments = if args.auto_center == Some(AutoCenter::Centroid) {
+        images
+            .par_iter()
+            .map(|image| image_util::alpha_moments(image, args.crop_alpha))
+            .reduce(|| (0.0, 0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2))
+    } else {
+        (0.0, 0.0, 0.0)
+    };
+
+    let crop = if args.no_crop {
+        None
+    } else {
+        image_util::crop_images(&mut images, args.crop_alpha, args.symmetry())?
+    };
+
+    let mut crop = Crop::new(original_size, crop).auto_centered(args.auto_center, moments, source);
+    if let Some((x, y)) = anchor {
+        // the marker position scales with the frames
+        crop = crop.with_origin((
+            (f64::from(x) + 0.5).mul_add(args.scale, -f64::from(original_size.0) / 2.0),
+            (f64::from(y) + 0.5).mul_add(args.scale, -f64::from(original_size.1) / 2.0),
+        ));
+    }
+
+    Ok(Some((
+        Frames::Loaded(images),
+        direction_count,
+        crop,
+        provenance,
+    )))
+}
+
+/// Align all frames so their `--anchor-marker` pixels coincide and paint the markers over.
+///
+/// Frames are placed on a canvas large enough for all of them, returns the marker position on it.
+fn align_on_marker(
+    images: &mut [RgbaImage],
+    color: [u8; 3],
+    tolerance: u8,
+) -> Result<(u32, u32), CommandError> {
+    let mut markers = Vec::with_capacity(images.len());
+    for (idx, image) in images.iter_mut().enumerate() {
+        let found = image_util::find_markers(image, color, tolerance);
+        let [marker] = found[..] else {
+            Err(SpriteSheetError::AnchorMarkerCount(idx, found.len()))?
+        };
+
+        image_util::remove_marker(image, marker, color, tolerance);
+        markers.push(marker);
+    }
+
+    let (max_x, max_y) = markers.iter().fold((0, 0), |(max_x, max_y), &(x, y)| {
+        (max_x.max(x), max_y.max(y))
+    });
+    if markers.iter().all(|&marker| marker == (max_x, max_y)) {
+        return Ok((max_x, max_y));
+    }
+
+    // the frames must not be cut off on any side
+    let (width, height) =
+        images
+            .iter()
+            .zip(&markers)
+            .fold((0, 0), |(width, height), (image, &(x, y))| {
+                (
+                    width.max(image.width() + max_x - x),
+                    height.max(image.height() + max_y - y),
+                )
+            });
+
+    for (image, &(x, y)) in images.iter_mut().zip(&markers) {
+        let mut aligned = RgbaImage::new(width, height);
+        imageops::replace(
+            &mut aligned,
+            image,
+            i64::from(max_x - x),
+            i64::from(max_y - y),
+        );
+        *image = aligned;
+    }
+
+    debug!("aligned {} frames on their anchor markers", images.len());
+    Ok((max_x, max_y))
+}
+
+/// Determine size, crop and provenance of `source` while only decoding a single frame at a time.
+fn scan_frames(
+    args: &SpritesheetArgs,
+    source: &Path,
+) -> Result<Option<SourceFrames>, CommandError> {
+    let files = image_util::image_paths(source, &args.input_formats())?;
+    let mut provenance = args.provenance_hasher();
+    let mut size = None;
+    let mut bounds = None;
+    let mut moments = (0.0, 0.0, 0.0);
+
+    for file in &files {
+        let image = image_util::load_image_from_file(file)?;
+        if let Some(provenance) = &mut provenance {
+            provenance.add(&image);
+        }
+
+        let image = args.prepared(image);
+        let expected = *size.get_or_insert_with(|| image.dimensions());
+        if expected != image.dimensions() {
+            warn!(
+                "{}: {}x{}px, the first frame is {}x{}px",
+                file.display(),
+                image.width(),
+                image.height(),
+                expected.0,
+                expected.1
+            );
+            Err(SpriteSheetError::ImagesNotSameSize)?;
+        }
+
+        if !args.no_crop {
+            bounds =
+                image_util::union_bounds(bounds, image_util::alpha_bounds(&image, args.crop_alpha));
+        }
+
+        if args.auto_center == Some(AutoCenter::Centroid) {
+            let (x, y, weight) = image_util::alpha_moments(&image, args.crop_alpha);
+            moments = (moments.0 + x, moments.1 + y, moments.2 + weight);
+        }
     }
 
-    Ok(name)
+    let Some(size) = size else {
+        return Ok(None);
+    };
+
+    let crop = if args.no_crop {
+        None
+    } else {
+        let bounds = bounds.ok_or(ImgUtilError::AllImagesEmpty)?;
+        image_util::crop_rect(size, args.symmetry().apply(bounds, size))
+    };
+
+    let rect = crop.map(|(rect, _)| rect);
+    let cropped_size = rect.map_or(size, |(_, _, width, height)| (width, height));
+    let provenance = provenance.map(|provenance| provenance.finish(source));
+
+    Ok(Some((
+        Frames::Streamed {
+            files,
+            crop: rect,
+            size: cropped_size,
+        },
+        None,
+        Crop::new(size, crop).auto_centered(args.auto_center, moments, source),
+        provenance,
+    )))
+}
+
+/// Add the `RotatedAnimation` specific values when generating a rotated animation.
+fn rotation_data(data: LuaOutput, sprite_count: u32, direction_count: Option<u32>) -> LuaOutput {
+    match direction_count {
+        Some(direction_count) => data
+            .set("direction_count", direction_count)
+            .set("frame_count", sprite_count / direction_count),
+        None => data,
+    }
 }
 
-type SubframeData = (RgbaImage, (u32, u32), (f64, f64), (u32, u32));
+/// One stripe per direction of a rotated animation, only possible when every direction fills exactly one line.
+fn direction_stripes(
+    files: &[PathBuf],
+    sprite_count: u32,
+    direction_count: Option<u32>,
+    (cols, rows): (u32, u32),
+    sprite_height: u32,
+) -> Option<Box<[LuaOutput]>> {
+    let direction_count = direction_count?;
+    let frame_count = sprite_count / direction_count;
+    if frame_count != cols {
+        return None;
+    }
+
+    (0..direction_count)
+        .map(|direction| {
+            let file = files.get((direction / rows) as usize)?.file_name()?;
+            Some(
+                LuaOutput::new()
+                    .set("filename", file.to_string_lossy().to_string())
+                    .set("width_in_frames", frame_count)
+                    .set("height_in_frames", 1)
+                    .set("y", direction % rows * sprite_height),
+            )
+        })
+        .collect()
+}
+
+/// Load the frames of all direction subfolders in clockwise order, starting north.
+fn load_directions(
+    source: &Path,
+    formats: &[ImageFormat],
+) -> Result<(Vec<RgbaImage>, u32), CommandError> {
+    let mut directions = fs::read_dir(source)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            path.is_dir().then_some(path)
+        })
+        .map(|path| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            direction_index(&name)
+                .map(|idx| (idx, name.clone(), path))
+                .ok_or(SpriteSheetError::UnknownDirection(name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if directions.is_empty() {
+        Err(SpriteSheetError::NoDirections)?;
+    }
+
+    directions.sort_by_key(|(idx, _, _)| *idx);
+
+    let mut images = Vec::new();
+    let mut frame_count = None;
+    for (_, name, path) in &directions {
+        let frames = image_util::load_from_path(path, formats)?;
+        let expected = *frame_count.get_or_insert(frames.len());
+
+        if frames.len() != expected {
+            Err(SpriteSheetError::DirectionFrameCountMismatch(
+                name.clone(),
+                frames.len(),
+                expected,
+            ))?;
+        }
+
+        debug!("{}: {} frames", path.display(), frames.len());
+        images.extend(frames);
+    }
+
+    Ok((images, directions.len() as u32))
+}
+
+/// Sheet, fragment size, shift, sheet layout (columns, rows) and fragment origin within the frame.
+type SubframeData = (RgbaImage, (u32, u32), (f64, f64), (u32, u32), (u32, u32));
 
 fn generate_subframe_sheets(
     _args: &SpritesheetArgs,
@@ -463,14 +2213,19 @@ fn generate_subframe_sheets(
 
             // TODO: autocrop subframes again (?)
 
-            frag_groups.push((frags, (width, height), (frag_shift_x, frag_shift_y)));
+            frag_groups.push((
+                frags,
+                (width, height),
+                (frag_shift_x, frag_shift_y),
+                (tx, ty),
+            ));
         }
     }
 
     // arrange subframes on sheets
     frag_groups
         .iter()
-        .map(|(frags, (width, height), (shift_x, shift_y))| {
+        .map(|(frags, (width, height), (shift_x, shift_y), origin)| {
             let cols = MAX_SIZE / width;
             let sheet_width = cols * width;
             let rows = sprite_count.div_ceil(cols);
@@ -488,7 +2243,47 @@ fn generate_subframe_sheets(
                 imageops::replace(&mut sheet, &frag.to_image(), i64::from(x), i64::from(y));
             }
 
-            (sheet, (*width, *height), (*shift_x, *shift_y), (cols, rows))
+            (
+                sheet,
+                (*width, *height),
+                (*shift_x, *shift_y),
+                (cols, rows),
+                *origin,
+            )
         })
         .collect()
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::Layout;
+
+    fn dims(layout: Layout) -> (u32, u32, u32, u32) {
+        (layout.cols, layout.rows, layout.sheets, layout.empty)
+    }
+
+    #[test]
+    fn optimized_fills_single_sheet() {
+        // 3x4 and 4x3 are equally square, the wider one wins
+        assert_eq!(dims(Layout::optimized(12, (64, 64), 8, 8)), (4, 3, 1, 0));
+    }
+
+    #[test]
+    fn optimized_prefers_fewer_empty_slots() {
+        // 7 frames only fit without gaps in a single row or column
+        assert_eq!(dims(Layout::optimized(7, (64, 64), 8, 8)), (7, 1, 1, 0));
+    }
+
+    #[test]
+    fn optimized_squares_in_pixels() {
+        assert_eq!(dims(Layout::optimized(4, (32, 128), 8, 8)), (4, 1, 1, 0));
+        assert_eq!(dims(Layout::optimized(4, (128, 32), 8, 8)), (1, 4, 1, 0));
+    }
+
+    #[test]
+    fn optimized_spreads_over_sheets() {
+        // 3 columns would leave 2 empty slots on the second sheet
+        assert_eq!(dims(Layout::optimized(10, (64, 64), 3, 3)), (2, 3, 2, 0));
+    }
+}
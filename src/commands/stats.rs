@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::ImageFormat;
+
+use super::{human_readable_bytes, recursive_folders, spritesheet::MAX_SIZE, CommandError};
+use crate::image_util;
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Mod folder to analyze, all subfolders are scanned.
+    pub source: PathBuf,
+
+    /// Group the usage by the folders at this depth below the source, deeper folders are added to their parent [default: every folder on its own]
+    #[clap(long)]
+    pub depth: Option<NonZeroUsize>,
+
+    /// Only list the N folders with the highest usage.
+    #[clap(long, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Follow symlinked folders.
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+}
+
+/// Decompressed texture usage of the images of a folder.
+#[derive(Default)]
+struct Usage {
+    images: usize,
+    bytes: u64,
+}
+
+/// Print the estimated VRAM usage of all images grouped by folder and the atlas occupancy to stdout.
+///
+/// # Errors
+///
+/// Fails when the source folder can't be read.
+pub fn stats(args: &StatsArgs) -> Result<(), CommandError> {
+    let mut visited = HashSet::from([args.source.canonicalize()?]);
+    let mut folders = vec![args.source.clone()];
+    folders.extend(recursive_folders(
+        &args.source,
+        args.follow_symlinks,
+        &mut visited,
+    )?);
+
+    let mut groups = BTreeMap::<String, Usage>::new();
+    let mut sizes = Vec::new();
+
+    for folder in &folders {
+        let mut files = fs::read_dir(folder)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.retain(|path| {
+            path.is_file()
+                && ImageFormat::from_path(path).is_ok_and(|format| format.reading_enabled())
+        });
+
+        for path in files {
+            let (width, height) = match image::image_dimensions(&path) {
+                Ok(size) => size,
+                Err(err) => {
+                    warn!("{}: {err}", path.display());
+                    continue;
+                }
+            };
+
+            let usage = groups
+                .entry(group(&args.source, folder, args.depth))
+                .or_default();
+            usage.images += 1;
+            usage.bytes += image_util::vram_size(width, height);
+
+            if width > MAX_SIZE || height > MAX_SIZE {
+                warn!(
+                    "{}: {width}x{height}px exceeds the texture size limit of {MAX_SIZE}px",
+                    path.display()
+                );
+            } else {
+                sizes.push((width, height));
+            }
+        }
+    }
+
+    let images = groups.values().map(|usage| usage.images).sum::<usize>();
+    let total = groups.values().map(|usage| usage.bytes).sum::<u64>();
+    if images == 0 {
+        warn!("no images found");
+        return Ok(());
+    }
+
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by(|(a_name, a), (b_name, b)| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| natord::compare(a_name, b_name))
+    });
+
+    let listed = args.top.unwrap_or(groups.len()).min(groups.len());
+    println!("{:>10} {:>7} {:>7}  folder", "usage", "share", "images");
+    for (name, usage) in &groups[..listed] {
+        println!(
+            "{:>10} {:>6.2}% {:>7}  {name}",
+            human_readable_bytes(usage.bytes),
+            usage.bytes as f64 / total as f64 * 100.0,
+            usage.images
+        );
+    }
+    if listed < groups.len() {
+        let rest = &groups[listed..];
+        println!(
+            "{:>10} {:>6.2}% {:>7}  ({} more folders)",
+            human_readable_bytes(rest.iter().map(|(_, usage)| usage.bytes).sum()),
+            rest.iter().map(|(_, usage)| usage.bytes).sum::<u64>() as f64 / total as f64 * 100.0,
+            rest.iter().map(|(_, usage)| usage.images).sum::<usize>(),
+            rest.len()
+        );
+    }
+
+    let (atlases, occupancy) = pack_atlases(&mut sizes);
+    println!();
+    println!(
+        "{images} images in {} folders, estimated VRAM usage: {}",
+        folders.len(),
+        human_readable_bytes(total)
+    );
+    println!(
+        "~{atlases} atlases of {MAX_SIZE}x{MAX_SIZE}px, {:.1}% occupied",
+        occupancy * 100.0
+    );
+
+    Ok(())
+}
+
+/// Name of the group the images of `folder` are counted towards.
+fn group(source: &Path, folder: &Path, depth: Option<NonZeroUsize>) -> String {
+    let relative = folder.strip_prefix(source).unwrap_or(folder);
+    let name = relative
+        .components()
+        .take(depth.map_or(usize::MAX, NonZeroUsize::get))
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if name.is_empty() {
+        ".".to_owned()
+    } else {
+        name
+    }
+}
+
+/// Estimate the number of atlases needed for all images with a simple shelf packing
+/// and how much of their area is occupied.
+///
+/// The game groups sprites by their usage into separate atlases,
+/// so the actual count is usually a bit higher.
+fn pack_atlases(sizes: &mut [(u32, u32)]) -> (usize, f64) {
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+
+    // shelves of each atlas as (height, used width), and the used height of each atlas
+    let mut atlases: Vec<(Vec<(u32, u32)>, u32)> = Vec::new();
+    let mut area = 0;
+
+    for &(width, height) in sizes.iter() {
+        area += u64::from(width) * u64::from(height);
+
+        let shelf = atlases.iter_mut().find_map(|(shelves, _)| {
+            shelves
+                .iter_mut()
+                .find(|(shelf_height, used)| *shelf_height >= height && used + width <= MAX_SIZE)
+        });
+        if let Some((_, used)) = shelf {
+            *used += width;
+            continue;
+        }
+
+        if let Some((shelves, used_height)) = atlases
+            .iter_mut()
+            .find(|(_, used_height)| used_height + height <= MAX_SIZE)
+        {
+            shelves.push((height, width));
+            *used_height += height;
+        } else {
+            atlases.push((vec![(height, width)], height));
+        }
+    }
+
+    let capacity = atlases.len() as u64 * u64::from(MAX_SIZE).pow(2);
+    (atlases.len(), area as f64 / capacity.max(1) as f64)
+}
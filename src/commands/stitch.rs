@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use image::{imageops, RgbaImage};
+
+use super::{create_output_dir, output_error, spritesheet::MAX_SIZE, CommandError, EncodeArgs};
+use crate::{
+    image_util::{self, PaletteOptions},
+    lua::{LuaOutput, LuaStyle, LuaValue},
+    summary,
+};
+
+#[derive(Args, Debug)]
+pub struct StitchArgs {
+    /// Folder containing the sheets and spritter generated data files (lua or json) to stitch.
+    pub source: PathBuf,
+
+    /// Output folder.
+    pub output: PathBuf,
+
+    /// Name of the stitched sheets and the combined data file.
+    #[clap(short, long, default_value = "stitched")]
+    pub name: String,
+
+    /// Max width and height of the stitched sheets in pixels.
+    #[clap(long, default_value_t = MAX_SIZE, value_parser = clap::value_parser!(u32).range(1..=i64::from(MAX_SIZE)))]
+    pub max_size: u32,
+
+    /// Write the combined data as json instead of lua.
+    #[clap(short, long, action)]
+    pub json: bool,
+
+    #[clap(flatten)]
+    pub encode: EncodeArgs,
+}
+
+/// Sheet of a data file that gets moved into one of the stitched sheets.
+struct Block {
+    name: String,
+    data: LuaOutput,
+    image: RgbaImage,
+}
+
+/// Row of blocks inside a stitched sheet.
+struct Shelf {
+    y: u32,
+    height: u32,
+    width: u32,
+}
+
+#[derive(Default)]
+struct Atlas {
+    shelves: Vec<Shelf>,
+    width: u32,
+    height: u32,
+
+    /// Block index and position.
+    blocks: Vec<(usize, u32, u32)>,
+}
+
+/// Merge the sheets of several data files into as few sheets as possible and write the combined data.
+///
+/// Every entry of the combined data references its stitched sheet with `filename`, `x` and `y`.
+///
+/// # Errors
+///
+/// Fails when a data file or sheet can't be loaded or the stitched outputs can't be written.
+pub fn stitch(args: &StitchArgs) -> Result<(), CommandError> {
+    create_output_dir(&args.output)?;
+
+    let blocks = load_blocks(&args.source, args.max_size)?;
+    if blocks.is_empty() {
+        warn!("no sheets to stitch found");
+        return Ok(());
+    }
+
+    let atlases = pack(&blocks, args.max_size);
+
+    let sheet_name = |idx: usize| {
+        if atlases.len() == 1 {
+            format!("{}.png", args.name)
+        } else {
+            format!("{}-{idx}.png", args.name)
+        }
+    };
+
+    let mut sheets = Vec::with_capacity(atlases.len());
+    let mut entries = Vec::with_capacity(blocks.len());
+    for (idx, atlas) in atlases.iter().enumerate() {
+        let mut sheet = RgbaImage::new(atlas.width, atlas.height);
+        for &(block_idx, x, y) in &atlas.blocks {
+            let block = &blocks[block_idx];
+            imageops::replace(&mut sheet, &block.image, i64::from(x), i64::from(y));
+
+            let mut data = block.data.clone();
+            data.remove("file_count");
+            let data = data
+                .set("filename", sheet_name(idx))
+                .set("x", x)
+                .set("y", y);
+            entries.push((block.name.clone(), data));
+        }
+
+        sheets.push((sheet, args.output.join(sheet_name(idx))));
+    }
+
+    image_util::save_sheets(
+        &sheets,
+        false,
+        false,
+        args.encode.options(),
+        &PaletteOptions::default(),
+    )?;
+
+    let data = entries
+        .into_iter()
+        .fold(LuaOutput::new(), |merged, (name, data)| {
+            merged.set(name, data)
+        });
+
+    let out = if args.json {
+        let out = args.output.join(format!("{}.json", args.name));
+        fs::File::create(&out)
+            .and_then(|mut file| data.write_json(&mut file, None))
+            .map_err(output_error(&out))?;
+        out
+    } else {
+        let out = args.output.join(format!("{}.lua", args.name));
+        data.save(&out, LuaStyle::default())
+            .map_err(output_error(&out))?;
+        out
+    };
+    summary::record_file(&out);
+
+    info!(
+        "stitched {} sheets into {} sheets, data saved to {}",
+        blocks.len(),
+        sheets.len(),
+        out.display()
+    );
+
+    Ok(())
+}
+
+/// Load the sheets of all data files in `source` that can be moved into a stitched sheet.
+fn load_blocks(source: &Path, max_size: u32) -> Result<Vec<Block>, CommandError> {
+    let mut files = fs::read_dir(source)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.retain(|path| path.is_file());
+    files.sort_by(|a, b| natord::compare(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    // the lua and json output of the same sheet only count once
+    let mut data_files = BTreeMap::new();
+    for path in files {
+        let data = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("lua") => LuaOutput::load(&path),
+            Some("json") => LuaOutput::load_json(&path),
+            _ => continue,
+        };
+
+        // other lua / json files are silently skipped
+        let Ok(mut data) = data else {
+            continue;
+        };
+
+        // the generator version is written again at the top level of the combined data
+        data.remove("spritter");
+
+        #[allow(clippy::unwrap_used)]
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        data_files.entry(name).or_insert((path, data));
+    }
+
+    let mut blocks = Vec::new();
+    for (name, (path, data)) in data_files {
+        let get_int = |key: &str| {
+            data.get(key)
+                .and_then(LuaValue::as_int)
+                .and_then(|value| u32::try_from(value).ok())
+        };
+
+        if data.get("single_sheet_split_layers").is_some() || data.get("icon_size").is_some() {
+            warn!(
+                "{}: split layers and icons can not be stitched, skipping",
+                path.display()
+            );
+            continue;
+        }
+
+        let (Some(width), Some(height)) = (get_int("width"), get_int("height")) else {
+            continue;
+        };
+
+        if get_int("file_count").unwrap_or(1) > 1 {
+            warn!(
+                "{}: sheets spread over multiple files can not be stitched, skipping",
+                path.display()
+            );
+            continue;
+        }
+
+        let sheet = path.with_file_name(format!("{name}.png"));
+        if !sheet.is_file() {
+            warn!(
+                "{}: sheet {} is missing, skipping",
+                path.display(),
+                sheet.display()
+            );
+            continue;
+        }
+
+        let image = image_util::load_image_from_file(&sheet)?;
+
+        // only the area covered by the layout is moved, static sprites have no layout in v2 data files
+        let used_width = (width * get_int("line_length").unwrap_or(1)).min(image.width());
+        let used_height = (height * get_int("lines_per_file").unwrap_or(1)).min(image.height());
+        if used_width > max_size || used_height > max_size {
+            warn!(
+                "{}: sheet is larger than {max_size}px, skipping",
+                path.display()
+            );
+            continue;
+        }
+
+        blocks.push(Block {
+            name,
+            data,
+            image: imageops::crop_imm(&image, 0, 0, used_width, used_height).to_image(),
+        });
+    }
+
+    Ok(blocks)
+}
+
+/// Shelf pack the blocks into sheets of up to `max_size` pixels, tallest blocks first.
+fn pack(blocks: &[Block], max_size: u32) -> Vec<Atlas> {
+    let mut order = (0..blocks.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| {
+        let (width, height) = blocks[idx].image.dimensions();
+        (std::cmp::Reverse(height), std::cmp::Reverse(width))
+    });
+
+    let mut atlases = Vec::<Atlas>::new();
+    for idx in order {
+        let (width, height) = blocks[idx].image.dimensions();
+
+        let free_shelf = atlases.iter_mut().find_map(|atlas| {
+            let shelf = atlas
+                .shelves
+                .iter_mut()
+                .find(|shelf| height <= shelf.height && shelf.width + width <= max_size)?;
+            Some((
+                shelf.width,
+                shelf.y,
+                shelf,
+                &mut atlas.blocks,
+                &mut atlas.width,
+            ))
+        });
+
+        if let Some((x, y, shelf, placed, atlas_width)) = free_shelf {
+            shelf.width += width;
+            *atlas_width = (*atlas_width).max(shelf.width);
+            placed.push((idx, x, y));
+            continue;
+        }
+
+        let atlas_idx = atlases
+            .iter()
+            .position(|atlas| atlas.height + height <= max_size)
+            .unwrap_or_else(|| {
+                atlases.push(Atlas::default());
+                atlases.len() - 1
+            });
+        let atlas = &mut atlases[atlas_idx];
+
+        atlas.shelves.push(Shelf {
+            y: atlas.height,
+            height,
+            width,
+        });
+        atlas.blocks.push((idx, 0, atlas.height));
+        atlas.width = atlas.width.max(width);
+        atlas.height += height;
+    }
+
+    atlases
+}
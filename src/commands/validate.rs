@@ -0,0 +1,298 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+
+use super::{
+    human_readable_bytes, parse_byte_size, recursive_folders, spritesheet::MAX_SIZE, CommandError,
+};
+use crate::{
+    image_util,
+    lua::{LuaOutput, LuaValue},
+    summary,
+};
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Mod folder to validate, all subfolders are scanned.
+    pub source: PathBuf,
+
+    /// Fail when the estimated VRAM usage of all images exceeds this size, e.g. `512MB`.
+    #[clap(long, value_parser = parse_byte_size)]
+    pub max_vram: Option<u64>,
+
+    /// Follow symlinked folders.
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+}
+
+/// Check all images and spritter data files of a mod, fails when any violation was found.
+///
+/// # Errors
+///
+/// Fails when the source folder can't be read or [`CommandError::ValidationFailed`] with the number of violations.
+pub fn validate(args: &ValidateArgs) -> Result<(), CommandError> {
+    let mut visited = HashSet::from([args.source.canonicalize()?]);
+    let mut folders = vec![args.source.clone()];
+    folders.extend(recursive_folders(
+        &args.source,
+        args.follow_symlinks,
+        &mut visited,
+    )?);
+
+    let mut violations = Vec::new();
+    let mut images = 0;
+    let mut data_files = 0;
+    let mut vram = 0;
+
+    for folder in &folders {
+        let mut files = fs::read_dir(folder)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.retain(|path| path.is_file());
+        files.sort();
+
+        for path in files {
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("png") => {
+                    let (width, height) = match image::image_dimensions(&path) {
+                        Ok(size) => size,
+                        Err(err) => {
+                            violations.push((path, format!("unreadable image: {err}")));
+                            continue;
+                        }
+                    };
+                    images += 1;
+                    vram += image_util::vram_size(width, height);
+
+                    if width > MAX_SIZE || height > MAX_SIZE {
+                        violations.push((
+                            path,
+                            format!(
+                                "{width}x{height}px exceeds the texture size limit of {MAX_SIZE}px"
+                            ),
+                        ));
+                    }
+                }
+                Some("lua" | "json") => {
+                    // most lua / json files in a mod are not generated by spritter
+                    let Some(data) = load_data_file(&path) else {
+                        continue;
+                    };
+
+                    data_files += 1;
+                    violations.extend(
+                        check_data_file(&path, &data)
+                            .into_iter()
+                            .map(|msg| (path.clone(), msg)),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let atlases = vram.div_ceil(image_util::vram_size(MAX_SIZE, MAX_SIZE));
+    info!(
+        "checked {images} images and {data_files} data files in {} folders, estimated VRAM usage: {} (~{atlases} atlases of {MAX_SIZE}x{MAX_SIZE}px)",
+        folders.len(),
+        human_readable_bytes(vram),
+    );
+
+    if let Some(max_vram) = args.max_vram {
+        if vram > max_vram {
+            violations.push((
+                args.source.clone(),
+                format!(
+                    "estimated VRAM usage of {} exceeds the limit of {}",
+                    human_readable_bytes(vram),
+                    human_readable_bytes(max_vram)
+                ),
+            ));
+        }
+    }
+
+    if violations.is_empty() {
+        info!("no violations found");
+        return Ok(());
+    }
+
+    for (path, msg) in &violations {
+        warn!("{}: {msg}", path.display());
+        summary::record_failure(Some(path), msg);
+    }
+
+    Err(CommandError::ValidationFailed(violations.len()))
+}
+
+/// Load a spritter generated data file, `None` for any other lua / json file.
+pub(super) fn load_data_file(path: &Path) -> Option<LuaOutput> {
+    let data = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("lua") => LuaOutput::load(path),
+        Some("json") => LuaOutput::load_json(path),
+        _ => return None,
+    };
+
+    data.ok().filter(|data| {
+        data.get("single_sheet_split_layers").is_some()
+            || data.get("icon_size").is_some()
+            || (data.get("width").is_some() && data.get("height").is_some())
+    })
+}
+
+fn check_data_file(path: &Path, data: &LuaOutput) -> Vec<String> {
+    let folder = path.parent().unwrap_or_else(|| Path::new("."));
+    #[allow(clippy::unwrap_used)]
+    let name = path.file_stem().unwrap().to_string_lossy();
+    let get_int = |data: &LuaOutput, key: &str| data.get(key).and_then(LuaValue::as_int);
+
+    if let Some(icon_size) = get_int(data, "icon_size") {
+        let mipmaps = get_int(data, "icon_mipmaps").unwrap_or(1).clamp(1, 16);
+        return check_icon(&folder.join(format!("{name}.png")), icon_size, mipmaps)
+            .into_iter()
+            .collect();
+    }
+
+    if let Some(layers) = data
+        .get("single_sheet_split_layers")
+        .and_then(LuaValue::as_array)
+    {
+        return layers
+            .iter()
+            .filter_map(|layer| match layer {
+                LuaValue::Table(layer) => Some(layer),
+                _ => None,
+            })
+            .filter_map(|layer| {
+                let file = folder.join(layer.get("filename")?.as_str()?);
+                check_sheets(&[file], layer).err()
+            })
+            .collect();
+    }
+
+    // static sprites don't have a sheet layout in v2 data files
+    let file_count = get_int(data, "file_count").unwrap_or(1).max(1) as usize;
+    let sheets = if file_count == 1 {
+        vec![folder.join(format!("{name}.png"))]
+    } else {
+        (0..file_count)
+            .map(|idx| folder.join(format!("{name}-{idx}.png")))
+            .collect()
+    };
+
+    check_sheets(&sheets, data).err().into_iter().collect()
+}
+
+/// Check that the sheets exist and fit the layout and frame count of the data.
+fn check_sheets(sheets: &[PathBuf], data: &LuaOutput) -> Result<(), String> {
+    let layout = SheetLayout::from_data(data)?;
+    if let Some(issue) = layout.check_frame_count(sheets.len()) {
+        return Err(issue);
+    }
+
+    let SheetLayout {
+        width,
+        height,
+        line_length,
+        lines_per_file,
+        ..
+    } = layout;
+
+    for sheet in sheets {
+        let Ok((sheet_width, sheet_height)) = image::image_dimensions(sheet) else {
+            return Err(format!("sheet {} is missing", sheet.display()));
+        };
+
+        if i64::from(sheet_width) < width * line_length
+            || i64::from(sheet_height) < height * lines_per_file
+        {
+            return Err(format!(
+                "sheet {} is too small for {line_length}x{lines_per_file} frames of {width}x{height}px",
+                sheet.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Sheet layout described by a data file.
+pub(super) struct SheetLayout {
+    pub width: i64,
+    pub height: i64,
+    pub line_length: i64,
+    pub lines_per_file: i64,
+    pub frames: Option<i64>,
+}
+
+impl SheetLayout {
+    pub fn from_data(data: &LuaOutput) -> Result<Self, String> {
+        let get_int = |key: &str| data.get(key).and_then(LuaValue::as_int);
+        let (Some(width), Some(height)) = (get_int("width"), get_int("height")) else {
+            return Err("data file is missing the sprite size".to_owned());
+        };
+
+        // icon strips are a single line of icons
+        let line_length = get_int("line_length")
+            .or_else(|| get_int("icon_count"))
+            .unwrap_or(1)
+            .max(1);
+        let lines_per_file = get_int("lines_per_file").unwrap_or(1).max(1);
+        let frames = get_int("sprite_count")
+            .or_else(|| {
+                get_int("frame_count")
+                    .map(|frames| frames * get_int("direction_count").unwrap_or(1))
+            })
+            .or_else(|| get_int("icon_count"));
+
+        Ok(Self {
+            width,
+            height,
+            line_length,
+            lines_per_file,
+            frames,
+        })
+    }
+
+    pub const fn per_sheet(&self) -> i64 {
+        self.line_length * self.lines_per_file
+    }
+
+    /// Check that the frames fill all sheets, only the last one may be partially empty.
+    pub fn check_frame_count(&self, file_count: usize) -> Option<String> {
+        let frames = self.frames?;
+        let per_sheet = self.per_sheet();
+        let capacity = per_sheet.saturating_mul(file_count as i64);
+
+        (frames > capacity || frames <= capacity - per_sheet).then(|| {
+            format!(
+                "{frames} frames don't match the grid of {file_count} sheets with {}x{} frames each",
+                self.line_length, self.lines_per_file
+            )
+        })
+    }
+}
+
+/// Check that all mip levels of an icon have even sizes and the image matches them.
+pub(super) fn check_icon(path: &Path, icon_size: i64, mipmaps: i64) -> Option<String> {
+    let levels = 1_i64 << (mipmaps - 1);
+    if icon_size % levels != 0 {
+        return Some(format!(
+            "icon size {icon_size}px can not be halved for {mipmaps} mipmap levels"
+        ));
+    }
+
+    let expected = (0..mipmaps).map(|level| icon_size >> level).sum::<i64>();
+    match image::image_dimensions(path) {
+        Err(_) => Some(format!("icon {} is missing", path.display())),
+        Ok((width, height)) if i64::from(width) != expected || i64::from(height) != icon_size => {
+            Some(format!(
+                "icon is {width}x{height}px, expected {expected}x{icon_size}px for {mipmaps} mipmap levels"
+            ))
+        }
+        Ok(_) => None,
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+
+use super::{
+    recursive_folders,
+    validate::{check_icon, load_data_file, SheetLayout},
+    CommandError,
+};
+use crate::{
+    lua::{LuaOutput, LuaValue},
+    summary,
+};
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Folder with spritter generated sheets and data files, all subfolders are scanned.
+    pub source: PathBuf,
+
+    /// Follow symlinked folders.
+    #[clap(long, action)]
+    pub follow_symlinks: bool,
+}
+
+/// Check that all spritter data files still describe the sheets next to them,
+/// fails when any mismatch was found.
+///
+/// # Errors
+///
+/// Fails when the source folder can't be read or [`CommandError::ValidationFailed`] with the number of mismatches.
+pub fn verify(args: &VerifyArgs) -> Result<(), CommandError> {
+    let mut visited = HashSet::from([args.source.canonicalize()?]);
+    let mut folders = vec![args.source.clone()];
+    folders.extend(recursive_folders(
+        &args.source,
+        args.follow_symlinks,
+        &mut visited,
+    )?);
+
+    let mut mismatches = Vec::new();
+    let mut data_files = 0;
+
+    for folder in &folders {
+        let mut files = fs::read_dir(folder)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.retain(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "lua" || ext == "json")
+        });
+        files.sort();
+
+        for path in files {
+            let issues = if let Some(data) = load_data_file(&path) {
+                verify_data_file(&path, &data)
+            } else if let Some(entries) = load_stitched_file(&path) {
+                verify_stitched_file(&path, &entries)
+            } else {
+                // most lua / json files in a mod are not generated by spritter
+                continue;
+            };
+
+            data_files += 1;
+            mismatches.extend(issues.into_iter().map(|msg| (path.clone(), msg)));
+        }
+    }
+
+    info!(
+        "verified {data_files} data files in {} folders",
+        folders.len()
+    );
+
+    if mismatches.is_empty() {
+        info!("all data files match their sheets");
+        return Ok(());
+    }
+
+    for (path, msg) in &mismatches {
+        warn!("{}: {msg}", path.display());
+        summary::record_failure(Some(path), msg);
+    }
+
+    Err(CommandError::ValidationFailed(mismatches.len()))
+}
+
+/// Load the data file of stitched sheets, a table of sprite name to sprite data.
+fn load_stitched_file(path: &Path) -> Option<Vec<(String, LuaOutput)>> {
+    let data = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("lua") => LuaOutput::load(path),
+        Some("json") => LuaOutput::load_json(path),
+        _ => return None,
+    }
+    .ok()?;
+
+    let entries = data
+        .keys()
+        .into_iter()
+        .filter_map(|name| match data.get(&name) {
+            Some(LuaValue::Table(entry)) if entry.get("filename").is_some() => {
+                Some((name, entry.clone()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    (!entries.is_empty()).then_some(entries)
+}
+
+fn verify_data_file(path: &Path, data: &LuaOutput) -> Vec<String> {
+    let folder = path.parent().unwrap_or_else(|| Path::new("."));
+    #[allow(clippy::unwrap_used)]
+    let name = path.file_stem().unwrap().to_string_lossy();
+    let get_int = |key: &str| data.get(key).and_then(LuaValue::as_int);
+
+    if let Some(icon_size) = get_int("icon_size") {
+        let mipmaps = get_int("icon_mipmaps").unwrap_or(1).clamp(1, 16);
+        return check_icon(&folder.join(format!("{name}.png")), icon_size, mipmaps)
+            .into_iter()
+            .collect();
+    }
+
+    if let Some(layers) = data
+        .get("single_sheet_split_layers")
+        .and_then(LuaValue::as_array)
+    {
+        return layers
+            .iter()
+            .filter_map(|layer| match layer {
+                LuaValue::Table(layer) => Some(layer),
+                _ => None,
+            })
+            .flat_map(|layer| {
+                layer
+                    .get("filename")
+                    .and_then(LuaValue::as_str)
+                    .map_or_else(
+                        || vec!["layer is missing its filename".to_owned()],
+                        |file| verify_sheets(&[folder.join(file)], layer),
+                    )
+            })
+            .collect();
+    }
+
+    let file_count = get_int("file_count").unwrap_or(1).max(1);
+    let sheet = |idx: i64| folder.join(format!("{name}-{idx}.png"));
+    let (sheets, stale) = if file_count == 1 {
+        (vec![folder.join(format!("{name}.png"))], vec![sheet(0)])
+    } else {
+        (
+            (0..file_count).map(sheet).collect(),
+            vec![folder.join(format!("{name}.png")), sheet(file_count)],
+        )
+    };
+
+    let mut issues = verify_sheets(&sheets, data);
+    issues.extend(stale.into_iter().filter(|path| path.exists()).map(|path| {
+        format!(
+            "sheet {} is not part of the data file, left over from an earlier run?",
+            path.display()
+        )
+    }));
+    issues
+}
+
+fn verify_stitched_file(path: &Path, entries: &[(String, LuaOutput)]) -> Vec<String> {
+    let folder = path.parent().unwrap_or_else(|| Path::new("."));
+
+    entries
+        .iter()
+        .flat_map(|(name, entry)| {
+            let file = entry
+                .get("filename")
+                .and_then(LuaValue::as_str)
+                .unwrap_or_default();
+            verify_sheets(&[folder.join(file)], entry)
+                .into_iter()
+                .map(move |msg| format!("{name}: {msg}"))
+        })
+        .collect()
+}
+
+/// Compare the layout described by the data with the actual sheets.
+///
+/// Sheets of a data file have to match the layout exactly,
+/// entries placed at `x` / `y` on a stitched sheet only have to fit their region.
+fn verify_sheets(sheets: &[PathBuf], data: &LuaOutput) -> Vec<String> {
+    let layout = match SheetLayout::from_data(data) {
+        Ok(layout) => layout,
+        Err(issue) => return vec![issue],
+    };
+    let mut issues = layout
+        .check_frame_count(sheets.len())
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let get_int = |key: &str| data.get(key).and_then(LuaValue::as_int);
+    let SheetLayout {
+        width,
+        height,
+        line_length,
+        lines_per_file,
+        frames,
+    } = layout;
+    let file_count = sheets.len() as i64;
+    let per_sheet = line_length * lines_per_file;
+
+    if let Some(sequence) = data.get("frame_sequence").and_then(LuaValue::as_array) {
+        let frame_count = get_int("frame_count").or(frames);
+        for (idx, value) in sequence.iter().enumerate() {
+            let in_bounds = value
+                .as_int()
+                .is_some_and(|frame| frame >= 1 && frame_count.is_none_or(|count| frame <= count));
+
+            if !in_bounds {
+                issues.push(format!(
+                    "frame_sequence entry {} ({value}) is out of bounds for {} frames",
+                    idx + 1,
+                    frame_count.map_or_else(|| "?".to_owned(), |count| count.to_string())
+                ));
+            }
+        }
+    }
+
+    let region = (data.get("x").is_some() || data.get("y").is_some())
+        .then(|| (get_int("x").unwrap_or(0), get_int("y").unwrap_or(0)));
+
+    for (idx, sheet) in sheets.iter().enumerate() {
+        let Ok((sheet_width, sheet_height)) = image::image_dimensions(sheet) else {
+            issues.push(format!("sheet {} is missing", sheet.display()));
+            continue;
+        };
+        let actual = (i64::from(sheet_width), i64::from(sheet_height));
+
+        // the last of multiple sheets only has as many lines as it needs
+        let lines = match frames {
+            Some(frames) if file_count > 1 && idx as i64 == file_count - 1 => {
+                let last_count = (frames - per_sheet * (file_count - 1)).clamp(1, per_sheet);
+                (last_count + line_length - 1) / line_length
+            }
+            _ => lines_per_file,
+        };
+        let expected = (width * line_length, height * lines);
+
+        if let Some((x, y)) = region {
+            if x + expected.0 > actual.0 || y + expected.1 > actual.1 {
+                issues.push(format!(
+                    "sheet {} is {}x{}px, too small for {}x{}px at {x}, {y}",
+                    sheet.display(),
+                    actual.0,
+                    actual.1,
+                    expected.0,
+                    expected.1
+                ));
+            }
+        } else if actual != expected {
+            issues.push(format!(
+                "sheet {} is {}x{}px, expected {}x{}px for {line_length}x{lines} frames of {width}x{height}px",
+                sheet.display(),
+                actual.0,
+                actual.1,
+                expected.0,
+                expected.1
+            ));
+        }
+    }
+
+    issues
+}
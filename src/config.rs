@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+
+use crate::commands::Preset;
+
+/// Name of the project config file, looked up in the current working directory.
+pub const PROJECT_CONFIG: &str = "spritter.toml";
+
+/// Key of the job list in the project config, run with `spritter run`.
+const JOBS_KEY: &str = "jobs";
+
+/// Keys of a job that are not passed to its command as options.
+const JOB_KEYS: [&str; 4] = ["name", "command", "source", "output"];
+
+/// Command of the project config, run with the given command line arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// Name to select the job by, the source if not set.
+    pub name: String,
+
+    /// Subcommand followed by its arguments, without the binary name.
+    pub args: Vec<String>,
+}
+
+/// Config files providing default values for the CLI arguments, later files take precedence.
+pub fn config_files() -> Vec<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    config_home
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(PROJECT_CONFIG)))
+        .collect()
+}
+
+/// Use the values of all existing config files as defaults for the arguments of `cmd`.
+///
+/// Top level keys apply to every command that has an argument with that long name,
+/// tables named after a command only apply to that command. Arguments given on the
+/// command line always take precedence. Problems with the config files are returned
+/// as warnings since the logger is not set up yet.
+pub fn apply(mut cmd: Command, warnings: &mut Vec<String>) -> Command {
+    for path in config_files() {
+        if !path.is_file() {
+            continue;
+        }
+
+        match load(&path) {
+            Ok(table) => cmd = apply_table(cmd, &table, &path, warnings),
+            Err(err) => warnings.push(format!("{}: {err}", path.display())),
+        }
+    }
+
+    cmd
+}
+
+/// Use the defaults of `preset` for the arguments of the subcommand `name`.
+///
+/// Presets are applied before the config files, so both the config files and
+/// the command line take precedence over them.
+#[must_use]
+pub fn apply_preset(cmd: Command, name: &str, preset: Preset) -> Command {
+    cmd.mut_subcommand(name, |mut sub| {
+        for &(key, value) in preset.defaults() {
+            let Some(id) = sub
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key))
+                .map(|arg| arg.get_id().clone())
+            else {
+                continue;
+            };
+
+            sub = sub.mut_arg(id, |arg| arg.default_value(value));
+        }
+        sub
+    })
+}
+
+/// Jobs of the project config at `path`.
+///
+/// Every `[[jobs]]` entry names the `command` to run with its `source` and `output`,
+/// all other keys are options of the command, like in the command tables.
+///
+/// # Errors
+///
+/// Fails when the file can't be read or a job is invalid.
+pub fn jobs(path: &Path) -> Result<Vec<Job>, String> {
+    let table = load(path)?;
+    let Some(jobs) = table.get(JOBS_KEY) else {
+        return Ok(Vec::new());
+    };
+
+    let toml::Value::Array(jobs) = jobs else {
+        return Err(format!("{JOBS_KEY} has to be a list of tables"));
+    };
+
+    jobs.iter()
+        .enumerate()
+        .map(|(idx, job)| {
+            let toml::Value::Table(job) = job else {
+                return Err(format!("job {} is not a table", idx + 1));
+            };
+            parse_job(job).map_err(|err| format!("job {}: {err}", idx + 1))
+        })
+        .collect()
+}
+
+fn parse_job(job: &toml::Table) -> Result<Job, String> {
+    let string = |key: &str| match job.get(key) {
+        Some(toml::Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(format!("{key} has to be a string")),
+        None => Ok(None),
+    };
+
+    let command = string("command")?.ok_or("missing command")?;
+    if command == "run" {
+        return Err("jobs can't run other jobs".to_owned());
+    }
+
+    let source = string("source")?;
+    let mut args = std::iter::once(command)
+        .chain(source.clone())
+        .chain(string("output")?)
+        .collect::<Vec<_>>();
+
+    for (key, value) in job {
+        if JOB_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Boolean(true) => {
+                args.push(format!("--{key}"));
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            toml::Value::Array(values) => values.iter().map(value_to_string).collect(),
+            value => value_to_string(value).map(|value| vec![value]),
+        };
+
+        let values = values.ok_or_else(|| format!("unsupported value for {key}"))?;
+        args.extend(values.into_iter().map(|value| format!("--{key}={value}")));
+    }
+
+    Ok(Job {
+        name: string("name")?.or(source).unwrap_or_default(),
+        args,
+    })
+}
+
+fn load(path: &Path) -> Result<toml::Table, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    content
+        .parse()
+        .map_err(|err: toml::de::Error| err.to_string())
+}
+
+fn apply_table(
+    mut cmd: Command,
+    table: &toml::Table,
+    path: &Path,
+    warnings: &mut Vec<String>,
+) -> Command {
+    let subcommands = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_owned())
+        .collect::<Vec<_>>();
+
+    for (key, value) in table {
+        if key == JOBS_KEY {
+            continue;
+        }
+
+        if let toml::Value::Table(section) = value {
+            if !subcommands.contains(key) {
+                warnings.push(format!("{}: unknown command [{key}]", path.display()));
+                continue;
+            }
+
+            cmd = cmd.mut_subcommand(key, |mut sub| {
+                for (key, value) in section {
+                    let (res, found) = set_default(sub, key, value, path, warnings);
+                    sub = res;
+
+                    if !found {
+                        warnings.push(format!(
+                            "{}: unknown option {key} for [{}]",
+                            path.display(),
+                            sub.get_name()
+                        ));
+                    }
+                }
+                sub
+            });
+            continue;
+        }
+
+        let (res, mut found) = set_default(cmd, key, value, path, warnings);
+        cmd = res;
+
+        for name in &subcommands {
+            let mut found_in_sub = false;
+            cmd = cmd.mut_subcommand(name, |sub| {
+                let (sub, found) = set_default(sub, key, value, path, warnings);
+                found_in_sub = found;
+                sub
+            });
+            found |= found_in_sub;
+        }
+
+        if !found {
+            warnings.push(format!("{}: unknown option {key}", path.display()));
+        }
+    }
+
+    cmd
+}
+
+/// Set the default of the argument with the long name `key`, returns if the argument exists.
+fn set_default(
+    cmd: Command,
+    key: &str,
+    value: &toml::Value,
+    path: &Path,
+    warnings: &mut Vec<String>,
+) -> (Command, bool) {
+    let Some(id) = cmd
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(key))
+        .map(|arg| arg.get_id().clone())
+    else {
+        return (cmd, false);
+    };
+
+    let values = match value {
+        toml::Value::Array(values) => values.iter().map(value_to_string).collect(),
+        value => vec![value_to_string(value)],
+    };
+
+    let Some(values) = values.into_iter().collect::<Option<Vec<_>>>() else {
+        warnings.push(format!("{}: unsupported value for {key}", path.display()));
+        return (cmd, true);
+    };
+
+    (cmd.mut_arg(id, |arg| arg.default_values(values)), true)
+}
+
+pub(crate) fn value_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
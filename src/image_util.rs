@@ -1,23 +1,44 @@
 use std::{
     borrow::Cow,
+    cell::OnceCell,
     collections::HashMap,
     fs,
     io::Write,
+    num::{NonZeroU32, NonZeroU8},
     ops::Deref,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Once,
+    },
 };
 
+use crate::{check, progress::Progress, summary};
 use image::{
-    codecs::png, EncodableLayout, ImageBuffer, ImageEncoder, ImageReader, PixelWithColorType, Rgba,
-    RgbaImage,
+    codecs::png, EncodableLayout, ImageBuffer, ImageEncoder, ImageFormat, ImageReader,
+    PixelWithColorType, Rgba, RgbaImage,
 };
 use imagequant::{Attributes, Histogram, HistogramEntry};
+use rayon::{
+    iter::{
+        IndexedParallelIterator as _, IntoParallelIterator as _, IntoParallelRefIterator as _,
+        ParallelIterator as _,
+    },
+    slice::ParallelSlice as _,
+    ThreadPool, ThreadPoolBuilder,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ImgUtilError {
     #[error("io error: {0}")]
     IOError(#[from] std::io::Error),
 
+    #[error("unable to read {path}: {1}", path = .0.display())]
+    ReadError(PathBuf, std::io::Error),
+
+    #[error("unable to write {path}: {1}", path = .0.display())]
+    WriteError(PathBuf, std::io::Error),
+
     #[error("image error: {0}")]
     ImageError(#[from] image::ImageError),
 
@@ -27,6 +48,15 @@ pub enum ImgUtilError {
     #[error("oxipng error: {0}")]
     OxipngError(#[from] oxipng::PngError),
 
+    #[error("apng error: {0}")]
+    ApngError(#[from] ::png::EncodingError),
+
+    #[error("png error: {0}")]
+    PngDecodingError(#[from] ::png::DecodingError),
+
+    #[error("invalid palette file: {0}")]
+    InvalidPalette(String),
+
     #[error("no images to crop")]
     NoImagesToCrop,
 
@@ -39,20 +69,148 @@ pub enum ImgUtilError {
 
 type ImgUtilResult<T> = std::result::Result<T, ImgUtilError>;
 
-pub fn load_from_path_with_path(path: &Path) -> ImgUtilResult<Vec<(RgbaImage, PathBuf)>> {
+/// Attribute an io failure to reading the input `path`.
+pub fn read_error(path: &Path) -> impl Fn(std::io::Error) -> ImgUtilError + '_ {
+    move |err| ImgUtilError::ReadError(path.to_owned(), err)
+}
+
+/// Attribute an io failure to writing the output `path`.
+pub fn write_error(path: &Path) -> impl Fn(std::io::Error) -> ImgUtilError + '_ {
+    move |err| ImgUtilError::WriteError(path.to_owned(), err)
+}
+
+/// Attribute io failures while decoding the input `path` to reading it.
+fn decode_error(path: &Path) -> impl Fn(image::ImageError) -> ImgUtilError + '_ {
+    move |err| match err {
+        image::ImageError::IoError(err) => ImgUtilError::ReadError(path.to_owned(), err),
+        err => err.into(),
+    }
+}
+
+/// Palette files to reuse or record for the grouped lossy compression.
+#[derive(Debug, Clone, Default)]
+pub struct PaletteOptions {
+    /// Remap to the palette stored in this file instead of quantizing.
+    pub input: Option<PathBuf>,
+
+    /// Store the used palette in this file.
+    pub output: Option<PathBuf>,
+}
+
+impl PaletteOptions {
+    const fn is_set(&self) -> bool {
+        self.input.is_some() || self.output.is_some()
+    }
+}
+
+/// Threads of the pool each [oxipng] run is confined to, 0 shares the global pool.
+static OXIPNG_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Limit the threads [oxipng] uses for a single image, set by `--oxipng-threads`.
+///
+/// By default it works on the same pool that encodes the images in parallel,
+/// with a limit every thread encoding an image gets its own small pool instead.
+/// The global pool has to be shrunk accordingly to stay within `--threads`.
+pub fn set_oxipng_threads(threads: usize) {
+    OXIPNG_THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Run [oxipng] on an encoded png, confined to the `--oxipng-threads` limit.
+fn oxipng_optimize(data: &[u8], opts: &oxipng::Options) -> ImgUtilResult<Vec<u8>> {
+    thread_local! {
+        static POOL: OnceCell<Option<ThreadPool>> = const { OnceCell::new() };
+    }
+
+    let threads = OXIPNG_THREADS.load(Ordering::Relaxed);
+    if threads == 0 {
+        return Ok(oxipng::optimize_from_memory(data, opts)?);
+    }
+
+    let optimized = POOL.with(|pool| {
+        let pool = pool.get_or_init(|| {
+            ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .inspect_err(|err| warn!("unable to limit oxipng threads: {err}"))
+                .ok()
+        });
+
+        let optimize = || oxipng::optimize_from_memory(data, opts);
+        pool.as_ref()
+            .map_or_else(optimize, |pool| pool.install(optimize))
+    })?;
+
+    Ok(optimized)
+}
+
+/// Settings for the final PNG encoding and [oxipng] optimization pass.
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions {
+    /// Use the Zopfli deflater with the given amount of iterations instead of libdeflate.
+    pub zopfli: Option<NonZeroU8>,
+
+    /// Write Adam7 interlaced PNGs instead of non-interlaced ones.
+    pub interlace: bool,
+
+    /// [imagequant] speed for lossy compression, 1 (slowest, best quality) to 10 (fastest).
+    pub quant_speed: u8,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            zopfli: None,
+            interlace: false,
+            quant_speed: 1,
+        }
+    }
+}
+
+impl EncodeOptions {
+    fn oxipng_options(self) -> oxipng::Options {
+        let mut opts = oxipng::Options::max_compression();
+        opts.optimize_alpha = true;
+        opts.scale_16 = true;
+        opts.force = true;
+
+        if let Some(iterations) = self.zopfli {
+            opts.deflate = oxipng::Deflaters::Zopfli { iterations };
+        }
+
+        if self.interlace {
+            opts.interlace = Some(oxipng::Interlacing::Adam7);
+        }
+
+        opts
+    }
+}
+
+/// Check if the extension of `path` belongs to one of the `formats`.
+fn has_format(path: &Path, formats: &[ImageFormat]) -> bool {
+    path.extension()
+        .and_then(ImageFormat::from_extension)
+        .is_some_and(|format| formats.contains(&format))
+}
+
+/// Sorted paths of all images in `formats` in `path`, or `path` itself if it is such an image.
+///
+/// # Errors
+///
+/// Returns [`ImgUtilError::ReadError`] when `path` doesn't exist or can't be listed.
+pub fn image_paths(path: &Path, formats: &[ImageFormat]) -> ImgUtilResult<Vec<PathBuf>> {
     if !path.exists() {
-        return Err(ImgUtilError::IOError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("path not found: {}", path.display()),
-        )));
+        return Err(ImgUtilError::ReadError(
+            path.to_owned(),
+            std::io::Error::new(std::io::ErrorKind::NotFound, "path not found"),
+        ));
     }
 
-    if path.is_file() && path.extension().unwrap_or_default() == "png" {
-        return Ok(vec![(load_image_from_file(path)?, path.to_path_buf())]);
+    if path.is_file() && has_format(path, formats) {
+        return Ok(vec![path.to_path_buf()]);
     }
 
-    let mut images = Vec::new();
-    let mut files = fs::read_dir(path)?
+    let mut files = fs::read_dir(path)
+        .map_err(read_error(path))?
         .filter_map(|res| res.map_or(None, |e| Some(e.path())))
         .collect::<Vec<_>>();
 
@@ -62,107 +220,306 @@ pub fn load_from_path_with_path(path: &Path) -> ImgUtilResult<Vec<(RgbaImage, Pa
         natord::compare(&a, &b)
     });
 
-    for path in files {
-        // skip directories, no recursive search
-        if path.is_dir() {
-            continue;
-        }
-
-        if path.extension().unwrap_or_default() != "png" {
-            continue;
-        }
-
-        if !path.exists() {
-            continue;
-        }
+    // skip directories, no recursive search
+    files.retain(|path| !path.is_dir() && has_format(path, formats) && path.exists());
 
-        images.push((load_image_from_file(&path)?, path));
-    }
+    Ok(files)
+}
 
-    Ok(images)
+/// All images in `formats` in `path` together with their paths, in the order of [`image_paths`].
+///
+/// # Errors
+///
+/// Fails when an image can't be read or decoded.
+pub fn load_from_path_with_path(
+    path: &Path,
+    formats: &[ImageFormat],
+) -> ImgUtilResult<Vec<(RgbaImage, PathBuf)>> {
+    let paths = image_paths(path, formats)?;
+    let progress = Progress::new("loaded", paths.len());
+
+    // decode in parallel, collecting into a Vec keeps the sorted order
+    let images = paths
+        .into_par_iter()
+        .map(|path| {
+            let image = load_image_from_file(&path)?;
+            progress.inc();
+            Ok((image, path))
+        })
+        .collect();
+
+    progress.finish();
+    images
 }
 
-pub fn load_from_path(path: &Path) -> ImgUtilResult<Vec<RgbaImage>> {
-    let res = load_from_path_with_path(path)?;
+/// Same as [`load_from_path_with_path`] without the paths.
+///
+/// # Errors
+///
+/// Fails when an image can't be read or decoded.
+pub fn load_from_path(path: &Path, formats: &[ImageFormat]) -> ImgUtilResult<Vec<RgbaImage>> {
+    let res = load_from_path_with_path(path, formats)?;
     Ok(res.into_iter().map(|(img, _)| img).collect())
 }
 
+/// Treat all source images as sRGB, ignoring their gamma and color profile chunks.
+static ASSUME_SRGB: AtomicBool = AtomicBool::new(false);
+
+pub fn set_assume_srgb(assume_srgb: bool) {
+    ASSUME_SRGB.store(assume_srgb, Ordering::Relaxed);
+}
+
+/// Load an image as RGBA, pngs with a non sRGB gamma are converted to sRGB.
+///
+/// # Errors
+///
+/// Fails when the file can't be read or decoded.
 pub fn load_image_from_file(path: &Path) -> ImgUtilResult<RgbaImage> {
     trace!("loading image from {}", path.display());
-    let image = ImageReader::open(path)?
-        .with_guessed_format()?
-        .decode()?
-        .to_rgba8();
+    let reader = ImageReader::open(path)
+        .map_err(read_error(path))?
+        .with_guessed_format()
+        .map_err(read_error(path))?;
+    let is_png = reader.format() == Some(ImageFormat::Png);
+    let mut image = reader.decode().map_err(decode_error(path))?.to_rgba8();
+
+    if is_png && !ASSUME_SRGB.load(Ordering::Relaxed) {
+        let file = fs::File::open(path).map_err(read_error(path))?;
+        normalize_gamma(file, path, &mut image)?;
+    }
+
     Ok(image)
 }
 
-pub fn crop_images(images: &mut Vec<RgbaImage>, limit: u8) -> ImgUtilResult<(f64, f64)> {
-    if images.is_empty() {
-        return Err(ImgUtilError::NoImagesToCrop);
+/// Decode an image from raw file bytes, `name` is only used for log messages.
+///
+/// # Errors
+///
+/// Fails when the data can't be decoded.
+pub fn load_image_from_memory(data: &[u8], name: &Path) -> ImgUtilResult<RgbaImage> {
+    trace!("loading image from {}", name.display());
+    let format = image::guess_format(data)?;
+    let mut image = image::load_from_memory_with_format(data, format)?.to_rgba8();
+
+    if format == ImageFormat::Png && !ASSUME_SRGB.load(Ordering::Relaxed) {
+        normalize_gamma(std::io::Cursor::new(data), name, &mut image)?;
     }
 
-    #[allow(clippy::unwrap_used)]
-    let (raw_width, raw_height) = images.first().unwrap().dimensions();
+    Ok(image)
+}
 
-    let mut min_x = u32::MAX;
-    let mut min_y = u32::MAX;
-    let mut max_x = u32::MIN;
-    let mut max_y = u32::MIN;
+/// Convert a png with a non sRGB `gAMA` chunk to sRGB.
+///
+/// Embedded ICC profiles can't be applied, they only trigger a warning.
+fn normalize_gamma(
+    source: impl std::io::Read,
+    path: &Path,
+    image: &mut RgbaImage,
+) -> ImgUtilResult<()> {
+    static ICC_WARNING: Once = Once::new();
+
+    let decoder = ::png::Decoder::new(source);
+    let reader = decoder.read_info()?;
+    let info = reader.info();
+
+    if info.srgb.is_some() {
+        return Ok(());
+    }
 
-    for image in images.iter() {
-        // ensure image has same size
-        if image.width() != raw_width || image.height() != raw_height {
-            return Err(ImgUtilError::NotSameSize);
-        }
+    if info.icc_profile.is_some() {
+        ICC_WARNING.call_once(|| {
+            warn!(
+                "{}: embedded ICC profiles are not supported, colors are used as sRGB (use --assume-srgb to silence this)",
+                path.display()
+            );
+        });
+        return Ok(());
+    }
 
-        let mut x = image
-            .enumerate_pixels()
-            .filter_map(|(x, _, pxl)| if pxl[3] > limit { Some(x) } else { None })
-            .collect::<Vec<_>>();
-        x.sort_unstable();
+    let Some(exponent) = srgb_exponent(info) else {
+        return Ok(());
+    };
 
-        let mut y = image
-            .enumerate_pixels()
-            .filter_map(|(_, y, pxl)| if pxl[3] > limit { Some(y) } else { None })
-            .collect::<Vec<_>>();
-        y.sort_unstable();
+    debug!(
+        "{}: converting from gamma {:.5} to sRGB",
+        path.display(),
+        SRGB_GAMMA / exponent
+    );
 
-        // ensure image is not empty
-        if x.is_empty() || y.is_empty() {
-            continue;
+    let lut: [u8; 256] =
+        std::array::from_fn(|value| ((value as f64 / 255.0).powf(exponent) * 255.0).round() as u8);
+
+    for pxl in image.pixels_mut() {
+        for channel in &mut pxl.0[..3] {
+            *channel = lut[*channel as usize];
         }
+    }
 
-        let local_min_x = x[0];
-        let local_min_y = y[0];
-        let local_max_x = x[x.len() - 1];
-        let local_max_y = y[y.len() - 1];
+    Ok(())
+}
 
-        if min_x > local_min_x {
-            min_x = local_min_x;
-        }
+const SRGB_GAMMA: f64 = 1.0 / 2.2;
 
-        if max_x < local_max_x {
-            max_x = local_max_x;
-        }
+/// Exponent that converts the colors of a png with a `gAMA` chunk to sRGB, `None` if no conversion is needed.
+fn srgb_exponent(info: &::png::Info) -> Option<f64> {
+    let gamma = f64::from(info.source_gamma?.into_value());
+    if gamma <= 0.0 || (gamma - SRGB_GAMMA).abs() < 0.01 {
+        return None;
+    }
 
-        if min_y > local_min_y {
-            min_y = local_min_y;
-        }
+    Some(SRGB_GAMMA / gamma)
+}
 
-        if max_y < local_max_y {
-            max_y = local_max_y;
+/// Load all frames of an animated GIF, PNG or WebP file together with their delay in milliseconds.
+///
+/// Still images are returned as a single frame without delay.
+///
+/// # Errors
+///
+/// Fails when the file can't be read or decoded.
+pub fn load_animation(path: &Path) -> ImgUtilResult<Vec<(RgbaImage, f64)>> {
+    use image::{
+        codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+        AnimationDecoder, Frames, ImageFormat,
+    };
+
+    trace!("loading animation from {}", path.display());
+    let reader = ImageReader::open(path)
+        .map_err(read_error(path))?
+        .with_guessed_format()
+        .map_err(read_error(path))?;
+
+    let frames: Frames = match reader.format() {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader.into_inner())
+            .map_err(decode_error(path))?
+            .into_frames(),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader.into_inner()).map_err(decode_error(path))?;
+            if !decoder.is_apng().map_err(decode_error(path))? {
+                return Ok(vec![(load_image_from_file(path)?, 0.0)]);
+            }
+            decoder.apng().map_err(decode_error(path))?.into_frames()
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader.into_inner()).map_err(decode_error(path))?;
+            if !decoder.has_animation() {
+                return Ok(vec![(load_image_from_file(path)?, 0.0)]);
+            }
+            decoder.into_frames()
         }
+        _ => return Ok(vec![(load_image_from_file(path)?, 0.0)]),
+    };
+
+    Ok(frames
+        .collect_frames()
+        .map_err(decode_error(path))?
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            (
+                frame.into_buffer(),
+                f64::from(numer) / f64::from(denom.max(1)),
+            )
+        })
+        .collect())
+}
+
+/// Inclusive bounding box `(min_x, min_y, max_x, max_y)`.
+pub type Bounds = (u32, u32, u32, u32);
+
+/// Rectangle `(x, y, width, height)`.
+pub type Rect = (u32, u32, u32, u32);
+
+/// Estimated VRAM usage of an image in bytes.
+///
+/// Factorio keeps the sprites uncompressed in its texture atlases, so every pixel takes 4 bytes.
+#[must_use]
+pub fn vram_size(width: u32, height: u32) -> u64 {
+    u64::from(width) * u64::from(height) * 4
+}
+
+/// Bounding box of all pixels with an alpha value above `limit`.
+///
+/// Returns `None` for fully transparent images.
+#[must_use]
+pub fn alpha_bounds(image: &RgbaImage, limit: u8) -> Option<Bounds> {
+    let row_len = image.width() as usize * 4;
+    if row_len == 0 {
+        return None;
     }
 
-    // are all images are empty? (or some other edge case?)
-    if min_x == u32::MAX || min_y == u32::MAX || max_x == u32::MIN || max_y == u32::MIN {
-        return Err(ImgUtilError::AllImagesEmpty);
+    // only the first and last opaque pixel of each row matter
+    image
+        .as_raw()
+        .par_chunks_exact(row_len)
+        .enumerate()
+        .filter_map(|(y, row)| {
+            let first = row.chunks_exact(4).position(|pxl| pxl[3] > limit)?;
+            let last = row.chunks_exact(4).rposition(|pxl| pxl[3] > limit)?;
+            Some((first as u32, y as u32, last as u32, y as u32))
+        })
+        .reduce_with(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+}
+
+/// Alpha weighted sums of the pixel centers with an alpha value above `limit` as `(x, y, weight)`.
+///
+/// Sums of multiple frames can be added up, their centroid is `(x / weight, y / weight)`.
+#[must_use]
+pub fn alpha_moments(image: &RgbaImage, limit: u8) -> (f64, f64, f64) {
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pxl)| pxl[3] > limit)
+        .fold((0.0, 0.0, 0.0), |(sum_x, sum_y, weight), (x, y, pxl)| {
+            let alpha = f64::from(pxl[3]);
+            (
+                alpha.mul_add(f64::from(x) + 0.5, sum_x),
+                alpha.mul_add(f64::from(y) + 0.5, sum_y),
+                weight + alpha,
+            )
+        })
+}
+
+/// Positions of isolated pixels with an alpha value in `limit + 1..=max_alpha`.
+///
+/// A pixel is isolated when none of its 8 neighbors has an alpha above `limit`,
+/// these are usually renderer noise that still grows the crop box.
+#[must_use]
+pub fn stray_pixels(image: &RgbaImage, limit: u8, max_alpha: u8) -> Vec<(u32, u32)> {
+    let (width, height) = image.dimensions();
+
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pxl)| pxl[3] > limit && pxl[3] <= max_alpha)
+        .filter(|&(x, y, _)| {
+            (y.saturating_sub(1)..=(y + 1).min(height - 1)).all(|ny| {
+                (x.saturating_sub(1)..=(x + 1).min(width - 1))
+                    .all(|nx| (nx, ny) == (x, y) || image.get_pixel(nx, ny)[3] <= limit)
+            })
+        })
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+/// Union of two bounding boxes as returned by [`alpha_bounds`].
+#[must_use]
+pub fn union_bounds(a: Option<Bounds>, b: Option<Bounds>) -> Option<Bounds> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+        (a, None) => a,
+        (None, b) => b,
     }
+}
 
+/// Crop rectangle and the resulting shift of the center point when cropping
+/// images of size `raw` to `bounds`, `None` if no cropping is needed.
+#[must_use]
+pub fn crop_rect(
+    (raw_width, raw_height): (u32, u32),
+    (min_x, min_y, max_x, max_y): Bounds,
+) -> Option<(Rect, (f64, f64))> {
     // do we need to crop?
     if min_x == 0 && min_y == 0 && max_x == (raw_width - 1) && max_y == (raw_height - 1) {
-        // no cropping needed
-        return Ok((0.0, 0.0));
+        return None;
     }
 
     let cropped_width = max_x - min_x + 1;
@@ -171,14 +528,6 @@ pub fn crop_images(images: &mut Vec<RgbaImage>, limit: u8) -> ImgUtilResult<(f64
     debug!("cropping from {raw_width}x{raw_height} to {cropped_width}x{cropped_height}");
     trace!("min_x: {min_x}, min_y: {min_y}, max_x: {max_x}, max_y: {max_y}");
 
-    // crop images
-    for image in images {
-        let cropped_image =
-            image::imageops::crop_imm(image, min_x, min_y, cropped_width, cropped_height)
-                .to_image();
-        *image = cropped_image;
-    }
-
     // calculate how the center point shifted relative to the original image
     let mut shift_x = -((f64::from(raw_width - cropped_width) / 2.0) - f64::from(min_x));
     let mut shift_y = -((f64::from(raw_height - cropped_height) / 2.0) - f64::from(min_y));
@@ -193,11 +542,378 @@ pub fn crop_images(images: &mut Vec<RgbaImage>, limit: u8) -> ImgUtilResult<(f64
 
     trace!("shifted by ({shift_x}, {shift_y})");
 
-    Ok((shift_x, shift_y))
+    Some((
+        (min_x, min_y, cropped_width, cropped_height),
+        (shift_x, shift_y),
+    ))
+}
+
+/// Make all pixels within `tolerance` of `color` on every channel fully transparent.
+pub fn key_color(image: &mut RgbaImage, color: [u8; 3], tolerance: u8) {
+    for pxl in image.pixels_mut() {
+        if pxl.0[..3]
+            .iter()
+            .zip(color)
+            .all(|(&value, key)| value.abs_diff(key) <= tolerance)
+        {
+            *pxl = Rgba([0, 0, 0, 0]);
+        }
+    }
+}
+
+/// Whether the color of `pxl` is within `tolerance` of `color` on every channel and it is fully opaque.
+fn is_marker(pxl: Rgba<u8>, color: [u8; 3], tolerance: u8) -> bool {
+    pxl[3] == 255
+        && pxl.0[..3]
+            .iter()
+            .zip(color)
+            .all(|(&value, key)| value.abs_diff(key) <= tolerance)
+}
+
+/// Positions of all opaque pixels within `tolerance` of `color` on every channel.
+#[must_use]
+pub fn find_markers(image: &RgbaImage, color: [u8; 3], tolerance: u8) -> Vec<(u32, u32)> {
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pxl)| is_marker(**pxl, color, tolerance))
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+/// Replace the marker pixel at `x`, `y` with the average of its neighbors that are no markers,
+/// colors are weighted by their alpha so transparent neighbors don't darken it.
+pub fn remove_marker(image: &mut RgbaImage, (x, y): (u32, u32), color: [u8; 3], tolerance: u8) {
+    let (width, height) = image.dimensions();
+
+    let mut sums = [0u32; 4];
+    let mut count = 0;
+    for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+        for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+            let pxl = *image.get_pixel(nx, ny);
+            if (nx, ny) == (x, y) || is_marker(pxl, color, tolerance) {
+                continue;
+            }
+
+            for (sum, &value) in sums[..3].iter_mut().zip(&pxl.0[..3]) {
+                *sum += u32::from(value) * u32::from(pxl[3]);
+            }
+            sums[3] += u32::from(pxl[3]);
+            count += 1;
+        }
+    }
+
+    let Some(alpha) = NonZeroU32::new(sums[3]) else {
+        image.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+        return;
+    };
+
+    image.put_pixel(
+        x,
+        y,
+        Rgba([
+            (sums[0] / alpha) as u8,
+            (sums[1] / alpha) as u8,
+            (sums[2] / alpha) as u8,
+            (sums[3] / count) as u8,
+        ]),
+    );
+}
+
+/// Max luma difference to the more opaque neighbors before a pixel counts as fringe.
+const FRINGE_THRESHOLD: f32 = 24.0;
+
+/// Restore the straight colors of a frame that was exported with premultiplied alpha.
+pub fn unpremultiply(image: &mut RgbaImage) {
+    for pxl in image.pixels_mut().filter(|pxl| pxl[3] > 0 && pxl[3] < 255) {
+        let alpha = u32::from(pxl[3]);
+        for channel in 0..3 {
+            pxl[channel] = ((u32::from(pxl[channel]) * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+}
+
+/// Correct the color of semi transparent edge pixels that got blended with the background.
+///
+/// Edge pixels that are noticeably darker or brighter than their more opaque neighbors
+/// take over the alpha weighted color of those neighbors.
+pub fn clean_fringe(image: &mut RgbaImage) {
+    let semi_transparent = |pxl: &Rgba<u8>| pxl[3] > 0 && pxl[3] < 255;
+    if !image.pixels().any(semi_transparent) {
+        return;
+    }
+
+    let luma = |color: [f32; 3]| {
+        0.2126f32.mul_add(color[0], 0.7152f32.mul_add(color[1], 0.0722 * color[2]))
+    };
+    let source = image.clone();
+    let (width, height) = source.dimensions();
+
+    for (x, y, pxl) in image.enumerate_pixels_mut() {
+        if !semi_transparent(pxl) {
+            continue;
+        }
+
+        let mut sum = [0.0; 3];
+        let mut weight = 0.0;
+        for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+            for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                let neighbor = source.get_pixel(nx, ny);
+                if neighbor[3] <= pxl[3] {
+                    continue;
+                }
+
+                let alpha = f32::from(neighbor[3]);
+                for (acc, &value) in sum.iter_mut().zip(&neighbor.0[..3]) {
+                    *acc += f32::from(value) * alpha;
+                }
+                weight += alpha;
+            }
+        }
+
+        if weight <= 0.0 {
+            continue;
+        }
+
+        let expected = sum.map(|value| value / weight);
+        let color = [pxl[0], pxl[1], pxl[2]].map(f32::from);
+        if (luma(color) - luma(expected)).abs() > FRINGE_THRESHOLD {
+            for (channel, value) in expected.into_iter().enumerate() {
+                pxl[channel] = value.round() as u8;
+            }
+        }
+    }
+}
+
+/// Replace the alpha value of every pixel with its entry in `lut`.
+pub fn map_alpha(image: &mut RgbaImage, lut: &[u8; 256]) {
+    for pxl in image.pixels_mut() {
+        pxl[3] = lut[usize::from(pxl[3])];
+    }
+}
+
+/// Global tone adjustments of the color channels.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorAdjustment {
+    /// Offset added to every channel, `0.0` keeps the colors.
+    pub brightness: f32,
+
+    /// Factor of the distance to mid gray, `1.0` keeps the colors.
+    pub contrast: f32,
+
+    /// Factor of the distance to the luma of the pixel, `1.0` keeps the colors.
+    pub saturation: f32,
+}
+
+impl ColorAdjustment {
+    #[must_use]
+    pub fn is_identity(self) -> bool {
+        self.brightness.abs() <= f32::EPSILON
+            && (self.contrast - 1.0).abs() <= f32::EPSILON
+            && (self.saturation - 1.0).abs() <= f32::EPSILON
+    }
+
+    /// Adjust saturation, contrast and brightness (in that order) of all pixels.
+    pub fn apply(self, image: &mut RgbaImage) {
+        for pxl in image.pixels_mut() {
+            let [r, g, b] = [pxl[0], pxl[1], pxl[2]].map(|value| f32::from(value) / 255.0);
+            let luma = 0.0722f32.mul_add(b, 0.2126f32.mul_add(r, 0.7152 * g));
+
+            for (channel, value) in [r, g, b].into_iter().enumerate() {
+                let value = (value - luma).mul_add(self.saturation, luma);
+                let value = (value - 0.5).mul_add(self.contrast, 0.5) + self.brightness;
+                pxl[channel] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+/// Axes the crop box has to stay centered on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Symmetry {
+    pub horizontal: bool,
+    pub vertical: bool,
+}
+
+impl Symmetry {
+    /// Extend `bounds` of images with `size` so they stay centered on the enabled axes.
+    #[must_use]
+    pub fn apply(
+        self,
+        (min_x, min_y, max_x, max_y): Bounds,
+        (width, height): (u32, u32),
+    ) -> Bounds {
+        let (min_x, max_x) = if self.horizontal {
+            let min_x = min_x.min(width - 1 - max_x);
+            (min_x, width - 1 - min_x)
+        } else {
+            (min_x, max_x)
+        };
+
+        let (min_y, max_y) = if self.vertical {
+            let min_y = min_y.min(height - 1 - max_y);
+            (min_y, height - 1 - min_y)
+        } else {
+            (min_y, max_y)
+        };
+
+        (min_x, min_y, max_x, max_y)
+    }
+}
+
+/// Crop all images to the union of their alpha bounds, extended to keep the `symmetry`.
+///
+/// Returns the crop rectangle and the resulting shift, `None` if no cropping was needed.
+///
+/// # Errors
+///
+/// Fails when there are no images or they are not the same size.
+pub fn crop_images(
+    images: &mut Vec<RgbaImage>,
+    limit: u8,
+    symmetry: Symmetry,
+) -> ImgUtilResult<Option<(Rect, (f64, f64))>> {
+    let Some(raw_size) = images.first().map(ImageBuffer::dimensions) else {
+        return Err(ImgUtilError::NoImagesToCrop);
+    };
+
+    let mut bounds = None;
+    for image in images.iter() {
+        // ensure image has same size
+        if image.dimensions() != raw_size {
+            return Err(ImgUtilError::NotSameSize);
+        }
+
+        bounds = union_bounds(bounds, alpha_bounds(image, limit));
+    }
+
+    // are all images are empty?
+    let Some(bounds) = bounds else {
+        return Err(ImgUtilError::AllImagesEmpty);
+    };
+
+    let bounds = symmetry.apply(bounds, raw_size);
+    let Some(((x, y, width, height), shift)) = crop_rect(raw_size, bounds) else {
+        // no cropping needed
+        return Ok(None);
+    };
+
+    // crop images
+    for image in images {
+        *image = image::imageops::crop_imm(image, x, y, width, height).to_image();
+    }
+
+    Ok(Some(((x, y, width, height), shift)))
+}
+
+/// Mean structural similarity (SSIM) of two equally sized images.
+///
+/// Computed per 8x8 block on the alpha premultiplied RGBA channels,
+/// so color changes of fully transparent pixels don't count.
+#[must_use]
+pub fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const BLOCK: u32 = 8;
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = a.dimensions();
+    let mut total = 0.0;
+    let mut count = 0;
+
+    for block_y in (0..height).step_by(BLOCK as usize) {
+        for block_x in (0..width).step_by(BLOCK as usize) {
+            let block_width = BLOCK.min(width - block_x);
+            let block_height = BLOCK.min(height - block_y);
+            let n = f64::from(block_width * block_height);
+
+            for channel in 0..4 {
+                let mut sum_a = 0.0;
+                let mut sum_b = 0.0;
+                let mut sq_a = 0.0;
+                let mut sq_b = 0.0;
+                let mut cross = 0.0;
+
+                for y in block_y..(block_y + block_height) {
+                    for x in block_x..(block_x + block_width) {
+                        let va = premultiplied(*a.get_pixel(x, y), channel);
+                        let vb = premultiplied(*b.get_pixel(x, y), channel);
+
+                        sum_a += va;
+                        sum_b += vb;
+                        sq_a += va * va;
+                        sq_b += vb * vb;
+                        cross += va * vb;
+                    }
+                }
+
+                let mean_a = sum_a / n;
+                let mean_b = sum_b / n;
+                let var_a = mean_a.mul_add(-mean_a, sq_a / n);
+                let var_b = mean_b.mul_add(-mean_b, sq_b / n);
+                let covar = mean_a.mul_add(-mean_b, cross / n);
+
+                total += ((2.0 * mean_a).mul_add(mean_b, C1) * 2.0f64.mul_add(covar, C2))
+                    / (mean_a.mul_add(mean_a, mean_b.mul_add(mean_b, C1)) * (var_a + var_b + C2));
+                count += 1;
+            }
+        }
+    }
+
+    if count == 0 {
+        return 1.0;
+    }
+
+    total / f64::from(count)
+}
+
+/// Peak signal-to-noise ratio (PSNR) in dB of two equally sized images.
+///
+/// Computed on the alpha premultiplied RGBA channels, identical images result in infinity.
+#[must_use]
+pub fn psnr(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let mut sum = 0.0;
+
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        for channel in 0..4 {
+            let diff = premultiplied(*pa, channel) - premultiplied(*pb, channel);
+            sum += diff * diff;
+        }
+    }
+
+    let mse = sum / a.as_raw().len().max(1) as f64;
+    if mse <= f64::EPSILON {
+        return f64::INFINITY;
+    }
+
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+fn premultiplied(pxl: Rgba<u8>, channel: usize) -> f64 {
+    if channel == 3 {
+        f64::from(pxl[3])
+    } else {
+        f64::from(pxl[channel]) * f64::from(pxl[3]) / 255.0
+    }
 }
 
 pub trait ImageBufferExt<P, C> {
-    fn save_optimized_png(&self, path: impl AsRef<Path>, lossy: bool) -> ImgUtilResult<u64>;
+    /// Encode the image as png, optionally quantized, optimize it with [oxipng] and write it to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the image can't be quantized, encoded or written.
+    fn save_optimized_png(
+        &self,
+        path: impl AsRef<Path>,
+        lossy: bool,
+        opts: EncodeOptions,
+    ) -> ImgUtilResult<u64>;
+
+    /// Same as [`ImageBufferExt::save_optimized_png`] but returns the encoded png instead of writing it.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the image can't be quantized or encoded.
+    fn to_optimized_png(&self, lossy: bool, opts: EncodeOptions) -> ImgUtilResult<Vec<u8>>;
 
     fn get_histogram(&self) -> Box<[HistogramEntry]>;
     fn to_quant_img(&self) -> Box<[imagequant::RGBA]>;
@@ -207,40 +923,59 @@ impl<C> ImageBufferExt<Rgba<u8>, C> for ImageBuffer<Rgba<u8>, C>
 where
     C: Deref<Target = [u8]>,
 {
-    fn save_optimized_png(&self, path: impl AsRef<Path>, lossy: bool) -> ImgUtilResult<u64> {
+    fn save_optimized_png(
+        &self,
+        path: impl AsRef<Path>,
+        lossy: bool,
+        opts: EncodeOptions,
+    ) -> ImgUtilResult<u64> {
         trace!("saving image to {}", path.as_ref().display());
         let (width, height) = self.dimensions();
+        optimize_png(
+            &raw_png_buffer(self, lossy, opts)?,
+            width,
+            height,
+            path,
+            opts,
+        )
+    }
 
-        let buf = if lossy {
-            let quant = quantization_attributes()?;
-            let mut img =
-                quant.new_image(self.to_quant_img(), width as usize, height as usize, 0.0)?;
-
-            let mut qres = quant.quantize(&mut img)?;
-            qres.set_dithering_level(1.0)?;
-
-            let (palette, pxls) = qres.remapped(&mut img)?;
-            image_buf_from_palette(width, height, &convert_palette(&palette), &pxls)
-        } else {
-            Cow::Borrowed(self.as_bytes())
-        };
-
-        optimize_png(&buf, width, height, path)
+    fn to_optimized_png(&self, lossy: bool, opts: EncodeOptions) -> ImgUtilResult<Vec<u8>> {
+        let (width, height) = self.dimensions();
+        encode_optimized_png(&raw_png_buffer(self, lossy, opts)?, width, height, opts)
     }
 
     fn get_histogram(&self) -> Box<[HistogramEntry]> {
-        let mut res = HashMap::new();
-
-        for pxl in self.pixels() {
-            let key = (pxl[0], pxl[1], pxl[2], pxl[3]);
-            let entry = res.entry(key).or_insert(0);
-            *entry += 1;
-        }
-
-        res.iter()
-            .map(|(&(r, g, b, a), v)| HistogramEntry {
-                color: imagequant::RGBA { r, g, b, a },
-                count: *v,
+        /// Bytes per chunk of the raw buffer that is counted on its own.
+        const CHUNK_SIZE: usize = 4 * 64 * 1024;
+
+        // count the pixels packed into a single u32 per chunk, then merge the chunk counts
+        let res = self
+            .as_bytes()
+            .par_chunks(CHUNK_SIZE)
+            .fold(HashMap::new, |mut counts, chunk| {
+                for pxl in chunk.chunks_exact(4) {
+                    let key = u32::from_ne_bytes([pxl[0], pxl[1], pxl[2], pxl[3]]);
+                    *counts.entry(key).or_insert(0) += 1;
+                }
+                counts
+            })
+            .reduce(HashMap::new, |a, b| {
+                // merge the smaller map into the larger one
+                if a.len() < b.len() {
+                    merge_counts(b, a)
+                } else {
+                    merge_counts(a, b)
+                }
+            });
+
+        res.into_iter()
+            .map(|(key, count)| {
+                let [r, g, b, a] = key.to_ne_bytes();
+                HistogramEntry {
+                    color: imagequant::RGBA { r, g, b, a },
+                    count,
+                }
             })
             .collect()
     }
@@ -257,20 +992,137 @@ where
     }
 }
 
-pub fn quantization_attributes() -> ImgUtilResult<Attributes> {
+/// Add the color counts of `b` to `a`.
+fn merge_counts(mut a: HashMap<u32, u32>, b: HashMap<u32, u32>) -> HashMap<u32, u32> {
+    for (key, count) in b {
+        *a.entry(key).or_insert(0) += count;
+    }
+    a
+}
+
+/// Color counts of several images, filled per thread and merged into one histogram.
+#[derive(Debug, Default)]
+pub struct ColorCounts(HashMap<imagequant::RGBA, u32>);
+
+impl ColorCounts {
+    pub fn add(&mut self, histogram: &[HistogramEntry]) {
+        for entry in histogram {
+            let count = self.0.entry(entry.color).or_insert(0);
+            *count = count.saturating_add(entry.count);
+        }
+    }
+
+    /// Merge the smaller counts into the larger ones.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        let (mut a, b) = if self.0.len() < other.0.len() {
+            (other, self)
+        } else {
+            (self, other)
+        };
+
+        for (color, count) in b.0 {
+            let total = a.0.entry(color).or_insert(0);
+            *total = total.saturating_add(count);
+        }
+        a
+    }
+
+    /// Histogram entries sorted by color, so quantizing the same images gives the same palette.
+    #[must_use]
+    pub fn histogram(&self) -> Box<[HistogramEntry]> {
+        let mut entries = self
+            .0
+            .iter()
+            .map(|(&color, &count)| HistogramEntry { color, count })
+            .collect::<Box<[_]>>();
+        entries.sort_unstable_by_key(|entry| {
+            let color = entry.color;
+            [color.r, color.g, color.b, color.a]
+        });
+        entries
+    }
+}
+
+/// Quantization settings for the given speed [1-10].
+///
+/// # Errors
+///
+/// Fails when the speed is out of range.
+pub fn quantization_attributes(speed: u8) -> ImgUtilResult<Attributes> {
     let mut attr = Attributes::new();
-    attr.set_speed(1)?;
+    attr.set_speed(i32::from(speed))?;
 
     Ok(attr)
 }
 
+/// Raw RGBA pixels of the image, quantized first if `lossy` is set.
+fn raw_png_buffer<C>(
+    image: &ImageBuffer<Rgba<u8>, C>,
+    lossy: bool,
+    opts: EncodeOptions,
+) -> ImgUtilResult<Cow<'_, [u8]>>
+where
+    C: Deref<Target = [u8]>,
+{
+    if !lossy {
+        return Ok(Cow::Borrowed(image.as_bytes()));
+    }
+
+    let (width, height) = image.dimensions();
+    let quant = quantization_attributes(opts.quant_speed)?;
+    let mut img = quant.new_image(image.to_quant_img(), width as usize, height as usize, 0.0)?;
+
+    let mut qres = quant.quantize(&mut img)?;
+    qres.set_dithering_level(1.0)?;
+
+    let (palette, pxls) = qres.remapped(&mut img)?;
+    Ok(image_buf_from_palette(
+        width,
+        height,
+        &convert_palette(&palette),
+        &pxls,
+    ))
+}
+
 /// Encode image as PNG and optimize with [oxipng] before writing to disk.
+///
+/// # Errors
+///
+/// Fails when the image can't be encoded or written.
 pub fn optimize_png(
     buf: &[u8],
     width: u32,
     height: u32,
     path: impl AsRef<Path>,
+    opts: EncodeOptions,
 ) -> ImgUtilResult<u64> {
+    if check::enabled() {
+        return Ok(check::image(buf, width, height, path.as_ref()));
+    }
+
+    debug!("optimizing {}", path.as_ref().display());
+    let res = encode_optimized_png(buf, width, height, opts)?;
+    let path = path.as_ref();
+    fs::File::create(path)
+        .and_then(|mut file| file.write_all(&res))
+        .map_err(write_error(path))?;
+    summary::record_file(path);
+
+    Ok(res.len() as u64)
+}
+
+/// Encode image as PNG and optimize with [oxipng] in memory.
+///
+/// # Errors
+///
+/// Fails when the image can't be encoded.
+pub fn encode_optimized_png(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    opts: EncodeOptions,
+) -> ImgUtilResult<Vec<u8>> {
     let mut data = Vec::new();
     png::PngEncoder::new_with_quality(
         &mut data,
@@ -284,18 +1136,135 @@ pub fn optimize_png(
         <Rgba<u8> as PixelWithColorType>::COLOR_TYPE,
     )?;
 
-    let mut opts = oxipng::Options::max_compression();
-    opts.optimize_alpha = true;
-    opts.scale_16 = true;
-    opts.force = true;
+    oxipng_optimize(&data, &opts.oxipng_options())
+}
 
-    debug!("optimizing {}", path.as_ref().display());
-    let res = oxipng::optimize_from_memory(&data, &opts)?;
-    fs::File::create(path)?.write_all(&res)?;
+/// Whether the png has an animation control chunk, checks only the header.
+pub fn is_apng(source: impl std::io::Read) -> bool {
+    ::png::Decoder::new(source)
+        .read_info()
+        .is_ok_and(|reader| reader.info().animation_control.is_some())
+}
 
-    Ok(res.len() as u64)
+/// Optimize an animated png with [oxipng] as is, all frames are kept.
+///
+/// # Errors
+///
+/// Fails when the data isn't a valid png.
+pub fn optimize_apng(data: &[u8], opts: EncodeOptions) -> ImgUtilResult<Vec<u8>> {
+    oxipng_optimize(data, &opts.oxipng_options())
 }
 
+/// Optimize an encoded palette or grayscale png with [oxipng] as is, keeping its compact color type
+/// instead of round-tripping it through RGBA.
+///
+/// Returns `None` for other color types and pngs that need a gamma conversion.
+///
+/// # Errors
+///
+/// Fails when the data isn't a valid png.
+pub fn optimize_encoded_png(data: &[u8], opts: EncodeOptions) -> ImgUtilResult<Option<Vec<u8>>> {
+    use ::png::ColorType;
+
+    // files with a png extension that are something else are decoded as usual
+    let Ok(reader) = ::png::Decoder::new(std::io::Cursor::new(data)).read_info() else {
+        return Ok(None);
+    };
+    let info = reader.info();
+
+    if !matches!(
+        info.color_type,
+        ColorType::Indexed | ColorType::Grayscale | ColorType::GrayscaleAlpha
+    ) {
+        return Ok(None);
+    }
+
+    if !ASSUME_SRGB.load(Ordering::Relaxed) && srgb_exponent(info).is_some() {
+        return Ok(None);
+    }
+
+    trace!("optimizing {:?} png as is", info.color_type);
+
+    // the RGBA path writes no metadata either
+    let mut oxipng_opts = opts.oxipng_options();
+    oxipng_opts.strip = oxipng::StripChunks::Safe;
+
+    oxipng_optimize(data, &oxipng_opts).map(Some)
+}
+
+/// Quantize image with the given target quality [0-100] and encode it as optimized PNG in memory.
+///
+/// # Errors
+///
+/// Fails when the image can't be quantized or encoded.
+pub fn encode_quantized_png(
+    image: &RgbaImage,
+    quality: u8,
+    opts: EncodeOptions,
+) -> ImgUtilResult<Vec<u8>> {
+    let (width, height) = image.dimensions();
+
+    let mut quant = quantization_attributes(opts.quant_speed)?;
+    quant.set_quality(0, quality)?;
+    let mut img = quant.new_image(image.to_quant_img(), width as usize, height as usize, 0.0)?;
+
+    let mut qres = quant.quantize(&mut img)?;
+    qres.set_dithering_level(1.0)?;
+
+    let (palette, pxls) = qres.remapped(&mut img)?;
+    encode_optimized_png(
+        &image_buf_from_palette(width, height, &convert_palette(&palette), &pxls),
+        width,
+        height,
+        opts,
+    )
+}
+
+/// Save frames as an infinitely looping animated PNG.
+///
+/// `animation_speed` is identical to the in-game speed, 1.0 means 60 frames per second.
+///
+/// # Errors
+///
+/// Fails when the frames differ in size or the file can't be encoded or written.
+pub fn save_apng(
+    images: &[RgbaImage],
+    path: impl AsRef<Path>,
+    animation_speed: f64,
+) -> ImgUtilResult<()> {
+    let Some(first) = images.first() else {
+        return Ok(());
+    };
+
+    let (width, height) = first.dimensions();
+    let mut data = Vec::new();
+
+    let mut encoder = ::png::Encoder::new(&mut data, width, height);
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+    encoder.set_animated(images.len() as u32, 0)?;
+    // the delay is a fraction of u16s, use the largest numerator that keeps it exact at common speeds
+    let fps = 60.0 * animation_speed;
+    let numer = (f64::from(u16::MAX) / fps).clamp(1.0, 1000.0).floor();
+    let denom = (numer * fps).round().clamp(1.0, f64::from(u16::MAX));
+    encoder.set_frame_delay(numer as u16, denom as u16)?;
+
+    let mut writer = encoder.write_header()?;
+    for image in images {
+        if image.dimensions() != (width, height) {
+            return Err(ImgUtilError::NotSameSize);
+        }
+
+        writer.write_image_data(image.as_raw())?;
+    }
+    writer.finish()?;
+
+    let path = path.as_ref();
+    check::write(path, &data).map_err(write_error(path))?;
+    Ok(())
+}
+
+#[must_use]
 pub fn convert_palette<'a>(palette: &[imagequant::RGBA]) -> Cow<'a, [[u8; 4]]> {
     palette
         .iter()
@@ -303,6 +1272,34 @@ pub fn convert_palette<'a>(palette: &[imagequant::RGBA]) -> Cow<'a, [[u8; 4]]> {
         .collect()
 }
 
+/// Load a palette file written by [`save_palette`].
+///
+/// # Errors
+///
+/// Fails when the file can't be read or isn't a palette of at most 256 RGBA colors.
+pub fn load_palette(path: &Path) -> ImgUtilResult<Vec<[u8; 4]>> {
+    let data = fs::read(path).map_err(read_error(path))?;
+    if data.is_empty() || data.len() % 4 != 0 || data.len() / 4 > 256 {
+        return Err(ImgUtilError::InvalidPalette(path.display().to_string()));
+    }
+
+    Ok(data
+        .chunks_exact(4)
+        .map(|color| [color[0], color[1], color[2], color[3]])
+        .collect())
+}
+
+/// Save a palette as raw RGBA bytes, 4 bytes per color.
+///
+/// # Errors
+///
+/// Fails when the file can't be written.
+pub fn save_palette(path: &Path, palette: &[[u8; 4]]) -> ImgUtilResult<()> {
+    check::write(path, &palette.concat()).map_err(write_error(path))?;
+    Ok(())
+}
+
+#[must_use]
 pub fn image_buf_from_palette<'a>(
     width: u32,
     height: u32,
@@ -319,73 +1316,105 @@ pub fn image_buf_from_palette<'a>(
 /// This will also optimize the images using [oxipng].
 /// When `lossy` is true the images will also be compressed using [imagequant].
 /// When `group` is true and there are multiple sheets it will generate a histogram and quantize ahead of time.
+/// The `palette` files allow to reuse the palette of the lossy compression across runs.
+///
+/// # Errors
+///
+/// Fails when a sheet can't be quantized, encoded or written, or a palette file can't be read or written.
 pub fn save_sheets(
     sheets: &[(RgbaImage, PathBuf)],
     lossy: bool,
     group: bool,
+    opts: EncodeOptions,
+    palette: &PaletteOptions,
 ) -> ImgUtilResult<Box<[u64]>> {
     let sheets_count = sheets.len();
-    let mut sizes = Vec::with_capacity(sheets_count);
+    let progress = Progress::new("saved", sheets_count);
     // more than one sheet, lossy compression and grouping -> generate histogram and quantize ahead of time
-    if sheets_count > 1 && lossy && group {
-        info!("analyzing multiple images for quantization (grouped lossy compression)");
-
-        let quant = quantization_attributes()?;
-        let mut histo = Histogram::new(&quant);
+    if lossy && ((sheets_count > 1 && group) || palette.is_set()) {
+        let mut quant = quantization_attributes(opts.quant_speed)?;
 
-        for (sheet, _) in sheets {
-            histo.add_colors(&sheet.get_histogram(), 0.0)?;
-        }
+        let mut qres = if let Some(path) = &palette.input {
+            info!("using palette from {}", path.display());
 
-        let mut qres = histo.quantize(&quant)?;
-        qres.set_dithering_level(1.0)?;
-        let palette = convert_palette(qres.palette());
+            let colors = load_palette(path)?;
+            quant.set_max_colors(colors.len() as u32)?;
 
-        info!("analyzing done, saving images");
+            let mut histo = Histogram::new(&quant);
+            for &[r, g, b, a] in &colors {
+                histo.add_fixed_color(imagequant::RGBA { r, g, b, a }, 0.0)?;
+            }
 
-        for (idx, (sheet, path)) in sheets.iter().enumerate() {
-            trace!("saving image to {}", path.display());
+            histo.quantize(&quant)?
+        } else {
+            info!("analyzing multiple images for quantization (grouped lossy compression)");
 
-            let (width, height) = sheet.dimensions();
-            let w_usize = width as usize;
-            let h_usize = height as usize;
-            let mut img = quant.new_image(sheet.to_quant_img(), w_usize, h_usize, 0.0)?;
+            let mut histo = Histogram::new(&quant);
+            for (sheet, _) in sheets {
+                histo.add_colors(&sheet.get_histogram(), 0.0)?;
+            }
 
-            let mut pxls = Vec::with_capacity(w_usize * h_usize);
-            qres.remap_into_vec(&mut img, &mut pxls)?;
+            histo.quantize(&quant)?
+        };
 
-            sizes.push(optimize_png(
-                &image_buf_from_palette(width, height, &palette, &pxls),
-                width,
-                height,
-                path,
-            )?);
+        qres.set_dithering_level(1.0)?;
+        let palette_colors = convert_palette(qres.palette());
 
-            if sheets_count > 10 && (idx + 1) % 10 == 0 {
-                info!("saved {}/{}", idx + 1, sheets_count);
-            }
+        if let Some(path) = &palette.output {
+            save_palette(path, &palette_colors)?;
         }
 
-        if sheets_count > 10 && sheets_count % 10 != 0 {
-            info!("saved {}/{}", sheets_count, sheets_count);
-        }
+        info!("analyzing done, saving images");
+
+        // remapping refines the shared quantization result, so it runs in sheet order
+        // to keep the output deterministic, only the encoding runs in parallel
+        let remapped = sheets
+            .iter()
+            .map(|(sheet, path)| {
+                let (width, height) = sheet.dimensions();
+                let w_usize = width as usize;
+                let h_usize = height as usize;
+                let mut img = quant.new_image(sheet.to_quant_img(), w_usize, h_usize, 0.0)?;
+
+                let mut pxls = Vec::with_capacity(w_usize * h_usize);
+                qres.remap_into_vec(&mut img, &mut pxls)?;
+                Ok((pxls, width, height, path))
+            })
+            .collect::<ImgUtilResult<Vec<_>>>()?;
+
+        let sizes = remapped
+            .par_iter()
+            .map(|&(ref pxls, width, height, path)| {
+                trace!("saving image to {}", path.display());
+
+                let size = optimize_png(
+                    &image_buf_from_palette(width, height, &palette_colors, pxls),
+                    width,
+                    height,
+                    path,
+                    opts,
+                )?;
+
+                progress.inc();
+                Ok(size)
+            })
+            .collect::<ImgUtilResult<Box<_>>>()?;
 
-        return Ok(sizes.into_boxed_slice());
+        progress.finish();
+        return Ok(sizes);
     }
 
     // regular optimized saving
     info!("saving image(s)");
-    for (idx, (sheet, path)) in sheets.iter().enumerate() {
-        sizes.push(sheet.save_optimized_png(path, lossy)?);
-
-        if sheets_count > 10 && (idx + 1) % 10 == 0 {
-            info!("saved {}/{}", idx + 1, sheets_count);
-        }
-    }
-
-    if sheets_count > 10 && sheets_count % 10 != 0 {
-        info!("saved {}/{}", sheets_count, sheets_count);
-    }
-
-    Ok(sizes.into_boxed_slice())
+    let sizes = sheets
+        .par_iter()
+        .map(|(sheet, path)| {
+            let size = sheet.save_optimized_png(path, lossy, opts)?;
+            progress.inc();
+            Ok(size)
+        })
+        .collect::<ImgUtilResult<Box<_>>>()?;
+
+    progress.finish();
+    Ok(sizes)
 }
@@ -0,0 +1,29 @@
+//! Spritesheet generator for factorio.
+//!
+//! The `spritter` binary is a thin CLI on top of this library. Other tools can embed it
+//! by building the arguments of a command with [`parse_args`] and calling the command directly:
+//!
+//! ```no_run
+//! let args: spritter::SpritesheetArgs = spritter::parse_args(["sprites", "out", "--lua"])?;
+//! if let Some((name, data)) = spritter::generate_spritesheet(&args, &args.source)? {
+//!     println!("generated {name}: {:?}", data.get("shift"));
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[macro_use]
+extern crate log;
+
+pub mod check;
+pub mod commands;
+pub mod config;
+pub mod image_util;
+pub mod lockfile;
+pub mod logger;
+pub mod lua;
+pub mod lut;
+pub mod progress;
+pub mod summary;
+
+pub use commands::{generate_spritesheet, parse_args, CommandError, SheetResult, SpritesheetArgs};
+pub use lua::LuaOutput;
@@ -0,0 +1,260 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use sha2::Digest as _;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockfileError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("invalid lockfile {0}: {1}")]
+    Invalid(PathBuf, String),
+
+    #[error("unable to write {path}: {1}", path = .0.display())]
+    WriteError(PathBuf, std::io::Error),
+}
+
+/// Hashes of the source frames and the flags of every output, written with `--lockfile`.
+#[derive(Debug, Default)]
+pub struct Lockfile {
+    outputs: BTreeMap<String, LockEntry>,
+}
+
+/// Source frames of a single output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEntry {
+    command: String,
+
+    /// `sha256:` hash of the file content by path relative to the source folder.
+    frames: BTreeMap<String, String>,
+}
+
+impl LockEntry {
+    /// Hash the `files` of `source`.
+    ///
+    /// # Errors
+    ///
+    /// Fails when a file can't be read.
+    pub fn new(command: String, source: &Path, files: &[PathBuf]) -> std::io::Result<Self> {
+        let mut frames = BTreeMap::new();
+        for file in files {
+            let relative = file.strip_prefix(source).unwrap_or(file);
+            let name = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            let mut hash = String::from("sha256:");
+            for byte in sha2::Sha256::digest(std::fs::read(file)?) {
+                let _ = write!(hash, "{byte:02x}");
+            }
+
+            frames.insert(name, hash);
+        }
+
+        Ok(Self { command, frames })
+    }
+
+    /// Differences of `self` to the previously locked `old` entry, frames are relative to the source.
+    #[must_use]
+    pub fn diff(&self, old: &Self) -> Vec<LockChange> {
+        let mut changes = Vec::new();
+
+        for (frame, hash) in &self.frames {
+            match old.frames.get(frame) {
+                None => changes.push(LockChange::Added(frame.clone())),
+                Some(old_hash) if old_hash != hash => {
+                    changes.push(LockChange::Changed(frame.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+
+        changes.extend(
+            old.frames
+                .keys()
+                .filter(|frame| !self.frames.contains_key(*frame))
+                .map(|frame| LockChange::Removed(frame.clone())),
+        );
+
+        if self.command != old.command {
+            changes.push(LockChange::Command(old.command.clone()));
+        }
+
+        changes
+    }
+}
+
+/// Difference of an output to its locked state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockChange {
+    Added(String),
+    Changed(String),
+    Removed(String),
+
+    /// The flags differ, contains the locked command.
+    Command(String),
+}
+
+impl Lockfile {
+    /// Load a lockfile, a missing file is an empty lockfile.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the file can't be read or isn't a valid lockfile.
+    pub fn load(path: &Path) -> Result<Self, LockfileError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let invalid = |msg: String| LockfileError::Invalid(path.to_path_buf(), msg);
+        let table = std::fs::read_to_string(path)?
+            .parse::<toml::Table>()
+            .map_err(|err| invalid(err.to_string()))?;
+
+        let mut outputs = BTreeMap::new();
+        let Some(toml::Value::Table(locked)) = table.get("outputs") else {
+            return Ok(Self { outputs });
+        };
+
+        for (name, entry) in locked {
+            let command = entry
+                .get("command")
+                .and_then(toml::Value::as_str)
+                .ok_or_else(|| invalid(format!("output {name} has no command")))?;
+            let frames = entry
+                .get("frames")
+                .and_then(toml::Value::as_table)
+                .ok_or_else(|| invalid(format!("output {name} has no frames")))?
+                .iter()
+                .map(|(frame, hash)| {
+                    hash.as_str()
+                        .map(|hash| (frame.clone(), hash.to_owned()))
+                        .ok_or_else(|| invalid(format!("frame {frame} of {name} has no hash")))
+                })
+                .collect::<Result<_, _>>()?;
+
+            outputs.insert(
+                name.clone(),
+                LockEntry {
+                    command: command.to_owned(),
+                    frames,
+                },
+            );
+        }
+
+        Ok(Self { outputs })
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&LockEntry> {
+        self.outputs.get(name)
+    }
+
+    pub fn insert(&mut self, name: String, entry: LockEntry) {
+        self.outputs.insert(name, entry);
+    }
+
+    /// Write the lockfile, the outputs are sorted by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LockfileError::WriteError`] when the file can't be written.
+    pub fn save(&self, path: &Path) -> Result<(), LockfileError> {
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|(name, entry)| {
+                let frames = entry
+                    .frames
+                    .iter()
+                    .map(|(frame, hash)| (frame.clone(), toml::Value::from(hash.as_str())))
+                    .collect::<toml::Table>();
+
+                let mut table = toml::Table::new();
+                table.insert("command".to_owned(), entry.command.as_str().into());
+                table.insert("frames".to_owned(), frames.into());
+                (name.clone(), toml::Value::from(table))
+            })
+            .collect::<toml::Table>();
+
+        let mut table = toml::Table::new();
+        table.insert("outputs".to_owned(), outputs.into());
+
+        let content = toml::to_string_pretty(&table)
+            .map_err(|err| LockfileError::Invalid(path.to_path_buf(), err.to_string()))?;
+        std::fs::write(
+            path,
+            format!(
+                "# Generated by spritter v{}, hashes of the source frames per output\n\n{content}",
+                env!("CARGO_PKG_VERSION")
+            ),
+        )
+        .map_err(|err| LockfileError::WriteError(path.to_path_buf(), err))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{LockChange, LockEntry};
+
+    fn entry(command: &str, frames: &[(&str, &str)]) -> LockEntry {
+        LockEntry {
+            command: command.to_owned(),
+            frames: frames
+                .iter()
+                .map(|(frame, hash)| ((*frame).to_owned(), (*hash).to_owned()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn unchanged() {
+        let old = entry(
+            "spritesheet a out",
+            &[("0.png", "sha256:0"), ("1.png", "sha256:1")],
+        );
+
+        assert!(old.diff(&old.clone()).is_empty());
+    }
+
+    #[test]
+    fn frame_changes() {
+        let old = entry(
+            "spritesheet a out",
+            &[("0.png", "sha256:0"), ("1.png", "sha256:1")],
+        );
+        let new = entry(
+            "spritesheet a out",
+            &[("1.png", "sha256:x"), ("2.png", "sha256:2")],
+        );
+
+        assert_eq!(
+            new.diff(&old),
+            [
+                LockChange::Changed("1.png".to_owned()),
+                LockChange::Added("2.png".to_owned()),
+                LockChange::Removed("0.png".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn command_change() {
+        let old = entry("spritesheet a out", &[("0.png", "sha256:0")]);
+        let new = entry("spritesheet a out --lossy", &[("0.png", "sha256:0")]);
+
+        assert_eq!(
+            new.diff(&old),
+            [LockChange::Command("spritesheet a out".to_owned())]
+        );
+    }
+}
@@ -1,21 +1,83 @@
 // This is a modified version of pretty_env_logger v0.4.0 that uses Builder::from_env()
 
 use std::fmt;
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use clap::{builder::PossibleValue, ValueEnum};
 use env_logger::{
     fmt::{Color, Style, StyledValue},
-    Builder, Env,
+    Builder, Env, WriteStyle,
 };
+use indicatif_log_bridge::LogWrapper;
 use log::Level;
+use strum::{EnumIter, VariantArray};
 
-pub fn init(level: &str) {
+/// CI specific format for warnings and errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, VariantArray)]
+pub enum AnnotationFormat {
+    /// GitHub Actions workflow commands, shown inline on pull requests.
+    Github,
+}
+
+impl std::fmt::Display for AnnotationFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Github => write!(f, "github"),
+        }
+    }
+}
+
+impl ValueEnum for AnnotationFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Github => "github",
+        }))
+    }
+}
+
+pub fn init(level: &str, color: bool, annotations: Option<AnnotationFormat>) {
     let env = Env::default().filter_or("RUST_LOG", level);
 
-    Builder::from_env(env)
-        .format(|buf, record| {
+    build(Builder::from_env(env), color, annotations);
+}
+
+/// Same as [`init`] but `level` takes precedence over the defaults from `RUST_LOG`.
+pub fn init_with_level(level: &str, color: bool, annotations: Option<AnnotationFormat>) {
+    let env = Env::default().filter_or("RUST_LOG", level);
+
+    let mut builder = Builder::from_env(env);
+    builder.parse_filters(level);
+    build(builder, color, annotations);
+}
+
+/// Colors are disabled by `--no-color` or a non-empty `NO_COLOR` env var, see <https://no-color.org>.
+///
+/// Output that is not a terminal is never colored.
+#[must_use]
+pub fn color_enabled(no_color: bool) -> bool {
+    !no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+fn build(mut builder: Builder, color: bool, annotations: Option<AnnotationFormat>) {
+    if !color || annotations.is_some() {
+        builder.write_style(WriteStyle::Never);
+    }
+
+    let logger = builder
+        .format(move |buf, record| {
             use std::io::Write;
 
+            if annotations == Some(AnnotationFormat::Github) {
+                if let Some(line) = github_annotation(record.level(), &record.args().to_string()) {
+                    return writeln!(buf, "{line}");
+                }
+            }
+
             let target = record.target();
             let max_width = max_target_width(target);
 
@@ -48,7 +110,60 @@ pub fn init(level: &str) {
 
             Ok(())
         })
-        .init();
+        .build();
+
+    // print log lines above the progress bars instead of tearing them apart
+    let level = logger.filter();
+    if LogWrapper::new(crate::progress::multi().clone(), logger)
+        .try_init()
+        .is_ok()
+    {
+        log::set_max_level(level);
+    }
+}
+
+/// Warnings and errors as `::warning file=...::message` workflow command.
+///
+/// Messages starting with the path of an existing file (`path: message`) are attached to that file.
+fn github_annotation(level: Level, text: &str) -> Option<String> {
+    let command = match level {
+        Level::Error => "error",
+        Level::Warn => "warning",
+        _ => return None,
+    };
+
+    let file = text
+        .split_once(": ")
+        .map(|(file, _)| Path::new(file))
+        .filter(|file| file.exists())
+        .map(|file| {
+            // annotations need paths relative to the repository, CI runs from its root
+            let relative = std::env::current_dir()
+                .ok()
+                .and_then(|cwd| file.strip_prefix(cwd).ok())
+                .unwrap_or(file);
+            format!(
+                " file={}",
+                escape_property(&relative.to_string_lossy().replace('\\', "/"))
+            )
+        });
+
+    Some(format!(
+        "::{command}{}::{}",
+        file.unwrap_or_default(),
+        escape_data(text)
+    ))
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
 }
 
 struct Padded<T> {
@@ -74,7 +189,7 @@ fn max_target_width(target: &str) -> usize {
     }
 }
 
-fn colored_level(style: &mut Style, level: Level) -> StyledValue<&'static str> {
+fn colored_level(style: &mut Style, level: Level) -> StyledValue<'_, &'static str> {
     match level {
         Level::Trace => style.set_color(Color::Magenta).value("TRACE"),
         Level::Debug => style.set_color(Color::Blue).value("DEBUG"),
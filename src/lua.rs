@@ -1,5 +1,7 @@
 use std::{collections::BTreeMap, io::Write, path::Path};
 
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
 #[derive(Debug, Clone)]
 pub enum LuaValue {
     String(String),
@@ -7,7 +9,7 @@ pub enum LuaValue {
     Int(i64),
     Bool(bool),
     Shift(f64, f64, usize),
-    Array(Box<[LuaValue]>),
+    Array(Box<[Self]>),
     Table(LuaOutput),
 }
 
@@ -113,6 +115,35 @@ impl From<LuaOutput> for LuaValue {
     }
 }
 
+impl TryFrom<serde_json::Value> for LuaValue {
+    type Error = LuaParseError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        use serde_json::Value;
+
+        Ok(match value {
+            Value::Null => return Err(LuaParseError::JsonNull),
+            Value::Bool(value) => Self::Bool(value),
+            Value::Number(value) => value.as_i64().map_or_else(
+                || Self::Float(value.as_f64().unwrap_or_default()),
+                Self::Int,
+            ),
+            Value::String(value) => Self::String(value),
+            Value::Array(arr) => Self::Array(
+                arr.into_iter()
+                    .map(Self::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(obj) => Self::Table(LuaOutput {
+                map: obj
+                    .into_iter()
+                    .map(|(k, v)| Ok((k, Self::try_from(v)?)))
+                    .collect::<Result<_, LuaParseError>>()?,
+            }),
+        })
+    }
+}
+
 impl From<Box<[LuaOutput]>> for LuaValue {
     fn from(value: Box<[LuaOutput]>) -> Self {
         Self::Array(value.iter().map(|x| Self::Table(x.clone())).collect())
@@ -121,25 +152,130 @@ impl From<Box<[LuaOutput]>> for LuaValue {
 
 impl std::fmt::Display for LuaValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_lua(f, LuaStyle::default())
+    }
+}
+
+impl LuaValue {
+    /// Write the value as lua, nested tables are written inline.
+    fn write_lua(&self, f: &mut impl std::fmt::Write, style: LuaStyle) -> std::fmt::Result {
         match self {
-            Self::String(value) => write!(f, "\"{value}\""),
-            Self::Float(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "\"{}\"", escape(value)),
+            Self::Float(value) => match style.float_precision {
+                Some(precision) => write!(f, "{value:.precision$}"),
+                None => write!(f, "{value}"),
+            },
             Self::Int(value) => write!(f, "{value}"),
             Self::Bool(value) => write!(f, "{value}"),
-            Self::Shift(x, y, res) => write!(f, "{{x = {x} / {res}, y = {y} / {res}}}"),
+            Self::Shift(x, y, res) => {
+                let number = |value: f64| {
+                    style.float_precision.map_or_else(
+                        || value.to_string(),
+                        |precision| format!("{value:.precision$}"),
+                    )
+                };
+
+                match style.shift_format {
+                    ShiftFormat::Tiles => {
+                        write!(
+                            f,
+                            "{{x = {} / {res}, y = {} / {res}}}",
+                            number(*x),
+                            number(*y)
+                        )
+                    }
+                    ShiftFormat::Pixels => write!(f, "{{x = {}, y = {}}}", number(*x), number(*y)),
+                    ShiftFormat::ByPixel => {
+                        let factor = 32.0 / *res as f64;
+                        write!(
+                            f,
+                            "util.by_pixel({}, {})",
+                            number(x * factor),
+                            number(y * factor)
+                        )
+                    }
+                }
+            }
             Self::Array(arr) => {
                 write!(f, "{{")?;
-                for value in arr {
-                    write!(f, "{value},")?;
+                for (idx, value) in arr.iter().enumerate() {
+                    value.write_lua(f, style)?;
+                    write!(f, "{}", style.separator(idx, arr.len()))?;
                 }
                 write!(f, "}}")
             }
-            Self::Table(table) => write!(f, "{table}"),
+            Self::Table(table) => table.write_lua(f, style),
         }
     }
+
+    fn to_lua(&self, style: LuaStyle) -> String {
+        let mut res = String::new();
+        let _ = self.write_lua(&mut res, style);
+        res
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Formatting options for generated lua files.
+#[derive(Debug, Clone, Copy)]
+pub struct LuaStyle {
+    /// Amount of spaces to indent the top level entries with.
+    pub indent: usize,
+
+    /// Write the `-- Generated by ...` comment at the start of the file.
+    pub header: bool,
+
+    /// Write a comma after the last entry of a table.
+    pub trailing_comma: bool,
+
+    /// Use `local data = {...} return data` instead of directly returning the table.
+    pub local: bool,
+
+    /// Write floats with a fixed amount of decimals instead of the shortest representation.
+    pub float_precision: Option<usize>,
+
+    /// How shift values are written.
+    pub shift_format: ShiftFormat,
+}
+
+/// Lua representation of shift values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::EnumIter, strum::VariantArray)]
+pub enum ShiftFormat {
+    /// `{x = 12 / 64, y = -4 / 64}`, pixels divided by the tile resolution.
+    Tiles,
+
+    /// `{x = 12, y = -4}`, pixels at the tile resolution of the sprites.
+    Pixels,
+
+    /// `util.by_pixel(6, -2)`, pixels at 32px per tile.
+    ByPixel,
+}
+
+impl Default for LuaStyle {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            header: true,
+            trailing_comma: true,
+            local: false,
+            float_precision: None,
+            shift_format: ShiftFormat::Tiles,
+        }
+    }
+}
+
+impl LuaStyle {
+    /// Separator after the entry at `idx` of a table with `len` entries.
+    const fn separator(self, idx: usize, len: usize) -> &'static str {
+        if self.trailing_comma || idx + 1 < len {
+            ","
+        } else {
+            ""
+        }
+    }
+}
+
+#[must_use]
+#[derive(Debug, Clone, Default)]
 pub struct LuaOutput {
     map: BTreeMap<String, LuaValue>,
 }
@@ -156,9 +292,184 @@ impl LuaOutput {
         self
     }
 
-    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
-        let mut file = std::fs::File::create(path)?;
+    pub fn remove(&mut self, key: &str) -> Option<LuaValue> {
+        self.map.remove(key)
+    }
+
+    #[must_use]
+    pub fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    /// Set the value only if it is present.
+    pub fn set_opt(self, key: impl AsRef<str>, value: Option<impl Into<LuaValue>>) -> Self {
+        match value {
+            Some(value) => self.set(key, value),
+            None => self,
+        }
+    }
+
+    /// Save the data as a lua file returning the table.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the file can't be written.
+    pub fn save(&self, path: impl AsRef<Path>, style: LuaStyle) -> std::io::Result<()> {
+        self.write(&mut std::fs::File::create(path)?, style)
+    }
+
+    /// Write the data as a lua file returning the table.
+    ///
+    /// # Errors
+    ///
+    /// Fails when writing to `file` fails.
+    pub fn write(&self, file: &mut impl Write, style: LuaStyle) -> std::io::Result<()> {
+        write_header(file, style)?;
+
+        if style.local {
+            writeln!(file, "local data = {{")?;
+        } else {
+            writeln!(file, "return {{")?;
+        }
+
+        self.write_entries(file, style)?;
+        writeln!(file, "}}")?;
+
+        if style.local {
+            writeln!(file, "return data")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the data as a lua module with `get_animation(overrides)` and `get_icon(overrides)` helpers.
+    ///
+    /// `files` are the names of the generated images the helpers refer to.
+    ///
+    /// # Errors
+    ///
+    /// Fails when writing to `file` fails.
+    pub fn write_module(
+        &self,
+        file: &mut impl Write,
+        style: LuaStyle,
+        files: &[String],
+    ) -> std::io::Result<()> {
+        let indent = " ".repeat(style.indent);
+
+        write_header(file, style)?;
+
+        writeln!(file, "local data = {{")?;
+        self.write_entries(file, style)?;
+        writeln!(file, "}}")?;
+        writeln!(file)?;
+
+        writeln!(file, "local files = {{")?;
+        for (idx, name) in files.iter().enumerate() {
+            writeln!(
+                file,
+                "{indent}\"{}\"{}",
+                escape(name),
+                style.separator(idx, files.len())
+            )?;
+        }
+        writeln!(file, "}}")?;
+        writeln!(file)?;
+
+        file.write_all(LUA_MODULE.as_bytes())
+    }
+
+    fn write_entries(&self, file: &mut impl Write, style: LuaStyle) -> std::io::Result<()> {
+        let indent = " ".repeat(style.indent);
+        let len = self.map.len() + 1;
 
+        writeln!(
+            file,
+            "{indent}[\"spritter\"] = {{ {}, {}, {} }}{}",
+            env!("CARGO_PKG_VERSION_MAJOR"),
+            env!("CARGO_PKG_VERSION_MINOR"),
+            env!("CARGO_PKG_VERSION_PATCH"),
+            style.separator(0, len)
+        )?;
+
+        for (idx, (key, data)) in self.map.iter().enumerate() {
+            writeln!(
+                file,
+                "{indent}[\"{}\"] = {}{}",
+                escape(key),
+                data.to_lua(style),
+                style.separator(idx + 1, len)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the data as JSON, using the same keys and ordering as the lua output.
+    ///
+    /// Floats are rounded to `float_precision` decimals when set.
+    ///
+    /// # Errors
+    ///
+    /// Fails when writing to `file` fails.
+    pub fn write_json(
+        &self,
+        file: &mut impl Write,
+        float_precision: Option<usize>,
+    ) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(&mut *file, &DataFile(self, float_precision))?;
+        writeln!(file)
+    }
+}
+
+impl LuaOutput {
+    /// Convert the data into a JSON value, the same way it is written by [`LuaOutput::write_json`].
+    ///
+    /// # Errors
+    ///
+    /// Fails when a value can't be represented in JSON.
+    pub fn to_json_value(
+        &self,
+        float_precision: Option<usize>,
+    ) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(DataFile(self, float_precision))
+    }
+
+    /// Write the data as TOML, using the same keys as the lua output.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the data can't be represented in TOML or writing to `file` fails.
+    pub fn write_toml(
+        &self,
+        file: &mut impl Write,
+        float_precision: Option<usize>,
+    ) -> std::io::Result<()> {
+        let data = toml::to_string_pretty(&DataFile(self, float_precision))
+            .map_err(std::io::Error::other)?;
+        file.write_all(data.as_bytes())
+    }
+
+    /// Write the data as YAML, using the same keys and ordering as the lua output.
+    ///
+    /// # Errors
+    ///
+    /// Fails when writing to `file` fails.
+    pub fn write_yaml(
+        &self,
+        file: &mut impl Write,
+        float_precision: Option<usize>,
+    ) -> std::io::Result<()> {
+        serde_yaml_ng::to_writer(file, &DataFile(self, float_precision))
+            .map_err(std::io::Error::other)
+    }
+}
+
+/// Helper functions of the generated lua modules, expects `data` and `files` locals.
+const LUA_MODULE: &str = include_str!("lua_module.lua");
+
+fn write_header(file: &mut impl Write, style: LuaStyle) -> std::io::Result<()> {
+    if style.header {
         writeln!(
             file,
             "-- Generated by {} v{} - {}",
@@ -166,35 +477,681 @@ impl LuaOutput {
             env!("CARGO_PKG_VERSION"),
             env!("CARGO_PKG_REPOSITORY")
         )?;
-        writeln!(file, "return {{")?;
-        writeln!(
-            file,
-            "  [\"spritter\"] = {{ {}, {}, {} }},",
-            env!("CARGO_PKG_VERSION_MAJOR"),
-            env!("CARGO_PKG_VERSION_MINOR"),
-            env!("CARGO_PKG_VERSION_PATCH")
-        )?;
+    }
+
+    Ok(())
+}
 
-        for (key, data) in &self.map {
-            writeln!(file, "  [\"{key}\"] = {data},")?;
+/// Escape a string so it can be used inside a double quoted lua string.
+fn escape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '"' => res.push_str("\\\""),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if c.is_ascii_control() => {
+                let code = c as u32;
+                res.push('\\');
+                for digit in [code / 100, code / 10 % 10, code % 10] {
+                    res.push(char::from_digit(digit, 10).unwrap_or('0'));
+                }
+            }
+            c => res.push(c),
         }
+    }
 
-        writeln!(file, "}}")?;
+    res
+}
 
-        Ok(())
+/// Round `value` to `precision` decimals.
+fn round_to(value: f64, precision: Option<usize>) -> f64 {
+    precision.map_or(value, |precision| {
+        let factor = 10f64.powi(precision as i32);
+        (value * factor).round() / factor
+    })
+}
+
+/// Serializes a value with floats rounded to an optional amount of decimals.
+struct Rounded<'a>(&'a LuaValue, Option<usize>);
+
+impl Serialize for Rounded<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Self(value, precision) = *self;
+
+        match value {
+            LuaValue::String(value) => serializer.serialize_str(value),
+            LuaValue::Float(value) => serializer.serialize_f64(round_to(*value, precision)),
+            LuaValue::Int(value) => serializer.serialize_i64(*value),
+            LuaValue::Bool(value) => serializer.serialize_bool(*value),
+            LuaValue::Shift(x, y, res) => {
+                let res = *res as f64;
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("x", &round_to(x / res, precision))?;
+                map.serialize_entry("y", &round_to(y / res, precision))?;
+                map.end()
+            }
+            LuaValue::Array(arr) => {
+                serializer.collect_seq(arr.iter().map(|value| Self(value, precision)))
+            }
+            LuaValue::Table(table) => serializer.collect_map(
+                table
+                    .map
+                    .iter()
+                    .map(|(key, value)| (key, Self(value, precision))),
+            ),
+        }
+    }
+}
+
+impl Serialize for LuaValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Rounded(self, None).serialize(serializer)
+    }
+}
+
+impl Serialize for LuaOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.map.serialize(serializer)
+    }
+}
+
+/// Top level of a saved JSON / TOML / YAML file, includes the generator version like the lua header.
+struct DataFile<'a>(&'a LuaOutput, Option<usize>);
+
+impl Serialize for DataFile<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let version: [u32; 3] = [
+            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or_default(),
+            env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or_default(),
+            env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or_default(),
+        ];
+
+        let mut map = serializer.serialize_map(Some(self.0.map.len() + 1))?;
+        map.serialize_entry("spritter", &version)?;
+        for (key, data) in &self.0.map {
+            map.serialize_entry(key, &Rounded(data, self.1))?;
+        }
+        map.end()
     }
 }
 
 impl std::fmt::Display for LuaOutput {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_lua(f, LuaStyle::default())
+    }
+}
+
+impl LuaOutput {
+    fn write_lua(&self, f: &mut impl std::fmt::Write, style: LuaStyle) -> std::fmt::Result {
         write!(f, "{{")?;
 
-        for (key, data) in &self.map {
-            write!(f, "[\"{key}\"] = {data},")?;
+        for (idx, (key, data)) in self.map.iter().enumerate() {
+            write!(f, "[\"{}\"] = ", escape(key))?;
+            data.write_lua(f, style)?;
+            write!(f, "{}", style.separator(idx, self.map.len()))?;
         }
 
-        write!(f, "}}")?;
+        write!(f, "}}")
+    }
+}
 
-        Ok(())
+#[derive(Debug, thiserror::Error)]
+pub enum LuaParseError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("unexpected end of lua data")]
+    UnexpectedEnd,
+
+    #[error("unexpected character '{0}' at position {1}")]
+    UnexpectedChar(char, usize),
+
+    #[error("invalid number '{0}'")]
+    InvalidNumber(String),
+
+    #[error("lua data does not return a table")]
+    NoTable,
+
+    #[error("json error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("json data contains null, which has no lua equivalent")]
+    JsonNull,
+}
+
+impl LuaValue {
+    #[must_use]
+    pub const fn as_int(&self) -> Option<i64> {
+        match self {
+            Self::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Float(value) => Some(*value),
+            Self::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl LuaOutput {
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        self.map.get(key)
+    }
+
+    /// Load a lua file previously generated by [`LuaOutput::save`].
+    ///
+    /// # Errors
+    ///
+    /// Fails when the file can't be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LuaParseError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load a JSON file previously generated by [`LuaOutput::write_json`].
+    ///
+    /// # Errors
+    ///
+    /// Fails when the file can't be read or parsed.
+    pub fn load_json(path: impl AsRef<Path>) -> Result<Self, LuaParseError> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let value = serde_json::from_reader::<_, serde_json::Value>(file)?;
+
+        match LuaValue::try_from(value)? {
+            LuaValue::Table(table) => Ok(table),
+            _ => Err(LuaParseError::NoTable),
+        }
+    }
+
+    /// Parse the returned table of a generated lua file.
+    ///
+    /// Only the subset of lua that is written by [`LuaOutput::save`] is supported:
+    /// comments, a single `return` of a table with strings, numbers, booleans and nested tables.
+    ///
+    /// # Errors
+    ///
+    /// Fails on syntax outside of the supported subset.
+    pub fn parse(data: &str) -> Result<Self, LuaParseError> {
+        let mut parser = LuaParser {
+            chars: data.char_indices().peekable(),
+        };
+
+        parser.skip_whitespace();
+
+        // `local data = {...}` form, the rest of the file (return / module helpers) is ignored
+        let value = if parser.consume_word("local") {
+            parser.skip_whitespace();
+            if parser.name().is_empty() {
+                return Err(LuaParseError::NoTable);
+            }
+            parser.expect('=')?;
+            parser.value()?
+        } else if parser.consume_word("return") {
+            parser.value()?
+        } else {
+            return Err(LuaParseError::NoTable);
+        };
+
+        match value {
+            LuaValue::Table(table) => Ok(table),
+            LuaValue::Array(arr) if arr.is_empty() => Ok(Self::new()),
+            _ => Err(LuaParseError::NoTable),
+        }
+    }
+}
+
+struct LuaParser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl LuaParser<'_> {
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.chars.peek() {
+                Some((_, c)) if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                Some((_, '-')) => {
+                    // comments run until the end of the line
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if !matches!(lookahead.peek(), Some((_, '-'))) {
+                        return;
+                    }
+
+                    for (_, c) in self.chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some((_, c)) = self.chars.peek() {
+            if c.is_alphanumeric() || *c == '_' {
+                name.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        name
+    }
+
+    fn consume_word(&mut self, word: &str) -> bool {
+        let mut lookahead = self.chars.clone();
+
+        for expected in word.chars() {
+            match lookahead.next() {
+                Some((_, c)) if c == expected => {}
+                _ => return false,
+            }
+        }
+
+        if matches!(lookahead.peek(), Some((_, c)) if c.is_alphanumeric() || *c == '_') {
+            return false;
+        }
+
+        self.chars = lookahead;
+        true
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), LuaParseError> {
+        self.skip_whitespace();
+
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((pos, c)) => Err(LuaParseError::UnexpectedChar(c, pos)),
+            None => Err(LuaParseError::UnexpectedEnd),
+        }
+    }
+
+    fn peek(&mut self) -> Result<(usize, char), LuaParseError> {
+        self.skip_whitespace();
+        self.chars
+            .peek()
+            .copied()
+            .ok_or(LuaParseError::UnexpectedEnd)
+    }
+
+    fn value(&mut self) -> Result<LuaValue, LuaParseError> {
+        let (pos, c) = self.peek()?;
+
+        match c {
+            '{' => self.table(),
+            '"' | '\'' => Ok(LuaValue::String(self.string()?)),
+            '-' | '.' | '0'..='9' => self.number(),
+            _ if self.consume_word("true") => Ok(LuaValue::Bool(true)),
+            _ if self.consume_word("false") => Ok(LuaValue::Bool(false)),
+            _ if self.consume_word("util") => self.by_pixel(),
+            _ => Err(LuaParseError::UnexpectedChar(c, pos)),
+        }
+    }
+
+    /// Shift written as `util.by_pixel(x, y)`, the `util` was already consumed.
+    fn by_pixel(&mut self) -> Result<LuaValue, LuaParseError> {
+        self.expect('.')?;
+        let (pos, c) = self.peek()?;
+        if !self.consume_word("by_pixel") {
+            return Err(LuaParseError::UnexpectedChar(c, pos));
+        }
+
+        self.expect('(')?;
+        self.skip_whitespace();
+        let x = self.number()?;
+        self.expect(',')?;
+        self.skip_whitespace();
+        let y = self.number()?;
+        self.expect(')')?;
+
+        let (Some(x), Some(y)) = (x.as_float(), y.as_float()) else {
+            return Err(LuaParseError::UnexpectedChar(c, pos));
+        };
+
+        Ok(LuaValue::Table(
+            LuaOutput::new().set("x", x / 32.0).set("y", y / 32.0),
+        ))
+    }
+
+    fn string(&mut self) -> Result<String, LuaParseError> {
+        let Some((_, quote)) = self.chars.next() else {
+            return Err(LuaParseError::UnexpectedEnd);
+        };
+
+        let mut res = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, c)) if c == quote => return Ok(res),
+                Some((pos, '\\')) => match self.chars.next() {
+                    Some((_, 'n')) => res.push('\n'),
+                    Some((_, 'r')) => res.push('\r'),
+                    Some((_, 't')) => res.push('\t'),
+                    Some((_, c @ ('\\' | '"' | '\''))) => res.push(c),
+                    Some((_, c)) if c.is_ascii_digit() => {
+                        // decimal escape with up to 3 digits
+                        let mut code = c.to_digit(10).unwrap_or_default();
+                        for _ in 0..2 {
+                            match self.chars.peek() {
+                                Some((_, c)) if c.is_ascii_digit() => {
+                                    code = code * 10 + c.to_digit(10).unwrap_or_default();
+                                    self.chars.next();
+                                }
+                                _ => break,
+                            }
+                        }
+
+                        res.push(
+                            char::from_u32(code).ok_or(LuaParseError::UnexpectedChar(c, pos))?,
+                        );
+                    }
+                    Some((_, c)) => return Err(LuaParseError::UnexpectedChar(c, pos)),
+                    None => return Err(LuaParseError::UnexpectedEnd),
+                },
+                Some((_, c)) => res.push(c),
+                None => return Err(LuaParseError::UnexpectedEnd),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<LuaValue, LuaParseError> {
+        let mut raw = String::new();
+
+        while let Some((_, c)) = self.chars.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.') {
+                raw.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        let value = if let Ok(value) = raw.parse::<i64>() {
+            LuaValue::Int(value)
+        } else {
+            LuaValue::Float(
+                raw.parse::<f64>()
+                    .map_err(|_| LuaParseError::InvalidNumber(raw.clone()))?,
+            )
+        };
+
+        // simple divisions are used for shift values
+        if matches!(self.peek(), Ok((_, '/'))) {
+            self.chars.next();
+            self.skip_whitespace();
+
+            let (Some(lhs), Some(rhs)) = (value.as_float(), self.number()?.as_float()) else {
+                return Err(LuaParseError::InvalidNumber(raw));
+            };
+
+            return Ok(LuaValue::Float(lhs / rhs));
+        }
+
+        Ok(value)
+    }
+
+    fn table(&mut self) -> Result<LuaValue, LuaParseError> {
+        self.expect('{')?;
+
+        let mut table = LuaOutput::new();
+        let mut array = Vec::new();
+
+        loop {
+            let (pos, c) = self.peek()?;
+
+            match c {
+                '}' => {
+                    self.chars.next();
+                    break;
+                }
+                '[' => {
+                    self.chars.next();
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(']')?;
+                    self.expect('=')?;
+                    table = table.set(key, self.value()?);
+                }
+                _ if c.is_alphabetic() || c == '_' => {
+                    let mut lookahead = self.chars.clone();
+                    let mut key = String::new();
+                    while let Some((_, c)) = lookahead.peek() {
+                        if c.is_alphanumeric() || *c == '_' {
+                            key.push(*c);
+                            lookahead.next();
+                        } else {
+                            break;
+                        }
+                    }
+
+                    if key == "true" || key == "false" {
+                        array.push(self.value()?);
+                    } else {
+                        self.chars = lookahead;
+                        self.expect('=')?;
+                        table = table.set(key, self.value()?);
+                    }
+                }
+                ',' | ';' => return Err(LuaParseError::UnexpectedChar(c, pos)),
+                _ => array.push(self.value()?),
+            }
+
+            match self.peek()? {
+                (_, ',' | ';') => {
+                    self.chars.next();
+                }
+                (_, '}') => {}
+                (pos, c) => return Err(LuaParseError::UnexpectedChar(c, pos)),
+            }
+        }
+
+        if table.map.is_empty() {
+            Ok(LuaValue::Array(array.into_boxed_slice()))
+        } else {
+            Ok(LuaValue::Table(table))
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{LuaOutput, LuaParseError, LuaStyle, LuaValue, ShiftFormat};
+
+    fn written(data: &LuaOutput, style: LuaStyle) -> String {
+        let mut out = Vec::new();
+        data.write(&mut out, style).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn shift(data: &LuaOutput) -> (f64, f64) {
+        let Some(LuaValue::Table(shift)) = data.get("shift") else {
+            panic!("shift is not a table");
+        };
+        let coord = |key| shift.get(key).and_then(LuaValue::as_float).unwrap();
+        (coord("x"), coord("y"))
+    }
+
+    #[test]
+    fn parse_written_output() {
+        let layer = LuaOutput::new().set("name", "shadow").set("scale", 0.5);
+        let data = LuaOutput::new()
+            .set("name", "quote \" back\\slash\nnew line\u{1}")
+            .set("width", -64)
+            .set("scale", 0.25)
+            .set("draw_as_shadow", true)
+            .set("layers", vec![layer].into_boxed_slice());
+
+        let parsed = LuaOutput::parse(&written(&data, LuaStyle::default())).unwrap();
+
+        assert_eq!(
+            parsed.get("name").and_then(LuaValue::as_str),
+            Some("quote \" back\\slash\nnew line\u{1}")
+        );
+        assert_eq!(parsed.get("width").and_then(LuaValue::as_int), Some(-64));
+        assert_eq!(parsed.get("scale").and_then(LuaValue::as_float), Some(0.25));
+        assert!(matches!(
+            parsed.get("draw_as_shadow"),
+            Some(LuaValue::Bool(true))
+        ));
+
+        let layers = parsed.get("layers").and_then(LuaValue::as_array).unwrap();
+        let LuaValue::Table(layer) = &layers[0] else {
+            panic!("layer is not a table");
+        };
+        assert_eq!(layer.get("name").and_then(LuaValue::as_str), Some("shadow"));
+    }
+
+    #[test]
+    fn parse_shift_formats() {
+        let data = LuaOutput::new().set("shift", (12.0, -4.0, 64));
+
+        for shift_format in [
+            ShiftFormat::Tiles,
+            ShiftFormat::Pixels,
+            ShiftFormat::ByPixel,
+        ] {
+            let style = LuaStyle {
+                shift_format,
+                ..LuaStyle::default()
+            };
+            let parsed = LuaOutput::parse(&written(&data, style)).unwrap();
+
+            let expected = match shift_format {
+                ShiftFormat::Pixels => (12.0, -4.0),
+                _ => (0.1875, -0.0625),
+            };
+            assert_eq!(shift(&parsed), expected, "{shift_format:?}");
+        }
+    }
+
+    #[test]
+    fn parse_style_variants() {
+        let data = LuaOutput::new().set(
+            "frames",
+            LuaValue::Array(Box::new([LuaValue::Int(1), LuaValue::Int(2)])),
+        );
+        let style = LuaStyle {
+            indent: 0,
+            header: false,
+            trailing_comma: false,
+            local: true,
+            ..LuaStyle::default()
+        };
+
+        let parsed = LuaOutput::parse(&written(&data, style)).unwrap();
+        assert_eq!(
+            parsed
+                .get("frames")
+                .and_then(LuaValue::as_array)
+                .map(<[_]>::len),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn parse_hand_written() {
+        let parsed = LuaOutput::parse(
+            "-- comment\nreturn { width = 64, ['name'] = 'single', frames = { 1, 2, 3; }, }",
+        )
+        .unwrap();
+
+        assert_eq!(parsed.get("width").and_then(LuaValue::as_int), Some(64));
+        assert_eq!(
+            parsed.get("name").and_then(LuaValue::as_str),
+            Some("single")
+        );
+        assert!(LuaOutput::parse("return {}").unwrap().keys().is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            LuaOutput::parse("data = {}"),
+            Err(LuaParseError::NoTable)
+        ));
+        assert!(matches!(
+            LuaOutput::parse("return 5"),
+            Err(LuaParseError::NoTable)
+        ));
+        assert!(matches!(
+            LuaOutput::parse("return { width = 64"),
+            Err(LuaParseError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            LuaOutput::parse("return { width = nil }"),
+            Err(LuaParseError::UnexpectedChar('n', 17))
+        ));
+        assert!(matches!(
+            LuaOutput::parse("return { width = 6x4 }"),
+            Err(LuaParseError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn json_null() {
+        let value = serde_json::json!({ "width": 64, "name": null });
+
+        assert!(matches!(
+            LuaValue::try_from(value),
+            Err(LuaParseError::JsonNull)
+        ));
+    }
+
+    #[test]
+    fn module_output_loads() {
+        let data = LuaOutput::new()
+            .set("width", 64)
+            .set("height", 32)
+            .set("shift", (12.0, -4.0, 64))
+            .set("sprite_count", 8)
+            .set("line_length", 4);
+
+        let mut out = Vec::new();
+        data.write_module(&mut out, LuaStyle::default(), &["sheet.png".to_owned()])
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("local files = {\n  \"sheet.png\",\n}"));
+        assert!(out.contains("function M.get_animation(overrides)"));
+        assert!(out.trim_end().ends_with("return M"));
+
+        let loaded = LuaOutput::parse(&out).unwrap();
+        assert_eq!(loaded.get("width").unwrap().as_int(), Some(64));
+        assert_eq!(loaded.get("line_length").unwrap().as_int(), Some(4));
+        assert_eq!(
+            loaded.get("shift").unwrap().to_string(),
+            "{[\"x\"] = 0.1875,[\"y\"] = -0.0625,}"
+        );
     }
 }
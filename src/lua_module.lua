@@ -0,0 +1,97 @@
+local M = { data = data, files = files }
+
+-- copy all overrides except the path prefix onto the generated values
+local function merge(base, overrides)
+  for key, value in pairs(overrides or {}) do
+    if key ~= "path" then
+      base[key] = value
+    end
+  end
+
+  return base
+end
+
+local function file_path(overrides, file)
+  return ((overrides and overrides.path) or "") .. file
+end
+
+local function sheet(values, sheet_files, overrides)
+  local res = {
+    width = values.width,
+    height = values.height,
+    shift = values.shift,
+    scale = values.scale,
+    frame_count = values.frame_count or values.sprite_count,
+    direction_count = values.direction_count,
+    line_length = values.line_length,
+    frame_sequence = values.frame_sequence,
+  }
+
+  if values.stripes then
+    -- rotated animations with one stripe per direction
+    res.stripes = {}
+    for i, stripe in ipairs(values.stripes) do
+      res.stripes[i] = {
+        filename = file_path(overrides, stripe.filename),
+        width_in_frames = stripe.width_in_frames,
+        height_in_frames = stripe.height_in_frames,
+        y = stripe.y,
+      }
+    end
+    res.line_length = nil
+  elseif #sheet_files == 1 then
+    res.filename = file_path(overrides, sheet_files[1])
+  else
+    res.filenames = {}
+    for i, file in ipairs(sheet_files) do
+      res.filenames[i] = file_path(overrides, file)
+    end
+    res.lines_per_file = values.lines_per_file
+  end
+
+  return res
+end
+
+--- Animation definition of the generated sheet(s).
+--- `overrides.path` is prepended to the file names (e.g. "__my-mod__/graphics/"),
+--- all other overrides replace the generated values.
+function M.get_animation(overrides)
+  if data.single_sheet_split_layers then
+    local layers = {}
+    for i, layer in ipairs(data.single_sheet_split_layers) do
+      layers[i] = merge(sheet(layer, { files[i] }, overrides), overrides)
+    end
+
+    return { layers = layers }
+  end
+
+  return merge(sheet(data, files, overrides), overrides)
+end
+
+--- Animation4Way definition of a rotated animation with 4 directions, see `get_animation` for the overrides.
+function M.get_animation_4way(overrides)
+  if data.direction_count ~= 4 or not data.stripes then
+    error("get_animation_4way requires a rotated animation with 4 directions")
+  end
+
+  local res = {}
+  for i, direction in ipairs({ "north", "east", "south", "west" }) do
+    local animation = sheet(data, files, overrides)
+    animation.direction_count = nil
+    animation.stripes = { animation.stripes[i] }
+    res[direction] = merge(animation, overrides)
+  end
+
+  return res
+end
+
+--- Icon definition of the generated icon, see `get_animation` for the overrides.
+function M.get_icon(overrides)
+  return merge({
+    icon = file_path(overrides, files[1]),
+    icon_size = data.icon_size,
+    icon_mipmaps = data.icon_mipmaps,
+  }, overrides)
+end
+
+return M
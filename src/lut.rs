@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use image::RgbaImage;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LutError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("invalid line {0}: {1}")]
+    InvalidLine(usize, String),
+
+    #[error("missing LUT_3D_SIZE, only 3D LUTs are supported")]
+    MissingSize,
+
+    #[error("LUT_3D_SIZE of {0} is out of range, supported are 2 to {max}", max = MAX_LUT_SIZE)]
+    SizeOutOfRange(usize),
+
+    #[error("expected {0} entries, found {1}")]
+    WrongEntryCount(usize, usize),
+}
+
+/// Largest `LUT_3D_SIZE` allowed by the `.cube` specification.
+const MAX_LUT_SIZE: usize = 256;
+
+/// 3D color lookup table loaded from an Adobe / Resolve `.cube` file.
+#[derive(Clone)]
+pub struct Lut {
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+
+    /// Output colors with red changing fastest, then green, then blue.
+    table: Vec<[f32; 3]>,
+}
+
+impl std::fmt::Debug for Lut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lut")
+            .field("size", &self.size)
+            .field("domain_min", &self.domain_min)
+            .field("domain_max", &self.domain_max)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Lut {
+    /// Load a `.cube` 3D LUT file.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the file can't be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LutError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse the contents of a `.cube` 3D LUT file.
+    ///
+    /// # Errors
+    ///
+    /// Fails on invalid lines, a missing or out of range `LUT_3D_SIZE` or a wrong number of entries.
+    pub fn parse(data: &str) -> Result<Self, LutError> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (idx, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || LutError::InvalidLine(idx + 1, line.to_owned());
+            let parts = line.split_whitespace().collect::<Vec<_>>();
+            let triple = |values: &[&str]| -> Result<[f32; 3], LutError> {
+                match values {
+                    [r, g, b] => Ok([
+                        r.parse().map_err(|_| invalid())?,
+                        g.parse().map_err(|_| invalid())?,
+                        b.parse().map_err(|_| invalid())?,
+                    ]),
+                    _ => Err(invalid()),
+                }
+            };
+
+            match parts[0] {
+                "TITLE" | "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {}
+                "LUT_1D_SIZE" => return Err(LutError::MissingSize),
+                "LUT_3D_SIZE" => {
+                    let value = parts
+                        .get(1)
+                        .and_then(|size| size.parse::<usize>().ok())
+                        .ok_or_else(invalid)?;
+                    if !(2..=MAX_LUT_SIZE).contains(&value) {
+                        return Err(LutError::SizeOutOfRange(value));
+                    }
+                    size = Some(value);
+                }
+                "DOMAIN_MIN" => domain_min = triple(&parts[1..])?,
+                "DOMAIN_MAX" => domain_max = triple(&parts[1..])?,
+                _ => table.push(triple(&parts)?),
+            }
+        }
+
+        let size = size.ok_or(LutError::MissingSize)?;
+        let entries = size.checked_pow(3).ok_or(LutError::SizeOutOfRange(size))?;
+        if table.len() != entries {
+            return Err(LutError::WrongEntryCount(entries, table.len()));
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Map the color of every pixel through the table with trilinear interpolation, alpha is kept.
+    pub fn apply(&self, image: &mut RgbaImage) {
+        let max = (self.size - 1) as f32;
+
+        for pxl in image.pixels_mut() {
+            let mut base = [0; 3];
+            let mut fract = [0.0; 3];
+            for channel in 0..3 {
+                let range = self.domain_max[channel] - self.domain_min[channel];
+                let value = (f32::from(pxl[channel]) / 255.0 - self.domain_min[channel]) / range;
+                let pos = (value * max).clamp(0.0, max);
+
+                // the upper neighbor is clamped to the table as well
+                base[channel] = (pos.floor() as usize).min(self.size - 2);
+                fract[channel] = pos - base[channel] as f32;
+            }
+
+            let mut color = [0.0; 3];
+            for corner in 0..8 {
+                let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+                let weight = (0..3)
+                    .map(|channel| {
+                        if offset[channel] == 1 {
+                            fract[channel]
+                        } else {
+                            1.0 - fract[channel]
+                        }
+                    })
+                    .product::<f32>();
+
+                let entry = self.entry(
+                    base[0] + offset[0],
+                    base[1] + offset[1],
+                    base[2] + offset[2],
+                );
+                for (value, entry) in color.iter_mut().zip(entry) {
+                    *value += entry * weight;
+                }
+            }
+
+            for (channel, value) in color.into_iter().enumerate() {
+                pxl[channel] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[r + g * self.size + b * self.size * self.size]
+    }
+}
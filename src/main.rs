@@ -1,40 +1,247 @@
-use std::process::ExitCode;
+use std::{ffi::OsString, num::NonZeroUsize, process::ExitCode};
 
-use clap::Parser;
+use clap::{ArgAction, CommandFactory as _, FromArgMatches as _, Parser};
 
 #[macro_use]
 extern crate log;
 
-mod commands;
-mod image_util;
-mod logger;
-mod lua;
-
-use commands::{generate_gif, generate_mipmap_icon, optimize, GenerationCommand};
+use spritter::{
+    check,
+    commands::{
+        compare, diff_anim, effective_args, generate_gif, generate_mipmap_icon, generate_shadow,
+        info, init, mangen, montage, optimize, palette, preview, rotate, selected_jobs, split,
+        stats, stitch, validate, verify, CommandError, GenerationCommand, Preset, RunArgs,
+        RunError,
+    },
+    config, image_util, logger, summary,
+};
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Debug)]
 #[command(version, about, long_about=None)]
 struct Cli {
     #[clap(subcommand)]
     command: GenerationCommand,
+
+    /// Maximum number of threads to use for loading and encoding images [default: all cores]
+    #[clap(long, global = true)]
+    threads: Option<NonZeroUsize>,
+
+    /// Maximum number of threads oxipng uses for each image [default: shared with --threads]
+    #[clap(long, global = true, value_name = "THREADS")]
+    oxipng_threads: Option<NonZeroUsize>,
+
+    /// Optimize every image on a single thread, same as `--oxipng-threads 1`
+    #[clap(long, global = true, action, conflicts_with = "oxipng_threads")]
+    single_threaded_encode: bool,
+
+    /// Treat all source images as sRGB, skip converting pngs with a different gamma
+    #[clap(long, global = true, action)]
+    assume_srgb: bool,
+
+    /// Only print warnings and errors
+    #[clap(short, long, global = true, action, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print debug output, use twice for trace output
+    #[clap(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Disable colored output, also possible with the `NO_COLOR` env var
+    #[clap(long, global = true, action)]
+    no_color: bool,
+
+    /// Print a JSON summary of the written files, sprites and failures to stdout when done
+    #[clap(long, global = true, action)]
+    porcelain: bool,
+
+    /// Print warnings and errors as CI annotations, e.g. to show them inline on pull requests
+    #[clap(long, global = true, value_name = "FORMAT")]
+    annotations: Option<logger::AnnotationFormat>,
+}
+
+impl Cli {
+    /// Log filter for the quiet / verbose flags, `None` if neither is set.
+    const fn log_level(&self) -> Option<&'static str> {
+        if self.quiet {
+            return Some("warn");
+        }
+
+        match self.verbose {
+            0 => None,
+            1 => Some("debug,oxipng=warn"),
+            _ => Some("trace"),
+        }
+    }
+}
+
+/// Parse the command line arguments with the defaults of the config files and presets.
+fn parse_cli(
+    command_line: &[OsString],
+    config_warnings: &mut Vec<String>,
+) -> Result<Cli, clap::Error> {
+    let mut cmd = config::apply(Cli::command(), config_warnings);
+    let mut matches = cmd.clone().try_get_matches_from(command_line)?;
+
+    // the preset is only known after parsing, parse again with its defaults below the config files
+    if let Some((name, sub)) = matches.subcommand() {
+        if let Ok(Some(&preset)) = sub.try_get_one::<Preset>("preset") {
+            cmd = config::apply(
+                config::apply_preset(Cli::command(), name, preset),
+                &mut Vec::new(),
+            );
+            matches = cmd.clone().try_get_matches_from(command_line)?;
+        }
+    }
+
+    let mut cli = Cli::from_arg_matches(&matches)?;
+
+    // record the effective arguments including the config and preset defaults, without the binary path
+    if let (Some((name, sub_matches)), Some(shared)) =
+        (matches.subcommand(), cli.command.shared_args_mut())
+    {
+        let stock = Cli::command();
+        if let (Some(sub), Some(stock_sub)) =
+            (cmd.find_subcommand(name), stock.find_subcommand(name))
+        {
+            let command_line = std::iter::once(name.to_owned())
+                .chain(effective_args(sub, stock_sub, sub_matches))
+                .chain(effective_args(&cmd, &stock, &matches))
+                .collect();
+            shared.set_command_line(command_line);
+        }
+    }
+
+    Ok(cli)
 }
 
 fn main() -> ExitCode {
-    let args = Cli::parse();
-    logger::init("info,oxipng=warn");
+    let mut config_warnings = Vec::new();
+    let command_line = std::env::args_os().collect::<Vec<_>>();
+    let args = parse_cli(&command_line, &mut config_warnings).unwrap_or_else(|err| err.exit());
+
+    let color = logger::color_enabled(args.no_color);
+    match args.log_level() {
+        Some(level) => logger::init_with_level(level, color, args.annotations),
+        None => logger::init("info,oxipng=warn", color, args.annotations),
+    }
+
     info!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
-    let res = match args.command {
+    for warning in config_warnings {
+        warn!("{warning}");
+    }
+
+    image_util::set_assume_srgb(args.assume_srgb);
+
+    if args.porcelain {
+        summary::enable();
+    }
+
+    let oxipng_threads = if args.single_threaded_encode {
+        Some(1)
+    } else {
+        args.oxipng_threads.map(NonZeroUsize::get)
+    };
+
+    // every encoding thread runs oxipng on its own pool, split the threads between them
+    let threads = match oxipng_threads {
+        Some(per_image) => {
+            let total = args
+                .threads
+                .or_else(|| std::thread::available_parallelism().ok())
+                .map_or(1, NonZeroUsize::get);
+            Some((total / per_image).max(1))
+        }
+        None => args.threads.map(NonZeroUsize::get),
+    };
+
+    if let Some(threads) = threads {
+        if let Err(err) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            warn!("unable to limit thread count: {err}");
+        }
+    }
+
+    if let Some(threads) = oxipng_threads {
+        image_util::set_oxipng_threads(threads);
+    }
+
+    // with `--check` the outputs are only compared, drift fails the run
+    let res = execute(args.command).and_then(|()| check::finish());
+
+    if let Err(err) = res {
+        error!("{err}");
+
+        // failed folders, violations and differences are already part of the summary
+        if !matches!(
+            err,
+            CommandError::FoldersFailed(..)
+                | CommandError::ValidationFailed(_)
+                | CommandError::ImagesDiffer(_)
+                | CommandError::PalettesDiffer(_)
+                | CommandError::OutputsOutdated(_)
+        ) {
+            summary::record_failure(None, &err);
+        }
+
+        summary::print(err.exit_code());
+        return ExitCode::from(err.exit_code());
+    }
+
+    summary::print(0);
+    ExitCode::SUCCESS
+}
+
+fn execute(command: GenerationCommand) -> Result<(), CommandError> {
+    match command {
         GenerationCommand::Spritesheet { args } => args.execute(),
         GenerationCommand::Icon { args } => generate_mipmap_icon(&args),
         GenerationCommand::Gif { args } => generate_gif(&args),
         GenerationCommand::Optimize { args } => optimize(&args),
-    };
+        GenerationCommand::Split { args } => split(&args),
+        GenerationCommand::Preview { args } => preview(&args),
+        GenerationCommand::Validate { args } => validate(&args),
+        GenerationCommand::Verify { args } => verify(&args),
+        GenerationCommand::Stats { args } => stats(&args),
+        GenerationCommand::Compare { args } => compare(&args),
+        GenerationCommand::DiffAnim { args } => diff_anim(&args),
+        GenerationCommand::Info { args } => info(&args),
+        GenerationCommand::Rotate { args } => rotate(&args),
+        GenerationCommand::Shadow { args } => generate_shadow(&args),
+        GenerationCommand::Stitch { args } => stitch(&args),
+        GenerationCommand::Montage { args } => montage(&args),
+        GenerationCommand::Palette { args } => palette(&args),
+        GenerationCommand::Init { args } => init(&args),
+        GenerationCommand::Run { args } => run(&args),
+        GenerationCommand::Mangen { args } => mangen(&args, Cli::command()),
+    }
+}
 
-    if let Err(err) = res {
-        error!("{}", err);
-        return ExitCode::FAILURE;
+/// Run the selected jobs of the project config one after another, stops at the first failed job.
+///
+/// Global options of the jobs are ignored, the ones of the `run` invocation apply to all jobs.
+fn run(args: &RunArgs) -> Result<(), CommandError> {
+    let bin = OsString::from(env!("CARGO_PKG_NAME"));
+
+    for job in selected_jobs(args)? {
+        info!("running job {}", job.name);
+
+        let command_line = std::iter::once(bin.clone())
+            .chain(job.args.iter().map(OsString::from))
+            .collect::<Vec<_>>();
+        let cli = parse_cli(&command_line, &mut Vec::new()).map_err(|err| {
+            // only the message, without the usage clap adds for the command line
+            let msg = err.to_string();
+            let msg = msg.split("\n\n").next().unwrap_or_default();
+            let msg = msg.trim_start_matches("error: ").split_whitespace();
+            RunError::InvalidJob(job.name.clone(), msg.collect::<Vec<_>>().join(" "))
+        })?;
+
+        execute(cli.command)?;
     }
 
-    ExitCode::SUCCESS
+    Ok(())
 }
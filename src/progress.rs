@@ -0,0 +1,78 @@
+use std::{
+    io::IsTerminal as _,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// Template of the progress bars, uses indicatif placeholders instead of format args.
+#[allow(clippy::literal_string_with_formatting_args)]
+const TEMPLATE: &str = "{msg:>10} [{bar:40}] {pos}/{len} ({eta})";
+
+/// Shared draw target of all progress bars, the logger prints above it.
+pub fn multi() -> &'static MultiProgress {
+    static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI.get_or_init(MultiProgress::new)
+}
+
+/// Progress of a long operation with `total` steps.
+///
+/// Shown as a progress bar on a terminal, otherwise every 10th step is logged as `{message} N/M`.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    message: &'static str,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl Progress {
+    #[must_use]
+    pub fn new(message: &'static str, total: usize) -> Self {
+        let bar = (total > 1 && std::io::stderr().is_terminal() && log_enabled!(log::Level::Info))
+            .then(|| {
+                let bar = multi().add(ProgressBar::new(total as u64));
+                bar.set_style(
+                    ProgressStyle::with_template(TEMPLATE)
+                        .unwrap_or_else(|_| ProgressStyle::default_bar())
+                        .progress_chars("=> "),
+                );
+                bar.set_message(message);
+                bar
+            });
+
+        Self {
+            bar,
+            message,
+            done: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    pub fn inc(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+
+        match &self.bar {
+            Some(bar) => bar.inc(1),
+            None if self.total > 10 && done.is_multiple_of(10) => {
+                info!("{} {done}/{}", self.message, self.total);
+            }
+            None => {}
+        }
+    }
+
+    pub fn finish(self) {
+        match self.bar {
+            Some(bar) => {
+                bar.finish_and_clear();
+                multi().remove(&bar);
+            }
+            None if self.total > 10 && !self.total.is_multiple_of(10) => {
+                info!("{} {}/{}", self.message, self.total, self.total);
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use serde_json::{json, Value};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SUMMARY: Mutex<Summary> = Mutex::new(Summary {
+    files: Vec::new(),
+    sprites: Vec::new(),
+    failures: Vec::new(),
+});
+
+/// Everything written during a run, printed as a single JSON line with `--porcelain`.
+#[derive(Default)]
+struct Summary {
+    files: Vec<PathBuf>,
+    sprites: Vec<Value>,
+    failures: Vec<Value>,
+}
+
+/// Start collecting the run summary.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stop collecting the run summary, returns whether it was collected before.
+pub fn disable() -> bool {
+    ENABLED.swap(false, Ordering::Relaxed)
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn with_summary(f: impl FnOnce(&mut Summary)) {
+    if enabled() {
+        f(&mut SUMMARY.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+}
+
+/// Record a written output file.
+pub fn record_file(path: impl AsRef<Path>) {
+    with_summary(|summary| summary.files.push(path.as_ref().to_path_buf()));
+}
+
+/// Record a completed sprite with its final size and shift in pixels.
+pub fn record_sprite(name: &str, width: u32, height: u32, shift: (f64, f64)) {
+    with_summary(|summary| {
+        summary.sprites.push(json!({
+            "name": name,
+            "width": width,
+            "height": height,
+            "shift": [shift.0, shift.1],
+        }));
+    });
+}
+
+/// Record an error, `source` is the input that caused it if known.
+pub fn record_failure(source: Option<&Path>, error: &impl std::fmt::Display) {
+    with_summary(|summary| {
+        summary.failures.push(json!({
+            "source": source.map(|source| source.display().to_string()),
+            "error": error.to_string(),
+        }));
+    });
+}
+
+/// Print the summary to stdout, does nothing unless [`enable`] was called.
+pub fn print(exit_code: u8) {
+    if !enabled() {
+        return;
+    }
+
+    let summary = std::mem::take(&mut *SUMMARY.lock().unwrap_or_else(PoisonError::into_inner));
+
+    // optimizing may write the same file multiple times
+    let mut seen = HashSet::new();
+    let mut total_bytes = 0;
+    let files = summary
+        .files
+        .iter()
+        .filter(|path| seen.insert(*path))
+        .map(|path| {
+            let bytes = std::fs::metadata(path).map_or(0, |meta| meta.len());
+            total_bytes += bytes;
+            json!({ "path": path.display().to_string(), "bytes": bytes })
+        })
+        .collect::<Vec<_>>();
+
+    println!(
+        "{}",
+        json!({
+            "success": exit_code == 0,
+            "exit_code": exit_code,
+            "files": files,
+            "total_bytes": total_bytes,
+            "sprites": summary.sprites,
+            "failures": summary.failures,
+        })
+    );
+}